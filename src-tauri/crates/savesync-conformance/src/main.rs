@@ -0,0 +1,1027 @@
+//! Checks that a SaveSync plugin follows the plugin ABI contract
+//!
+//! Usage: savesync-conformance <plugin> [credentials]
+//!
+//! Without credentials the runner logs in by handing the `auth_url` result straight to
+//! `extract_credentials`, which only plugins without a real login (like savesync-mock-plugin)
+//! accept. Every violation found is reported, the exit code is 1 if there were any
+
+use libloading::{Library, Symbol};
+use savesync_plugin_abi::{
+    ABI_VERSION, DLLFileDetails, DLLFileDetailsV1, DLLHandle, DLLString, MIN_ABI_VERSION,
+    capabilities, error_kind, log_level, symbols,
+};
+use std::{
+    cell::Cell,
+    env,
+    ffi::{CStr, CString, c_char},
+    fmt::Display,
+    process::{self, ExitCode},
+    slice,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+const REQUIRED: [&str; 10] = [
+    "free_string",
+    "info",
+    "free_info",
+    "authenticate",
+    "auth_url",
+    "extract_credentials",
+    "upload",
+    "download",
+    "read_cloud",
+    "free_file_details",
+];
+
+const CAPABILITY_SYMBOLS: [(u64, &[&str]); 9] = [
+    (capabilities::REMOVE, &["remove"]),
+    (capabilities::ABORT, &["abort"]),
+    (
+        capabilities::STREAMING,
+        &[
+            "upload_open",
+            "upload_write",
+            "upload_finish",
+            "download_open",
+            "download_read",
+            "download_close",
+        ],
+    ),
+    (capabilities::PROGRESS, &["set_progress_callback"]),
+    (capabilities::CHANGES, &["read_changes"]),
+    (capabilities::LOGGING, &["set_log_callback"]),
+    (capabilities::SETTINGS, &["settings_schema"]),
+    (
+        capabilities::DEVICE_LOGIN,
+        &["device_login", "poll_device_login"],
+    ),
+    (capabilities::REFRESH, &["refresh", "credentials_expiry"]),
+];
+
+const TAG: &str = "savesync-conformance";
+const REDIRECT_URI: &str = "http://localhost:5000";
+const STATE: &str = "savesync-conformance-state";
+/// PKCE pair of RFC 7636's example, plugins only pass them through
+const CODE_VERIFIER: &str = "dBjjuJ44TCsCTx2XtBRZRjdE4L0Mbxq1Z-SkEkmmZZ0";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuJ-f06cIdb8M";
+const CHUNK_SIZE: usize = 4096;
+
+/// Progress reports received, and how many of them broke the contract
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+static BAD_REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Handed to the plugin as its progress callback
+unsafe extern "C" fn record_progress(
+    tag: DLLString,
+    folder_name: DLLString,
+    done: u64,
+    total: u64,
+) {
+    REPORTS.fetch_add(1, Ordering::Relaxed);
+    if tag.is_null() || folder_name.is_null() || (total != 0 && done > total) {
+        BAD_REPORTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Log records received, and how many of them broke the contract
+static LOGS: AtomicUsize = AtomicUsize::new(0);
+static BAD_LOGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Context handed to set_log_callback, which every record must carry back
+const LOG_CONTEXT: usize = 0x5a5e;
+
+/// Handed to the plugin as its log callback
+unsafe extern "C" fn record_log(
+    context: DLLHandle,
+    level: u32,
+    target: DLLString,
+    message: DLLString,
+) {
+    LOGS.fetch_add(1, Ordering::Relaxed);
+    if context as usize != LOG_CONTEXT
+        || target.is_null()
+        || message.is_null()
+        || !(log_level::ERROR..=log_level::TRACE).contains(&level)
+    {
+        BAD_LOGS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// (tag, folder_name, last_modified, data)
+type Detail = (Vec<u8>, Vec<u8>, u64, Option<Vec<u8>>);
+
+struct Runner {
+    library: Library,
+    abi_version: u32,
+    capabilities: u64,
+    violations: Cell<usize>,
+}
+
+impl Runner {
+    fn pass(&self, check: &str) {
+        println!("ok    {check}");
+    }
+
+    fn fail(&self, check: &str, message: impl Display) {
+        println!("FAIL  {check}: {message}");
+        self.violations.set(self.violations.get() + 1);
+    }
+
+    /// Passes the check if `violation` is None
+    fn expect(&self, check: &str, violation: Option<String>) {
+        match violation {
+            Some(message) => self.fail(check, message),
+            None => self.pass(check),
+        }
+    }
+
+    fn symbol<T>(&self, name: &str) -> Option<Symbol<'_, T>> {
+        unsafe { self.library.get::<T>(name.as_bytes()).ok() }
+    }
+
+    fn exports(&self, name: &str) -> bool {
+        self.symbol::<*const ()>(name).is_some()
+    }
+
+    fn supports(&self, capability: u64) -> bool {
+        self.capabilities & capability == capability
+    }
+
+    /// Copies a plugin string and hands it back through `free_string`
+    unsafe fn take_string(&self, raw: DLLString) -> Option<Vec<u8>> {
+        if raw.is_null() {
+            return None;
+        }
+
+        let bytes = unsafe { CStr::from_ptr(raw) }.to_bytes().to_vec();
+        unsafe { self.free_string(raw) };
+        Some(bytes)
+    }
+
+    unsafe fn free_string(&self, raw: DLLString) {
+        if let Some(f) = self.symbol::<symbols::FreeString>("free_string") {
+            unsafe { f(raw) }
+        }
+    }
+
+    /// Reads the kind and message of an error string, None if the call succeeded
+    unsafe fn take_error(&self, raw: DLLString) -> Option<(Option<u32>, String)> {
+        if raw.is_null() {
+            return None;
+        }
+
+        let kind = self
+            .symbol::<symbols::ErrorKind>("error_kind")
+            .map(|f| unsafe { f(raw) });
+        let message = unsafe { self.take_string(raw) }.unwrap_or_default();
+        Some((kind, String::from_utf8_lossy(&message).into_owned()))
+    }
+
+    /// Violation message if the call returned an error
+    unsafe fn succeeded(&self, call: &str, raw: DLLString) -> Option<String> {
+        unsafe { self.take_error(raw) }.map(|(_, e)| format!("{call} returned an error: {e}"))
+    }
+
+    fn check_exports(&mut self) -> bool {
+        let missing: Vec<_> = REQUIRED
+            .into_iter()
+            .filter(|name| !self.exports(name))
+            .collect();
+        let violation = (!missing.is_empty()).then(|| format!("missing {}", missing.join(", ")));
+        self.expect("exports", violation);
+
+        missing.is_empty()
+    }
+
+    fn check_abi_version(&mut self) {
+        self.abi_version = self
+            .symbol::<symbols::AbiVersion>("abi_version")
+            .map_or(1, |f| unsafe { f() });
+
+        let version = self.abi_version;
+        let violation = (!(MIN_ABI_VERSION..=ABI_VERSION).contains(&version))
+            .then(|| format!("version {version} is outside {MIN_ABI_VERSION} to {ABI_VERSION}"));
+        self.expect("abi_version", violation);
+    }
+
+    fn check_capabilities(&mut self) {
+        self.capabilities = match self.symbol::<symbols::Capabilities>("capabilities") {
+            Some(f) => unsafe { f() },
+            None => CAPABILITY_SYMBOLS
+                .iter()
+                .filter(|(_, names)| names.iter().all(|name| self.exports(name)))
+                .fold(capabilities::INLINE_DATA, |caps, (cap, _)| caps | cap),
+        };
+        if self.abi_version < 2 {
+            self.capabilities &= !capabilities::INLINE_DATA;
+        }
+
+        let missing: Vec<_> = CAPABILITY_SYMBOLS
+            .iter()
+            .filter(|(cap, _)| self.supports(*cap))
+            .flat_map(|(_, names)| names.iter().filter(|name| !self.exports(name)))
+            .copied()
+            .collect();
+        let violation = (!missing.is_empty())
+            .then(|| format!("advertised but not exported: {}", missing.join(", ")));
+        self.expect("capabilities", violation);
+    }
+
+    fn check_info(&mut self) {
+        let (Some(info), Some(free_info)) = (
+            self.symbol::<symbols::Info>("info"),
+            self.symbol::<symbols::FreeInfo>("free_info"),
+        ) else {
+            return;
+        };
+
+        // both calls happen before anything is freed, so equal pointers mean static memory
+        let (first, second) = unsafe { (info(), info()) };
+        let violation = if first.0.is_null() {
+            Some("name is null".to_owned())
+        } else if first.0 == second.0 {
+            Some("returned the same name pointer twice, strings must be allocated per call so free_string can free them".to_owned())
+        } else {
+            None
+        };
+
+        for info in [first, second] {
+            unsafe {
+                [info.0, info.1, info.2, info.3]
+                    .into_iter()
+                    .for_each(|raw| drop(self.take_string(raw)));
+                free_info(info);
+            }
+        }
+
+        self.expect("info", violation);
+    }
+
+    fn login(&mut self, credentials: Option<String>) -> Option<CString> {
+        let credentials = match credentials {
+            Some(credentials) => credentials,
+            None => {
+                let url = unsafe {
+                    let raw = self.auth_url()?;
+                    self.take_string(raw)
+                };
+                let Some(url) = url.map(|url| CString::new(url).unwrap()) else {
+                    self.fail("auth_url", "returned null");
+                    return None;
+                };
+                let violation = (self.abi_version >= 4
+                    && !url.to_string_lossy().contains(&format!("state={STATE}")))
+                .then(|| "the URL does not carry the state".to_owned());
+                self.expect("auth_url", violation);
+
+                let (raw, err) = self.extract_credentials(&url)?;
+                if let Some((_, e)) = unsafe { self.take_error(err) } {
+                    unsafe { self.free_string(raw) };
+                    self.fail(
+                        "extract_credentials",
+                        format!("{e}, pass real credentials for plugins with a login"),
+                    );
+                    return None;
+                }
+                match unsafe { self.take_string(raw) } {
+                    Some(credentials) => {
+                        self.pass("extract_credentials");
+                        String::from_utf8_lossy(&credentials).into_owned()
+                    }
+                    None => {
+                        self.fail("extract_credentials", "returned null without an error");
+                        return None;
+                    }
+                }
+            }
+        };
+
+        let mut credentials = CString::new(credentials).ok()?;
+        let (refreshed, err) = self.authenticate(&credentials)?;
+        let refreshed = unsafe { self.take_string(refreshed) };
+        if let Some((_, e)) = unsafe { self.take_error(err) } {
+            self.fail("authenticate", format!("rejected the credentials: {e}"));
+            return None;
+        }
+        if let Some(refreshed) = refreshed {
+            credentials = CString::new(refreshed).ok()?;
+        }
+        self.pass("authenticate");
+
+        let empty = CString::default();
+        let (refreshed, err) = self.authenticate(&empty)?;
+        unsafe { drop(self.take_string(refreshed)) };
+        let violation = match unsafe { self.take_error(err) } {
+            None => Some("accepted empty credentials".to_owned()),
+            Some((Some(kind), _)) if kind != error_kind::AUTH => Some(format!(
+                "rejected empty credentials with kind {kind} instead of AUTH"
+            )),
+            Some(_) => None,
+        };
+        self.expect("authenticate without credentials", violation);
+
+        Some(credentials)
+    }
+
+    /// Calls `authenticate` with the signature of the plugin's ABI version, without settings
+    fn authenticate(&self, credentials: &CStr) -> Option<(DLLString, DLLString)> {
+        let settings = CString::new("{}").unwrap();
+        unsafe {
+            Some(match self.abi_version >= 3 {
+                true => self.symbol::<symbols::Authenticate>("authenticate")?(
+                    credentials.as_ptr(),
+                    settings.as_ptr(),
+                ),
+                false => {
+                    self.symbol::<symbols::AuthenticateV2>("authenticate")?(credentials.as_ptr())
+                }
+            })
+        }
+    }
+
+    /// Calls `auth_url` with the signature of the plugin's ABI version
+    fn auth_url(&self) -> Option<DLLString> {
+        let redirect_uri = CString::new(REDIRECT_URI).unwrap();
+        let (state, challenge) = (
+            CString::new(STATE).unwrap(),
+            CString::new(CODE_CHALLENGE).unwrap(),
+        );
+        unsafe {
+            Some(match self.abi_version >= 4 {
+                true => self.symbol::<symbols::AuthUrl>("auth_url")?(
+                    redirect_uri.as_ptr(),
+                    state.as_ptr(),
+                    challenge.as_ptr(),
+                ),
+                false => self.symbol::<symbols::AuthUrlV3>("auth_url")?(redirect_uri.as_ptr()),
+            })
+        }
+    }
+
+    /// Calls `extract_credentials` with the signature of the plugin's ABI version
+    fn extract_credentials(&self, url: &CStr) -> Option<(DLLString, DLLString)> {
+        let verifier = CString::new(CODE_VERIFIER).unwrap();
+        unsafe {
+            Some(match self.abi_version >= 4 {
+                true => self.symbol::<symbols::ExtractCredentials>("extract_credentials")?(
+                    url.as_ptr(),
+                    verifier.as_ptr(),
+                ),
+                false => self.symbol::<symbols::ExtractCredentialsV3>("extract_credentials")?(
+                    url.as_ptr(),
+                ),
+            })
+        }
+    }
+
+    /// The login needs someone to enter the code, so only its start and one poll are checked
+    fn check_device_login(&mut self) {
+        let (Some(device_login), Some(poll)) = (
+            self.symbol::<symbols::DeviceLogin>("device_login"),
+            self.symbol::<symbols::PollDeviceLogin>("poll_device_login"),
+        ) else {
+            return;
+        };
+        if !self.supports(capabilities::DEVICE_LOGIN) {
+            return;
+        }
+
+        let ((device_code, user_code, verification_uri, _, expires_in), err) =
+            unsafe { device_login() };
+        let strings =
+            unsafe { [device_code, user_code, verification_uri].map(|raw| self.take_string(raw)) };
+        if let Some(violation) = unsafe { self.succeeded("device_login", err) } {
+            self.fail("device_login", violation);
+            return;
+        }
+        let [Some(device_code), Some(_), Some(_)] = strings else {
+            self.fail("device_login", "returned a null code or verification URI");
+            return;
+        };
+        let violation = (expires_in == 0).then(|| "the login expires immediately".to_owned());
+        self.expect("device_login", violation);
+
+        let device_code = CString::new(device_code).unwrap();
+        let (credentials, err) = unsafe { poll(device_code.as_ptr()) };
+        unsafe { drop(self.take_string(credentials)) };
+        let violation = match unsafe { self.take_error(err) } {
+            Some((Some(error_kind::RATE_LIMITED), _)) | None => None,
+            Some((_, e)) => Some(format!("failed before the user could log in: {e}")),
+        };
+        self.expect("poll_device_login", violation);
+    }
+
+    /// The schema has to be a JSON object describing the settings object
+    fn check_settings(&mut self) {
+        let Some(settings_schema) = self
+            .symbol::<symbols::SettingsSchema>("settings_schema")
+            .filter(|_| self.supports(capabilities::SETTINGS))
+        else {
+            return;
+        };
+
+        let violation = match unsafe { self.take_string(settings_schema()) } {
+            None => Some("returned null".to_owned()),
+            Some(schema) => match serde_json::from_slice::<serde_json::Value>(&schema) {
+                Err(e) => Some(format!("returned invalid JSON: {e}")),
+                Ok(schema) if schema["type"] != "object" => {
+                    Some("schema does not describe an object".to_owned())
+                }
+                Ok(_) => None,
+            },
+        };
+        self.expect("settings_schema", violation);
+    }
+
+    fn upload(
+        &self,
+        credentials: &CString,
+        folder_name: &CString,
+        date: u64,
+        data: &[u8],
+    ) -> Option<String> {
+        let tag = CString::new(TAG).unwrap();
+        unsafe {
+            let raw = self.symbol::<symbols::Upload>("upload")?(
+                credentials.as_ptr(),
+                tag.as_ptr(),
+                folder_name.as_ptr(),
+                date,
+                data.as_ptr() as DLLString,
+                data.len() as u64,
+            );
+            self.succeeded("upload", raw)
+        }
+    }
+
+    /// Returns the downloaded data, or the violation found
+    fn download(
+        &self,
+        credentials: &CString,
+        folder_name: &CString,
+    ) -> Result<Vec<u8>, (Option<u32>, String)> {
+        let tag = CString::new(TAG).unwrap();
+        let download = self
+            .symbol::<symbols::Download>("download")
+            .ok_or((None, "download is not exported".to_owned()))?;
+
+        unsafe {
+            let (raw, count, err) =
+                download(credentials.as_ptr(), tag.as_ptr(), folder_name.as_ptr());
+            if let Some(e) = self.take_error(err) {
+                self.free_string(raw);
+                return Err(e);
+            }
+            if raw.is_null() {
+                return Err((None, format!("returned null data with a count of {count}")));
+            }
+
+            let data = slice::from_raw_parts(raw as *const u8, count as usize).to_vec();
+            self.free_string(raw);
+            Ok(data)
+        }
+    }
+
+    /// Lists the cloud in the layout of the plugin's ABI version
+    fn read_cloud(&self, credentials: &CString) -> Option<Vec<Detail>> {
+        unsafe {
+            if self.abi_version >= 2 {
+                self.read_cloud_as::<DLLFileDetails>(credentials, |d| parse_v2(d))
+            } else {
+                self.read_cloud_as::<DLLFileDetailsV1>(credentials, |d| (d.0, d.1, d.2, None))
+            }
+        }
+    }
+
+    unsafe fn read_cloud_as<D>(
+        &self,
+        credentials: &CString,
+        parse: impl Fn(&D) -> (DLLString, DLLString, u64, Option<Vec<u8>>),
+    ) -> Option<Vec<Detail>> {
+        let read_cloud = self.symbol::<symbols::ReadCloud<D>>("read_cloud")?;
+
+        unsafe {
+            let (ptr, count, err) = read_cloud(credentials.as_ptr());
+            if let Some((_, e)) = self.take_error(err) {
+                self.fail("read_cloud", format!("returned an error: {e}"));
+                return None;
+            }
+            self.take_details("read_cloud", ptr, count, parse)
+        }
+    }
+
+    /// Lists the changes since `cursor`, with the cursor to continue from
+    fn read_changes(
+        &self,
+        credentials: &CString,
+        cursor: &CString,
+    ) -> Option<(Vec<Detail>, CString)> {
+        let read_changes = self.symbol::<symbols::ReadChanges>("read_changes")?;
+
+        unsafe {
+            let (ptr, count, next, err) = read_changes(credentials.as_ptr(), cursor.as_ptr());
+            if let Some((_, e)) = self.take_error(err) {
+                self.fail("read_changes", format!("returned an error: {e}"));
+                return None;
+            }
+            let details = self.take_details("read_changes", ptr, count, |d| parse_v2(d))?;
+            match self.take_string(next) {
+                Some(next) => Some((details, CString::new(next).unwrap())),
+                None => {
+                    self.fail("read_changes", "returned no cursor");
+                    None
+                }
+            }
+        }
+    }
+
+    /// Copies the details and hands them back through `free_file_details`
+    unsafe fn take_details<D>(
+        &self,
+        check: &str,
+        ptr: *const D,
+        count: u64,
+        parse: impl Fn(&D) -> (DLLString, DLLString, u64, Option<Vec<u8>>),
+    ) -> Option<Vec<Detail>> {
+        let free_file_details = self.symbol::<symbols::FreeFileDetails<D>>("free_file_details")?;
+        if ptr.is_null() && count > 0 {
+            self.fail(
+                check,
+                format!("returned null details with a count of {count}"),
+            );
+            return None;
+        }
+
+        unsafe {
+            let mut details = Vec::new();
+            for i in 0..count as usize {
+                let (tag, folder_name, date, data) = parse(&*ptr.add(i));
+                match (self.take_string(tag), self.take_string(folder_name)) {
+                    (Some(tag), Some(folder_name)) => details.push((tag, folder_name, date, data)),
+                    _ => {
+                        self.fail(check, format!("detail {i} of {count} has a null tag or folder name, is count correct?"));
+                    }
+                }
+            }
+            free_file_details(count, ptr);
+
+            Some(details)
+        }
+    }
+
+    fn check_round_trip(&mut self, credentials: &CString) {
+        let folder_name = CString::new(format!("roundtrip-{}.zip", process::id())).unwrap();
+        let date = now();
+        // NUL bytes catch plugins that treat archives as C strings
+        let data: Vec<u8> = (0..=255u8).cycle().take(70_000).collect();
+
+        let violation = self.upload(credentials, &folder_name, date, &data);
+        let uploaded = violation.is_none();
+        self.expect("upload", violation);
+        if !uploaded {
+            return;
+        }
+
+        if let Some(details) = self.read_cloud(credentials) {
+            let matching: Vec<_> = details
+                .iter()
+                .filter(|(tag, name, _, _)| tag == TAG.as_bytes() && name == folder_name.as_bytes())
+                .collect();
+            let violation = match matching.as_slice() {
+                [] => Some("uploaded file is not listed".to_owned()),
+                [(_, _, listed, _)] if *listed != date => Some(format!(
+                    "lists a last modified time of {listed} instead of {date}"
+                )),
+                [(_, _, _, Some(inline))] if *inline != data => {
+                    Some("attached data differs from the uploaded data".to_owned())
+                }
+                [_] => None,
+                _ => Some(format!("uploaded file is listed {} times", matching.len())),
+            };
+            self.expect("read_cloud", violation);
+        }
+
+        let violation = match self.download(credentials, &folder_name) {
+            Ok(downloaded) if downloaded == data => None,
+            Ok(downloaded) => Some(format!(
+                "returned {} bytes that differ from the {} uploaded",
+                downloaded.len(),
+                data.len()
+            )),
+            Err((_, e)) => Some(e),
+        };
+        self.expect("download", violation);
+
+        let missing = CString::new(format!("missing-{}.zip", process::id())).unwrap();
+        let violation = match self.download(credentials, &missing) {
+            Ok(_) => Some("returned data for a file that does not exist".to_owned()),
+            Err((Some(kind), _)) if kind != error_kind::NOT_FOUND => {
+                Some(format!("reported kind {kind} instead of NOT_FOUND"))
+            }
+            Err(_) => None,
+        };
+        self.expect("download missing file", violation);
+
+        self.check_remove(credentials, &folder_name);
+    }
+
+    /// Fresh credentials cannot have expired, refreshing them has to give working ones back
+    /// and rejected credentials must not refresh
+    fn check_refresh(&mut self, credentials: &CString) {
+        let (Some(refresh), Some(credentials_expiry)) = (
+            self.symbol::<symbols::Refresh>("refresh"),
+            self.symbol::<symbols::CredentialsExpiry>("credentials_expiry"),
+        ) else {
+            return;
+        };
+        if !self.supports(capabilities::REFRESH) {
+            return;
+        }
+
+        let expiry = unsafe { credentials_expiry(credentials.as_ptr()) };
+        let violation = (expiry != 0 && expiry <= now())
+            .then(|| format!("credentials of the login expired already, at {expiry}"));
+        self.expect("credentials_expiry", violation);
+
+        let (refreshed, err) = unsafe { refresh(credentials.as_ptr()) };
+        let refreshed = unsafe { self.take_string(refreshed) };
+        if let Some(violation) = unsafe { self.succeeded("refresh", err) } {
+            self.fail("refresh", violation);
+            return;
+        }
+        let Some(refreshed) = refreshed else {
+            self.fail("refresh", "returned null credentials");
+            return;
+        };
+        let refreshed = CString::new(refreshed).unwrap();
+        if self.read_cloud(&refreshed).is_some() {
+            self.pass("refresh");
+        }
+
+        let (rejected, err) = unsafe { refresh(CString::default().as_ptr()) };
+        unsafe { drop(self.take_string(rejected)) };
+        let violation = match unsafe { self.take_error(err) } {
+            Some((Some(error_kind::AUTH), _)) => None,
+            Some((kind, e)) => Some(format!("failed with kind {kind:?} instead of AUTH: {e}")),
+            None => Some("accepted empty credentials".to_owned()),
+        };
+        self.expect("refresh empty credentials", violation);
+    }
+
+    /// Uploads and removes a file, the change feed has to list both
+    fn check_changes(&mut self, credentials: &CString) {
+        if !self.supports(capabilities::CHANGES) {
+            return;
+        }
+        let Some((_, cursor)) = self.read_changes(credentials, &CString::default()) else {
+            return;
+        };
+
+        let folder_name = CString::new(format!("changed-{}.zip", process::id())).unwrap();
+        let date = now();
+        // a failing upload is already reported by the round trip
+        if self
+            .upload(credentials, &folder_name, date, b"changed")
+            .is_some()
+        {
+            return;
+        }
+        let Some((changes, cursor)) = self.read_changes(credentials, &cursor) else {
+            return;
+        };
+        let violation = match listed_date(&changes, &folder_name) {
+            Some(listed) if listed == date => None,
+            Some(listed) => Some(format!(
+                "lists a last modified time of {listed} instead of {date}"
+            )),
+            None => Some("uploaded file is not listed as changed".to_owned()),
+        };
+        self.expect("read_changes after upload", violation);
+
+        if !self.supports(capabilities::REMOVE) {
+            return;
+        }
+        self.check_remove(credentials, &folder_name);
+        if let Some((changes, _)) = self.read_changes(credentials, &cursor) {
+            let violation = match listed_date(&changes, &folder_name) {
+                Some(0) => None,
+                Some(listed) => Some(format!(
+                    "lists a last modified time of {listed} instead of 0 for a removed file"
+                )),
+                None => Some("removed file is not listed as changed".to_owned()),
+            };
+            self.expect("read_changes after remove", violation);
+        }
+    }
+
+    fn check_streaming(&mut self, credentials: &CString) {
+        if !self.supports(capabilities::STREAMING) {
+            return;
+        }
+
+        let folder_name = CString::new(format!("streamed-{}.zip", process::id())).unwrap();
+        let discarded = CString::new(format!("discarded-{}.zip", process::id())).unwrap();
+        let data: Vec<u8> = (0..=255u8).rev().cycle().take(3 * CHUNK_SIZE + 7).collect();
+
+        let violation = self
+            .stream_upload(credentials, &folder_name, &data, true)
+            .or_else(|| self.stream_upload(credentials, &discarded, &data, false));
+        let uploaded = violation.is_none();
+        self.expect("streamed upload", violation);
+        if !uploaded {
+            return;
+        }
+
+        let violation = match self.stream_download(credentials, &folder_name) {
+            Ok(downloaded) if downloaded == data => None,
+            Ok(downloaded) => Some(format!(
+                "returned {} bytes that differ from the {} uploaded",
+                downloaded.len(),
+                data.len()
+            )),
+            Err(e) => Some(e),
+        };
+        self.expect("streamed download", violation);
+
+        if let Some(details) = self.read_cloud(credentials) {
+            let violation = details
+                .iter()
+                .any(|(tag, name, _, _)| tag == TAG.as_bytes() && name == discarded.as_bytes())
+                .then(|| "upload finished without commit is listed".to_owned());
+            self.expect("discarded upload", violation);
+        }
+
+        self.check_remove(credentials, &folder_name);
+    }
+
+    fn stream_upload(
+        &self,
+        credentials: &CString,
+        folder_name: &CString,
+        data: &[u8],
+        commit: bool,
+    ) -> Option<String> {
+        let tag = CString::new(TAG).unwrap();
+        let open = self.symbol::<symbols::UploadOpen>("upload_open")?;
+        let write = self.symbol::<symbols::UploadWrite>("upload_write")?;
+        let finish = self.symbol::<symbols::UploadFinish>("upload_finish")?;
+
+        unsafe {
+            let (handle, err) = open(
+                credentials.as_ptr(),
+                tag.as_ptr(),
+                folder_name.as_ptr(),
+                now(),
+                data.len() as u64,
+            );
+            if let Some(violation) = self.succeeded("upload_open", err) {
+                return Some(violation);
+            }
+
+            let mut violation = None;
+            for chunk in data.chunks(CHUNK_SIZE) {
+                let err = write(handle, chunk.as_ptr() as DLLString, chunk.len() as u64);
+                violation = self.succeeded("upload_write", err);
+                if violation.is_some() {
+                    break;
+                }
+            }
+
+            let commit = commit && violation.is_none();
+            violation.or_else(|| self.succeeded("upload_finish", finish(handle, commit)))
+        }
+    }
+
+    fn stream_download(
+        &self,
+        credentials: &CString,
+        folder_name: &CString,
+    ) -> Result<Vec<u8>, String> {
+        let tag = CString::new(TAG).unwrap();
+        let missing = || "streaming functions are not exported".to_owned();
+        let open = self
+            .symbol::<symbols::DownloadOpen>("download_open")
+            .ok_or_else(missing)?;
+        let read = self
+            .symbol::<symbols::DownloadRead>("download_read")
+            .ok_or_else(missing)?;
+        let close = self
+            .symbol::<symbols::DownloadClose>("download_close")
+            .ok_or_else(missing)?;
+
+        unsafe {
+            let (handle, err) = open(credentials.as_ptr(), tag.as_ptr(), folder_name.as_ptr());
+            if let Some(violation) = self.succeeded("download_open", err) {
+                return Err(violation);
+            }
+
+            let mut data = Vec::new();
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+            let result = loop {
+                let (count, err) = read(
+                    handle,
+                    chunk.as_mut_ptr() as *mut c_char,
+                    chunk.len() as u64,
+                );
+                if let Some(violation) = self.succeeded("download_read", err) {
+                    break Err(violation);
+                }
+                if count > chunk.len() as u64 {
+                    break Err(format!(
+                        "download_read reported {count} bytes for a {CHUNK_SIZE} byte buffer"
+                    ));
+                }
+                if count == 0 {
+                    break Ok(data);
+                }
+                data.extend_from_slice(&chunk[..count as usize]);
+            };
+            close(handle);
+
+            result
+        }
+    }
+
+    fn register_progress(&mut self) {
+        if let Some(f) = self
+            .symbol::<symbols::SetProgressCallback>("set_progress_callback")
+            .filter(|_| self.supports(capabilities::PROGRESS))
+        {
+            unsafe { f(record_progress) }
+        }
+    }
+
+    fn register_log(&mut self) {
+        if let Some(f) = self
+            .symbol::<symbols::SetLogCallback>("set_log_callback")
+            .filter(|_| self.supports(capabilities::LOGGING))
+        {
+            unsafe { f(record_log, LOG_CONTEXT as DLLHandle) }
+        }
+    }
+
+    /// Records need a target, a message and one of the log_level levels
+    fn check_log(&mut self) {
+        if !self.supports(capabilities::LOGGING) {
+            return;
+        }
+
+        let bad = BAD_LOGS.load(Ordering::Relaxed);
+        let violation = (bad > 0).then(|| {
+            format!(
+                "{bad} of {} log records had a null string, an unknown level or another context",
+                LOGS.load(Ordering::Relaxed)
+            )
+        });
+        self.expect("logging", violation);
+    }
+
+    /// Reports need a tag and folder name, and cannot exceed their total
+    fn check_progress(&mut self) {
+        if !self.supports(capabilities::PROGRESS) {
+            return;
+        }
+
+        let bad = BAD_REPORTS.load(Ordering::Relaxed);
+        let violation = (bad > 0).then(|| {
+            format!(
+                "{bad} of {} reports had a null name or more bytes done than total",
+                REPORTS.load(Ordering::Relaxed)
+            )
+        });
+        self.expect("progress", violation);
+    }
+
+    /// The host may abort just after the call on the file finished, that must not fail
+    fn check_abort(&mut self) {
+        let Some(abort) = self
+            .symbol::<symbols::Abort>("abort")
+            .filter(|_| self.supports(capabilities::ABORT))
+        else {
+            return;
+        };
+        let tag = CString::new(TAG).unwrap();
+        let folder_name = CString::new(format!("idle-{}.zip", process::id())).unwrap();
+
+        let err = unsafe { abort(tag.as_ptr(), folder_name.as_ptr()) };
+        let violation = unsafe { self.succeeded("abort of an idle file", err) };
+        self.expect("abort", violation);
+    }
+
+    fn check_remove(&mut self, credentials: &CString, folder_name: &CString) {
+        let Some(remove) = self
+            .symbol::<symbols::Remove>("remove")
+            .filter(|_| self.supports(capabilities::REMOVE))
+        else {
+            println!(
+                "note  {} is left in the cloud, the plugin does not support remove",
+                folder_name.to_string_lossy()
+            );
+            return;
+        };
+        let tag = CString::new(TAG).unwrap();
+
+        let err = unsafe { remove(credentials.as_ptr(), tag.as_ptr(), folder_name.as_ptr()) };
+        let violation = unsafe { self.succeeded("remove", err) };
+        let removed = violation.is_none();
+        self.expect("remove", violation);
+        if !removed {
+            return;
+        }
+
+        if let Some(details) = self.read_cloud(credentials) {
+            let violation = details
+                .iter()
+                .any(|(tag, name, _, _)| tag == TAG.as_bytes() && name == folder_name.as_bytes())
+                .then(|| "removed file is still listed".to_owned());
+            self.expect("read_cloud after remove", violation);
+        }
+
+        let err = unsafe { remove(credentials.as_ptr(), tag.as_ptr(), folder_name.as_ptr()) };
+        let violation = match unsafe { self.take_error(err) } {
+            Some((Some(kind), e)) if kind != error_kind::NOT_FOUND => {
+                Some(format!("failed with kind {kind} instead of NOT_FOUND: {e}"))
+            }
+            _ => None,
+        };
+        self.expect("remove missing file", violation);
+    }
+}
+
+unsafe fn parse_v2(d: &DLLFileDetails) -> (DLLString, DLLString, u64, Option<Vec<u8>>) {
+    let data = (!d.3.is_null())
+        .then(|| unsafe { slice::from_raw_parts(d.3 as *const u8, d.4 as usize) }.to_vec());
+    (d.0, d.1, d.2, data)
+}
+
+/// Last modified time the details list for a file of the runner's tag
+fn listed_date(details: &[Detail], folder_name: &CString) -> Option<u64> {
+    details
+        .iter()
+        .find(|(tag, name, _, _)| tag == TAG.as_bytes() && name == folder_name.as_bytes())
+        .map(|(_, _, date, _)| *date)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args_os().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("Usage: savesync-conformance <plugin> [credentials]");
+        return ExitCode::FAILURE;
+    };
+    let credentials = args.next().map(|c| c.to_string_lossy().into_owned());
+
+    let library = match unsafe { Library::new(&path) } {
+        Ok(library) => library,
+        Err(e) => {
+            eprintln!("Unable to load {}: {e}", path.to_string_lossy());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut runner = Runner {
+        library,
+        abi_version: MIN_ABI_VERSION,
+        capabilities: 0,
+        violations: Cell::new(0),
+    };
+
+    // everything after this hands strings back through free_string
+    if runner.check_exports() {
+        runner.check_abi_version();
+        runner.check_capabilities();
+        runner.check_info();
+        runner.check_settings();
+        runner.check_device_login();
+        runner.register_progress();
+        runner.register_log();
+        if let Some(credentials) = runner.login(credentials) {
+            runner.check_round_trip(&credentials);
+            runner.check_streaming(&credentials);
+            runner.check_changes(&credentials);
+            runner.check_refresh(&credentials);
+        }
+        runner.check_abort();
+        runner.check_progress();
+        runner.check_log();
+    }
+
+    match runner.violations.get() {
+        0 => {
+            println!("\nNo contract violations found");
+            ExitCode::SUCCESS
+        }
+        n => {
+            println!("\n{n} contract violation(s) found");
+            ExitCode::FAILURE
+        }
+    }
+}
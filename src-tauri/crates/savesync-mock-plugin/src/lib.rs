@@ -0,0 +1,311 @@
+//! Reference SaveSync plugin keeping its files in memory
+//! Implements every optional part of the ABI, so the conformance runner can check the host
+//! side of the contract against it and plugin authors can use it as a starting point
+
+use savesync_plugin_sdk::{
+    Changes, CloudPlugin, DeviceLogin, Error, ErrorKind, FileDetails, Info, Result, Settings,
+    UploadStream, log, plugin, report_progress,
+    serde_json::{Value, json},
+};
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Read, Write},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Only credentials the mock accepts, handed out by `extract_credentials`
+const TOKEN: &str = "mock-token";
+const DEVICE_CODE: &str = "mock-device-code";
+/// How long the token lasts, counted from every call that asks
+const TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+type Key = (String, String);
+
+/// Files, and the log of changed keys its change feed is read from
+#[derive(Default)]
+struct Store {
+    files: HashMap<Key, (SystemTime, Vec<u8>)>,
+    log: Vec<Key>,
+}
+
+impl Store {
+    fn insert(&mut self, key: Key, file: (SystemTime, Vec<u8>)) {
+        self.log.push(key.clone());
+        self.files.insert(key, file);
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<(SystemTime, Vec<u8>)> {
+        self.log.push(key.clone());
+        self.files.remove(key)
+    }
+
+    fn detail((tag, folder_name): &Key, last_modified: SystemTime) -> FileDetails {
+        FileDetails {
+            tag: tag.clone(),
+            folder_name: folder_name.clone(),
+            last_modified,
+            data: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockCloud {
+    store: Arc<Mutex<Store>>,
+    /// The device login is approved on the second poll, so hosts see it pending once
+    device_polls: AtomicU32,
+}
+
+impl MockCloud {
+    fn check(credentials: &str) -> Result<()> {
+        match credentials == TOKEN {
+            true => Ok(()),
+            false => Err(Error::new(ErrorKind::Auth, "Invalid credentials")),
+        }
+    }
+
+    fn not_found(tag: &str, folder_name: &str) -> Error {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("{tag}/{folder_name} does not exist"),
+        )
+    }
+}
+
+/// Buffers the chunks and stores them once committed
+struct MockUpload {
+    store: Arc<Mutex<Store>>,
+    key: Key,
+    last_modified: SystemTime,
+    len: u64,
+    buffer: Vec<u8>,
+}
+
+impl Write for MockUpload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.buffer.write(buf)?;
+        let (tag, folder_name) = &self.key;
+        report_progress(tag, folder_name, self.buffer.len() as u64, self.len);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl UploadStream for MockUpload {
+    fn finish(self: Box<Self>, commit: bool) -> Result<()> {
+        let (tag, folder_name) = &self.key;
+        log::debug!(
+            "Upload of {tag}/{folder_name} {}",
+            if commit { "committed" } else { "discarded" }
+        );
+        if commit {
+            self.store
+                .lock()
+                .unwrap()
+                .insert(self.key, (self.last_modified, self.buffer));
+        }
+        Ok(())
+    }
+}
+
+#[plugin]
+impl CloudPlugin for MockCloud {
+    fn info(&self) -> Info {
+        Info {
+            name: "Mock cloud".into(),
+            description: "Keeps saves in memory until SaveSync closes".into(),
+            author: "SaveSync".into(),
+            icon_url: String::new(),
+        }
+    }
+
+    /// Only checked, as there is nothing for the mock to configure
+    fn authenticate(&self, credentials: &str, settings: &Settings) -> Result<Option<String>> {
+        if settings
+            .get("label")
+            .is_some_and(|label| !label.is_string())
+        {
+            return Err(Error::other("label must be a string"));
+        }
+        MockCloud::check(credentials).map(|_| None)
+    }
+
+    fn settings_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "label": {
+                    "type": "string",
+                    "title": "Label",
+                    "description": "Name to tell this mock cloud apart by",
+                    "default": "Mock cloud",
+                },
+            },
+        })
+    }
+
+    /// Logs in at once, redirecting with the code and the state like a real authorization server
+    fn auth_url(&self, redirect_uri: &str, state: &str, _code_challenge: &str) -> Option<String> {
+        Some(format!("{redirect_uri}/?code={TOKEN}&state={state}"))
+    }
+
+    fn extract_credentials(&self, url: &str, code_verifier: &str) -> Result<String> {
+        if code_verifier.is_empty() {
+            return Err(Error::new(ErrorKind::Auth, "No PKCE code verifier"));
+        }
+        url.split_once("code=")
+            .and_then(|(_, query)| query.split('&').next())
+            .map(str::to_owned)
+            .ok_or_else(|| Error::new(ErrorKind::Auth, "Redirect has no code"))
+    }
+
+    fn device_login(&self) -> Result<DeviceLogin> {
+        self.device_polls.store(0, Ordering::Relaxed);
+        Ok(DeviceLogin {
+            device_code: DEVICE_CODE.into(),
+            user_code: "MOCK-1234".into(),
+            verification_uri: "https://example.com/device".into(),
+            interval: Duration::ZERO,
+            expires_in: Duration::from_secs(60),
+        })
+    }
+
+    fn poll_device_login(&self, device_code: &str) -> Result<Option<String>> {
+        if device_code != DEVICE_CODE {
+            return Err(Error::new(ErrorKind::Auth, "Unknown device code"));
+        }
+        match self.device_polls.fetch_add(1, Ordering::Relaxed) {
+            0 => Ok(None),
+            _ => Ok(Some(TOKEN.into())),
+        }
+    }
+
+    /// Hands the same token back, there is no server to issue a new one
+    fn refresh(&self, credentials: &str) -> Result<String> {
+        MockCloud::check(credentials).map(|_| TOKEN.into())
+    }
+
+    fn credentials_expiry(&self, _credentials: &str) -> Option<SystemTime> {
+        Some(SystemTime::now() + TOKEN_LIFETIME)
+    }
+
+    fn upload(
+        &self,
+        credentials: &str,
+        tag: &str,
+        folder_name: &str,
+        last_modified: SystemTime,
+        data: &[u8],
+    ) -> Result<()> {
+        MockCloud::check(credentials)?;
+        log::debug!("Storing {tag}/{folder_name}, {} bytes", data.len());
+        self.store.lock().unwrap().insert(
+            (tag.to_owned(), folder_name.to_owned()),
+            (last_modified, data.to_vec()),
+        );
+        Ok(())
+    }
+
+    fn download(&self, credentials: &str, tag: &str, folder_name: &str) -> Result<Vec<u8>> {
+        MockCloud::check(credentials)?;
+        self.store
+            .lock()
+            .unwrap()
+            .files
+            .get(&(tag.to_owned(), folder_name.to_owned()))
+            .map(|(_, data)| data.clone())
+            .ok_or_else(|| MockCloud::not_found(tag, folder_name))
+    }
+
+    fn read_cloud(&self, credentials: &str) -> Result<Vec<FileDetails>> {
+        MockCloud::check(credentials)?;
+        Ok(self
+            .store
+            .lock()
+            .unwrap()
+            .files
+            .iter()
+            .map(|(key, (last_modified, _))| Store::detail(key, *last_modified))
+            .collect())
+    }
+
+    /// Cursors are positions in the log of changed keys
+    fn read_changes(&self, credentials: &str, cursor: &str) -> Result<Changes> {
+        MockCloud::check(credentials)?;
+        let store = self.store.lock().unwrap();
+        let next = store.log.len().to_string();
+        let mut keys = match cursor {
+            "" => store.files.keys().cloned().collect(),
+            cursor => cursor
+                .parse::<usize>()
+                .ok()
+                .and_then(|since| store.log.get(since..))
+                .ok_or_else(|| Error::other(format!("Unknown cursor {cursor}")))?
+                .to_vec(),
+        };
+        keys.sort();
+        keys.dedup();
+
+        let (changed, removed) = keys
+            .into_iter()
+            .partition::<Vec<_>, _>(|key| store.files.contains_key(key));
+        Ok(Changes {
+            changed: changed
+                .iter()
+                .map(|key| Store::detail(key, store.files[key].0))
+                .collect(),
+            removed,
+            cursor: next,
+        })
+    }
+
+    fn remove(&self, credentials: &str, tag: &str, folder_name: &str) -> Result<()> {
+        MockCloud::check(credentials)?;
+        self.store
+            .lock()
+            .unwrap()
+            .remove(&(tag.to_owned(), folder_name.to_owned()))
+            .map(|_| ())
+            .ok_or_else(|| MockCloud::not_found(tag, folder_name))
+    }
+
+    /// Every call finishes without waiting, so there is never one to stop
+    fn abort(&self, _tag: &str, _folder_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn upload_open(
+        &self,
+        credentials: &str,
+        tag: &str,
+        folder_name: &str,
+        last_modified: SystemTime,
+        len: u64,
+    ) -> Result<Box<dyn UploadStream>> {
+        MockCloud::check(credentials)?;
+        Ok(Box::new(MockUpload {
+            store: self.store.clone(),
+            key: (tag.to_owned(), folder_name.to_owned()),
+            last_modified,
+            len,
+            buffer: Vec::with_capacity(len as usize),
+        }))
+    }
+
+    fn download_open(
+        &self,
+        credentials: &str,
+        tag: &str,
+        folder_name: &str,
+    ) -> Result<Box<dyn Read + Send>> {
+        self.download(credentials, tag, folder_name)
+            .map(|data| Box::new(Cursor::new(data)) as Box<dyn Read + Send>)
+    }
+}
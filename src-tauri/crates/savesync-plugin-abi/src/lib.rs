@@ -0,0 +1,173 @@
+//! Types and symbol signatures of the SaveSync plugin ABI
+//! Both the host and savesync-plugin-sdk build against these, so the two cannot drift
+
+use std::ffi::{c_char, c_void};
+
+pub type DLLString = *const c_char;
+pub type DLLHandle = *mut c_void;
+/// (name, description, author, icon_url)
+pub type DLLInfo = (DLLString, DLLString, DLLString, DLLString);
+/// (tag, folder_name, last_modified, data, data length)
+pub type DLLFileDetails = (DLLString, DLLString, u64, DLLString, u64);
+/// (tag, folder_name, last_modified, NUL-terminated data)
+pub type DLLFileDetailsV1 = (DLLString, DLLString, u64, DLLString);
+/// (device_code, user_code, verification_uri, poll interval in seconds, expires in seconds)
+pub type DLLDeviceLogin = (DLLString, DLLString, DLLString, u64, u64);
+
+/// Version of the plugin ABI described here
+/// Version 2 added an explicit length to the data returned by `read_cloud`,
+/// version 3 passes the plugin's settings to `authenticate`,
+/// version 4 passes an OAuth state and PKCE pair to `auth_url` and `extract_credentials`
+pub const ABI_VERSION: u32 = 4;
+
+/// Oldest plugin ABI the host still loads
+/// Plugins that do not export `abi_version` are treated as version 1
+pub const MIN_ABI_VERSION: u32 = 1;
+
+/// Bits returned by the `capabilities` export
+pub mod capabilities {
+    /// Plugin exports `remove`
+    pub const REMOVE: u64 = 1 << 0;
+    /// `read_cloud` may attach file data to its details
+    pub const INLINE_DATA: u64 = 1 << 1;
+    /// Plugin exports `abort`
+    pub const ABORT: u64 = 1 << 2;
+    /// Plugin exports the `upload_*` and `download_*` streaming functions
+    pub const STREAMING: u64 = 1 << 3;
+    /// Plugin exports `set_progress_callback`
+    pub const PROGRESS: u64 = 1 << 4;
+    /// Plugin exports `read_changes`
+    pub const CHANGES: u64 = 1 << 5;
+    /// Plugin exports `set_log_callback`
+    pub const LOGGING: u64 = 1 << 6;
+    /// Plugin exports `settings_schema`
+    pub const SETTINGS: u64 = 1 << 7;
+    /// Plugin exports `device_login` and `poll_device_login`
+    pub const DEVICE_LOGIN: u64 = 1 << 8;
+    /// Plugin exports `refresh` and `credentials_expiry`
+    pub const REFRESH: u64 = 1 << 9;
+}
+
+/// Codes returned by the `error_kind` export
+pub mod error_kind {
+    pub const OTHER: u32 = 0;
+    /// Credentials are missing, expired or revoked
+    pub const AUTH: u32 = 1;
+    /// Service asked to slow down
+    pub const RATE_LIMITED: u32 = 2;
+    /// File does not exist in the cloud
+    pub const NOT_FOUND: u32 = 3;
+    /// Service could not be reached
+    pub const NETWORK: u32 = 4;
+}
+
+/// Levels passed to the log callback, in the order of the `log` crate's
+pub mod log_level {
+    pub const ERROR: u32 = 1;
+    pub const WARN: u32 = 2;
+    pub const INFO: u32 = 3;
+    pub const DEBUG: u32 = 4;
+    pub const TRACE: u32 = 5;
+}
+
+/// Signatures of the exported symbols, named after the symbol
+/// Every returned DLLString is owned by the plugin and handed back through `free_string`,
+/// a null error string means the call succeeded
+// tuples are not FFI-safe in general, but host and plugins both lay them out as Rust does
+#[allow(improper_ctypes_definitions)]
+pub mod symbols {
+    use super::{DLLDeviceLogin, DLLFileDetails, DLLHandle, DLLInfo, DLLString};
+    use std::ffi::c_char;
+
+    pub type AbiVersion = unsafe extern "C" fn() -> u32;
+    pub type Capabilities = unsafe extern "C" fn() -> u64;
+    pub type FreeString = unsafe extern "C" fn(DLLString);
+    /// Reads the kind of an error string before the host frees it
+    pub type ErrorKind = unsafe extern "C" fn(DLLString) -> u32;
+
+    pub type Info = unsafe extern "C" fn() -> DLLInfo;
+    /// Called after the host freed every string of the info through `free_string`
+    pub type FreeInfo = unsafe extern "C" fn(DLLInfo);
+
+    /// (credentials, settings) -> (refreshed credentials, error)
+    /// Settings are a JSON object of the values entered for `settings_schema`, empty without one
+    pub type Authenticate = unsafe extern "C" fn(DLLString, DLLString) -> (DLLString, DLLString);
+    /// (credentials) -> (refreshed credentials, error), `authenticate` up to ABI version 2
+    pub type AuthenticateV2 = unsafe extern "C" fn(DLLString) -> (DLLString, DLLString);
+    /// () -> JSON Schema of an object, whose properties the app shows as the plugin's settings
+    pub type SettingsSchema = unsafe extern "C" fn() -> DLLString;
+    /// (redirect_uri, state, code_challenge) -> url
+    /// The challenge is the S256 PKCE challenge, both go into the authorization request
+    pub type AuthUrl = unsafe extern "C" fn(DLLString, DLLString, DLLString) -> DLLString;
+    /// (redirect_uri) -> url, `auth_url` up to ABI version 3
+    pub type AuthUrlV3 = unsafe extern "C" fn(DLLString) -> DLLString;
+    /// (redirect url, code_verifier) -> (credentials, error)
+    /// The host checked the state of the redirect before calling
+    pub type ExtractCredentials =
+        unsafe extern "C" fn(DLLString, DLLString) -> (DLLString, DLLString);
+    /// (redirect url) -> (credentials, error), `extract_credentials` up to ABI version 3
+    pub type ExtractCredentialsV3 = unsafe extern "C" fn(DLLString) -> (DLLString, DLLString);
+    /// () -> (login, error), starts a login the user finishes on another device
+    pub type DeviceLogin = unsafe extern "C" fn() -> (DLLDeviceLogin, DLLString);
+    /// (device_code) -> (credentials, error), both null while the user has not finished
+    /// A RATE_LIMITED error asks the host to poll slower, any other error ends the login
+    pub type PollDeviceLogin = unsafe extern "C" fn(DLLString) -> (DLLString, DLLString);
+    /// (credentials) -> (new credentials, error), called before the credentials expire
+    /// and once after a call failed with an AUTH error
+    pub type Refresh = unsafe extern "C" fn(DLLString) -> (DLLString, DLLString);
+    /// (credentials) -> seconds since the unix epoch at which they expire, 0 when they do not
+    pub type CredentialsExpiry = unsafe extern "C" fn(DLLString) -> u64;
+
+    /// (credentials, tag, folder_name, last_modified, data, data length) -> error
+    pub type Upload =
+        unsafe extern "C" fn(DLLString, DLLString, DLLString, u64, DLLString, u64) -> DLLString;
+    /// (credentials, tag, folder_name) -> (data, data length, error)
+    pub type Download =
+        unsafe extern "C" fn(DLLString, DLLString, DLLString) -> (DLLString, u64, DLLString);
+    /// (credentials, tag, folder_name) -> error
+    pub type Remove = unsafe extern "C" fn(DLLString, DLLString, DLLString) -> DLLString;
+    /// (tag, folder_name) -> error, called from another thread while a call on the file is running
+    /// Makes that call return early with an error, without committing anything
+    pub type Abort = unsafe extern "C" fn(DLLString, DLLString) -> DLLString;
+
+    /// (credentials) -> (details, count, error), `D` is the file details layout of the ABI version
+    pub type ReadCloud<D> = unsafe extern "C" fn(DLLString) -> (*const D, u64, DLLString);
+    /// Called after the host freed the tag and folder_name of every detail through `free_string`
+    pub type FreeFileDetails<D> = unsafe extern "C" fn(u64, *const D);
+    /// (credentials, cursor) -> (details, count, next cursor, error)
+    /// Lists the files changed since the cursor, or every file for an empty cursor
+    /// Removed files are listed with a last_modified of 0, details go back through `free_file_details`
+    pub type ReadChanges =
+        unsafe extern "C" fn(
+            DLLString,
+            DLLString,
+        ) -> (*const DLLFileDetails, u64, DLLString, DLLString);
+
+    /// (credentials, tag, folder_name, last_modified, length) -> (handle, error)
+    pub type UploadOpen =
+        unsafe extern "C" fn(DLLString, DLLString, DLLString, u64, u64) -> (DLLHandle, DLLString);
+    /// (handle, chunk, chunk length) -> error
+    pub type UploadWrite = unsafe extern "C" fn(DLLHandle, DLLString, u64) -> DLLString;
+    /// (handle, commit) -> error, consumes the handle
+    pub type UploadFinish = unsafe extern "C" fn(DLLHandle, bool) -> DLLString;
+    /// (credentials, tag, folder_name) -> (handle, error)
+    pub type DownloadOpen =
+        unsafe extern "C" fn(DLLString, DLLString, DLLString) -> (DLLHandle, DLLString);
+    /// (handle, buffer, buffer length) -> (bytes read, error), 0 bytes marks the end
+    pub type DownloadRead = unsafe extern "C" fn(DLLHandle, *mut c_char, u64) -> (u64, DLLString);
+    /// Consumes the handle
+    pub type DownloadClose = unsafe extern "C" fn(DLLHandle);
+
+    /// (tag, folder_name, bytes done, bytes total), total is 0 when unknown
+    /// Implemented by the host, plugins may call it from any thread while a call on the file runs
+    pub type ProgressCallback = unsafe extern "C" fn(DLLString, DLLString, u64, u64);
+    /// (callback), called once after the plugin is loaded
+    pub type SetProgressCallback = unsafe extern "C" fn(ProgressCallback);
+
+    /// (context, level, target, message), level is one of `log_level`
+    /// Implemented by the host, plugins may call it from any thread and keep ownership of the strings
+    pub type LogCallback = unsafe extern "C" fn(DLLHandle, u32, DLLString, DLLString);
+    /// (callback, context), called once after the plugin is loaded
+    /// Every record passes the context back unchanged, it tells the host which plugin logged
+    pub type SetLogCallback = unsafe extern "C" fn(LogCallback, DLLHandle);
+}
@@ -0,0 +1,378 @@
+//! Procedural macros of savesync-plugin-sdk, use them through that crate
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{Error, ImplItem, ItemImpl, parse_macro_input};
+
+/// Generates the exported symbols for an `impl CloudPlugin for T` block
+/// `T` is created through `Default` the first time SaveSync calls into the plugin
+#[proc_macro_attribute]
+pub fn plugin(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return Error::new(Span::call_site(), "#[plugin] takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let item = parse_macro_input!(item as ItemImpl);
+    if item.trait_.is_none() {
+        return Error::new_spanned(
+            &item.self_ty,
+            "#[plugin] goes on an `impl CloudPlugin for T` block",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let remove = defines(&item, "remove");
+    let abort = defines(&item, "abort");
+    let changes = defines(&item, "read_changes");
+    let settings = defines(&item, "settings_schema");
+    let pairs = || {
+        Ok::<_, Error>((
+            paired(&item, ("device_login", "poll_device_login", "device login"))?,
+            paired(&item, ("refresh", "credentials_expiry", "refreshing"))?,
+            paired(&item, ("upload_open", "download_open", "streaming"))?,
+        ))
+    };
+    let (device_login, refresh, streaming) = match pairs() {
+        Ok(pairs) => pairs,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let self_ty = &item.self_ty;
+
+    let remove_export = remove.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn remove(
+                credentials: DLLString,
+                tag: DLLString,
+                folder_name: DLLString,
+            ) -> DLLString {
+                unsafe { ffi::remove(plugin(), credentials, tag, folder_name) }
+            }
+            const _: symbols::Remove = remove;
+        }
+    });
+
+    let abort_export = abort.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn abort(tag: DLLString, folder_name: DLLString) -> DLLString {
+                unsafe { ffi::abort(plugin(), tag, folder_name) }
+            }
+            const _: symbols::Abort = abort;
+        }
+    });
+
+    let changes_export = changes.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn read_changes(
+                credentials: DLLString,
+                cursor: DLLString,
+            ) -> (*const DLLFileDetails, u64, DLLString, DLLString) {
+                unsafe { ffi::read_changes(plugin(), credentials, cursor) }
+            }
+            const _: symbols::ReadChanges = read_changes;
+        }
+    });
+
+    let settings_export = settings.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn settings_schema() -> DLLString {
+                ffi::settings_schema(plugin())
+            }
+            const _: symbols::SettingsSchema = settings_schema;
+        }
+    });
+
+    let device_login_exports = device_login.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn device_login() -> (abi::DLLDeviceLogin, DLLString) {
+                ffi::device_login(plugin())
+            }
+            const _: symbols::DeviceLogin = device_login;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn poll_device_login(device_code: DLLString) -> (DLLString, DLLString) {
+                unsafe { ffi::poll_device_login(plugin(), device_code) }
+            }
+            const _: symbols::PollDeviceLogin = poll_device_login;
+        }
+    });
+
+    let refresh_exports = refresh.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn refresh(credentials: DLLString) -> (DLLString, DLLString) {
+                unsafe { ffi::refresh(plugin(), credentials) }
+            }
+            const _: symbols::Refresh = refresh;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn credentials_expiry(credentials: DLLString) -> u64 {
+                unsafe { ffi::credentials_expiry(plugin(), credentials) }
+            }
+            const _: symbols::CredentialsExpiry = credentials_expiry;
+        }
+    });
+
+    let streaming_exports = streaming.then(|| {
+        quote! {
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn upload_open(
+                credentials: DLLString,
+                tag: DLLString,
+                folder_name: DLLString,
+                date: u64,
+                len: u64,
+            ) -> (DLLHandle, DLLString) {
+                unsafe { ffi::upload_open(plugin(), credentials, tag, folder_name, date, len) }
+            }
+            const _: symbols::UploadOpen = upload_open;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn upload_write(
+                handle: DLLHandle,
+                chunk: DLLString,
+                len: u64,
+            ) -> DLLString {
+                unsafe { ffi::upload_write(handle, chunk, len) }
+            }
+            const _: symbols::UploadWrite = upload_write;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn upload_finish(handle: DLLHandle, commit: bool) -> DLLString {
+                unsafe { ffi::upload_finish(handle, commit) }
+            }
+            const _: symbols::UploadFinish = upload_finish;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn download_open(
+                credentials: DLLString,
+                tag: DLLString,
+                folder_name: DLLString,
+            ) -> (DLLHandle, DLLString) {
+                unsafe { ffi::download_open(plugin(), credentials, tag, folder_name) }
+            }
+            const _: symbols::DownloadOpen = download_open;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn download_read(
+                handle: DLLHandle,
+                buffer: *mut ::std::ffi::c_char,
+                len: u64,
+            ) -> (u64, DLLString) {
+                unsafe { ffi::download_read(handle, buffer, len) }
+            }
+            const _: symbols::DownloadRead = download_read;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn download_close(handle: DLLHandle) {
+                unsafe { ffi::download_close(handle) }
+            }
+            const _: symbols::DownloadClose = download_close;
+        }
+    });
+
+    quote! {
+        #item
+
+        #[allow(improper_ctypes_definitions)]
+        const _: () = {
+            use ::savesync_plugin_sdk::{
+                abi::{self, DLLFileDetails, DLLHandle, DLLInfo, DLLString, symbols},
+                ffi,
+            };
+
+            static PLUGIN: ::std::sync::LazyLock<#self_ty> =
+                ::std::sync::LazyLock::new(::std::default::Default::default);
+
+            fn plugin() -> &'static #self_ty {
+                &PLUGIN
+            }
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn abi_version() -> u32 {
+                abi::ABI_VERSION
+            }
+            const _: symbols::AbiVersion = abi_version;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn capabilities() -> u64 {
+                ffi::capabilities(#remove, #abort, #changes, #streaming, #settings, #device_login, #refresh)
+            }
+            const _: symbols::Capabilities = capabilities;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn set_progress_callback(callback: symbols::ProgressCallback) {
+                ffi::set_progress_callback(callback)
+            }
+            const _: symbols::SetProgressCallback = set_progress_callback;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn set_log_callback(
+                callback: symbols::LogCallback,
+                context: DLLHandle,
+            ) {
+                ffi::set_log_callback(callback, context)
+            }
+            const _: symbols::SetLogCallback = set_log_callback;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn free_string(raw: DLLString) {
+                unsafe { ffi::free_string(raw) }
+            }
+            const _: symbols::FreeString = free_string;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn error_kind(raw: DLLString) -> u32 {
+                unsafe { ffi::error_kind(raw) }
+            }
+            const _: symbols::ErrorKind = error_kind;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn info() -> DLLInfo {
+                ffi::info(plugin())
+            }
+            const _: symbols::Info = info;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn free_info(info: DLLInfo) {
+                ffi::free_info(info)
+            }
+            const _: symbols::FreeInfo = free_info;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn authenticate(
+                credentials: DLLString,
+                settings: DLLString,
+            ) -> (DLLString, DLLString) {
+                unsafe { ffi::authenticate(plugin(), credentials, settings) }
+            }
+            const _: symbols::Authenticate = authenticate;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn auth_url(
+                redirect_uri: DLLString,
+                state: DLLString,
+                code_challenge: DLLString,
+            ) -> DLLString {
+                unsafe { ffi::auth_url(plugin(), redirect_uri, state, code_challenge) }
+            }
+            const _: symbols::AuthUrl = auth_url;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn extract_credentials(
+                url: DLLString,
+                code_verifier: DLLString,
+            ) -> (DLLString, DLLString) {
+                unsafe { ffi::extract_credentials(plugin(), url, code_verifier) }
+            }
+            const _: symbols::ExtractCredentials = extract_credentials;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn upload(
+                credentials: DLLString,
+                tag: DLLString,
+                folder_name: DLLString,
+                date: u64,
+                data: DLLString,
+                len: u64,
+            ) -> DLLString {
+                unsafe { ffi::upload(plugin(), credentials, tag, folder_name, date, data, len) }
+            }
+            const _: symbols::Upload = upload;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn download(
+                credentials: DLLString,
+                tag: DLLString,
+                folder_name: DLLString,
+            ) -> (DLLString, u64, DLLString) {
+                unsafe { ffi::download(plugin(), credentials, tag, folder_name) }
+            }
+            const _: symbols::Download = download;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn read_cloud(
+                credentials: DLLString,
+            ) -> (*const DLLFileDetails, u64, DLLString) {
+                unsafe { ffi::read_cloud(plugin(), credentials) }
+            }
+            const _: symbols::ReadCloud<DLLFileDetails> = read_cloud;
+
+            #[unsafe(no_mangle)]
+            unsafe extern "C" fn free_file_details(count: u64, details: *const DLLFileDetails) {
+                unsafe { ffi::free_file_details(count, details) }
+            }
+            const _: symbols::FreeFileDetails<DLLFileDetails> = free_file_details;
+
+            #remove_export
+            #abort_export
+            #changes_export
+            #settings_export
+            #device_login_exports
+            #refresh_exports
+            #streaming_exports
+        };
+    }
+    .into()
+}
+
+fn defines(item: &ItemImpl, name: &str) -> bool {
+    item.items
+        .iter()
+        .any(|i| matches!(i, ImplItem::Fn(f) if f.sig.ident == name))
+}
+
+/// Whether the impl defines both functions of `feature`, which only work together
+fn paired(item: &ItemImpl, (first, second, feature): (&str, &str, &str)) -> syn::Result<bool> {
+    match (defines(item, first), defines(item, second)) {
+        (true, true) => Ok(true),
+        (false, false) => Ok(false),
+        _ => Err(Error::new_spanned(
+            &item.self_ty,
+            format!("{feature} needs both {first} and {second}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAMING: (&str, &str, &str) = ("upload_open", "download_open", "streaming");
+
+    #[test]
+    fn pairs_need_both_functions() {
+        let both: ItemImpl = syn::parse_quote! {
+            impl CloudPlugin for Cloud {
+                fn upload_open(&self) {}
+                fn download_open(&self) {}
+            }
+        };
+        let neither: ItemImpl = syn::parse_quote! {
+            impl CloudPlugin for Cloud {
+                fn upload(&self) {}
+            }
+        };
+        let half: ItemImpl = syn::parse_quote! {
+            impl CloudPlugin for Cloud {
+                fn download_open(&self) {}
+            }
+        };
+
+        assert!(paired(&both, STREAMING).unwrap());
+        assert!(!paired(&neither, STREAMING).unwrap());
+        assert_eq!(
+            paired(&half, STREAMING).unwrap_err().to_string(),
+            "streaming needs both upload_open and download_open"
+        );
+    }
+}
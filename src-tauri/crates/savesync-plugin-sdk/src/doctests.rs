@@ -0,0 +1,304 @@
+//! Checks of `#[plugin]` that need a whole crate, run as doctests
+//! Functions the host only calls together must be defined together, or not at all
+
+/// Every pair defined
+///
+/// ```no_run
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn device_login(&self) -> Result<DeviceLogin> {
+///         unimplemented!()
+///     }
+///     fn poll_device_login(&self, _: &str) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn refresh(&self, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn credentials_expiry(&self, _: &str) -> Option<SystemTime> {
+///         unimplemented!()
+///     }
+///     fn upload_open(
+///         &self,
+///         _: &str,
+///         _: &str,
+///         _: &str,
+///         _: SystemTime,
+///         _: u64,
+///     ) -> Result<Box<dyn UploadStream>> {
+///         unimplemented!()
+///     }
+///     fn download_open(&self, _: &str, _: &str, _: &str) -> Result<Box<dyn Read + Send>> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct Paired;
+
+/// device_login without poll_device_login
+///
+/// ```compile_fail
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn device_login(&self) -> Result<DeviceLogin> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct DeviceLoginWithoutPoll;
+
+/// poll_device_login without device_login
+///
+/// ```compile_fail
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn poll_device_login(&self, _: &str) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct PollWithoutDeviceLogin;
+
+/// refresh without credentials_expiry
+///
+/// ```compile_fail
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn refresh(&self, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct RefreshWithoutExpiry;
+
+/// credentials_expiry without refresh
+///
+/// ```compile_fail
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn credentials_expiry(&self, _: &str) -> Option<SystemTime> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct ExpiryWithoutRefresh;
+
+/// upload_open without download_open
+///
+/// ```compile_fail
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn upload_open(
+///         &self,
+///         _: &str,
+///         _: &str,
+///         _: &str,
+///         _: SystemTime,
+///         _: u64,
+///     ) -> Result<Box<dyn UploadStream>> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct UploadOpenWithoutDownloadOpen;
+
+/// download_open without upload_open
+///
+/// ```compile_fail
+/// use savesync_plugin_sdk::*;
+/// use std::{io::Read, time::SystemTime};
+///
+/// #[derive(Default)]
+/// struct Cloud;
+///
+/// #[plugin]
+/// impl CloudPlugin for Cloud {
+///     fn info(&self) -> Info {
+///         unimplemented!()
+///     }
+///     fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+///         unimplemented!()
+///     }
+///     fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+///         unimplemented!()
+///     }
+///     fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+///         unimplemented!()
+///     }
+///     fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+///         unimplemented!()
+///     }
+///     fn download(&self, _: &str, _: &str, _: &str) -> Result<Vec<u8>> {
+///         unimplemented!()
+///     }
+///     fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+///         unimplemented!()
+///     }
+///     fn download_open(&self, _: &str, _: &str, _: &str) -> Result<Box<dyn Read + Send>> {
+///         unimplemented!()
+///     }
+/// }
+/// ```
+pub struct DownloadOpenWithoutUploadOpen;
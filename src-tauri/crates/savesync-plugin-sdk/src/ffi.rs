@@ -0,0 +1,612 @@
+//! Glue called by the symbols `#[plugin]` generates
+//! Every string and buffer handed to the host carries a header, so `free_string` can free
+//! binary data and `error_kind` can read the kind of an error
+
+use std::{
+    ffi::{CStr, CString, c_char},
+    io::{self, Read},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::OnceLock,
+};
+
+use crate::{
+    Changes, CloudPlugin, DeviceLogin, Error, ErrorKind, FileDetails, Result, Settings,
+    UploadStream, from_secs, to_secs,
+};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use savesync_plugin_abi::{
+    DLLDeviceLogin, DLLFileDetails, DLLHandle, DLLInfo, DLLString, capabilities, log_level,
+    symbols::{LogCallback, ProgressCallback},
+};
+
+/// [allocation length: u64][error kind: u32][padding: u32]
+const HEADER: usize = 16;
+
+static PROGRESS: OnceLock<ProgressCallback> = OnceLock::new();
+/// The host's log callback and the context to hand back to it, kept as an address
+static LOG: OnceLock<(LogCallback, usize)> = OnceLock::new();
+
+/// Forwards the records of the `log` macros to the host's log callback
+struct HostLogger;
+
+impl Log for HostLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        LOG.get().is_some()
+    }
+
+    fn log(&self, record: &Record) {
+        let level = match record.level() {
+            Level::Error => log_level::ERROR,
+            Level::Warn => log_level::WARN,
+            Level::Info => log_level::INFO,
+            Level::Debug => log_level::DEBUG,
+            Level::Trace => log_level::TRACE,
+        };
+        if let Some((callback, context)) = LOG.get()
+            && let (Ok(target), Ok(message)) = (
+                CString::new(record.target()),
+                CString::new(record.args().to_string()),
+            )
+        {
+            unsafe {
+                callback(
+                    *context as DLLHandle,
+                    level,
+                    target.as_ptr(),
+                    message.as_ptr(),
+                )
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn alloc(bytes: &[u8], kind: u32) -> DLLString {
+    let len = HEADER + bytes.len() + 1;
+    let mut buffer = Vec::with_capacity(len);
+    buffer.extend_from_slice(&(len as u64).to_le_bytes());
+    buffer.extend_from_slice(&kind.to_le_bytes());
+    buffer.extend_from_slice(&[0; 4]);
+    buffer.extend_from_slice(bytes);
+    // keeps strings NUL-terminated for the host's CStr
+    buffer.push(0);
+
+    let base = Box::into_raw(buffer.into_boxed_slice()) as *mut u8;
+    unsafe { base.add(HEADER) as DLLString }
+}
+
+unsafe fn header(raw: DLLString) -> (*mut u8, usize, u32) {
+    unsafe {
+        let base = (raw as *mut u8).sub(HEADER);
+        let len = u64::from_le_bytes(*(base as *const [u8; 8]));
+        let kind = u32::from_le_bytes(*(base.add(8) as *const [u8; 4]));
+        (base, len as usize, kind)
+    }
+}
+
+fn string(s: &str) -> DLLString {
+    alloc(s.as_bytes(), 0)
+}
+
+fn error(e: &Error) -> DLLString {
+    alloc(e.message.as_bytes(), e.kind.code())
+}
+
+fn error_or_null(result: Result<()>) -> DLLString {
+    result.err().map_or(ptr::null(), |e| error(&e))
+}
+
+/// Copies a string the host owns
+unsafe fn arg(raw: DLLString) -> String {
+    match raw.is_null() {
+        true => String::new(),
+        false => unsafe { CStr::from_ptr(raw) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+unsafe fn bytes<'a>(raw: DLLString, len: u64) -> &'a [u8] {
+    match raw.is_null() {
+        true => &[],
+        false => unsafe { slice::from_raw_parts(raw as *const u8, len as usize) },
+    }
+}
+
+/// Turns a panic into an error, unwinding into the host is undefined behaviour
+fn guard<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(Error::other("Plugin panicked")))
+}
+
+pub fn capabilities(
+    remove: bool,
+    abort: bool,
+    changes: bool,
+    streaming: bool,
+    settings: bool,
+    device_login: bool,
+    refresh: bool,
+) -> u64 {
+    let mut bits = capabilities::INLINE_DATA | capabilities::PROGRESS | capabilities::LOGGING;
+    if remove {
+        bits |= capabilities::REMOVE;
+    }
+    if abort {
+        bits |= capabilities::ABORT;
+    }
+    if changes {
+        bits |= capabilities::CHANGES;
+    }
+    if streaming {
+        bits |= capabilities::STREAMING;
+    }
+    if settings {
+        bits |= capabilities::SETTINGS;
+    }
+    if device_login {
+        bits |= capabilities::DEVICE_LOGIN;
+    }
+    if refresh {
+        bits |= capabilities::REFRESH;
+    }
+    bits
+}
+
+pub fn set_progress_callback(callback: ProgressCallback) {
+    let _ = PROGRESS.set(callback);
+}
+
+/// Installs the logger, unless the plugin set up its own
+pub fn set_log_callback(callback: LogCallback, context: DLLHandle) {
+    let _ = LOG.set((callback, context as usize));
+    if log::set_logger(&HostLogger).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+}
+
+/// Hands progress to the host, does nothing before it set its callback
+pub fn progress(tag: &str, folder_name: &str, done: u64, total: u64) {
+    if let Some(callback) = PROGRESS.get()
+        && let (Ok(tag), Ok(folder_name)) = (CString::new(tag), CString::new(folder_name))
+    {
+        unsafe { callback(tag.as_ptr(), folder_name.as_ptr(), done, total) }
+    }
+}
+
+pub unsafe fn free_string(raw: DLLString) {
+    if !raw.is_null() {
+        unsafe {
+            let (base, len, _) = header(raw);
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(base, len)));
+        }
+    }
+}
+
+pub unsafe fn error_kind(raw: DLLString) -> u32 {
+    match raw.is_null() {
+        true => ErrorKind::Other.code(),
+        false => unsafe { header(raw).2 },
+    }
+}
+
+pub fn info<P: CloudPlugin>(plugin: &P) -> DLLInfo {
+    match guard(|| Ok(plugin.info())) {
+        Ok(info) => (
+            string(&info.name),
+            string(&info.description),
+            string(&info.author),
+            string(&info.icon_url),
+        ),
+        Err(_) => (ptr::null(), ptr::null(), ptr::null(), ptr::null()),
+    }
+}
+
+/// The host frees every string of the info itself
+pub fn free_info(_: DLLInfo) {}
+
+pub unsafe fn authenticate<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    settings: DLLString,
+) -> (DLLString, DLLString) {
+    let credentials = unsafe { arg(credentials) };
+    let settings: Settings = serde_json::from_str(&unsafe { arg(settings) }).unwrap_or_default();
+    match guard(|| plugin.authenticate(&credentials, &settings)) {
+        Ok(refreshed) => (
+            refreshed.as_deref().map_or(ptr::null(), string),
+            ptr::null(),
+        ),
+        Err(e) => (ptr::null(), error(&e)),
+    }
+}
+
+pub fn settings_schema<P: CloudPlugin>(plugin: &P) -> DLLString {
+    guard(|| Ok(plugin.settings_schema())).map_or(ptr::null(), |schema| string(&schema.to_string()))
+}
+
+pub unsafe fn auth_url<P: CloudPlugin>(
+    plugin: &P,
+    redirect_uri: DLLString,
+    state: DLLString,
+    code_challenge: DLLString,
+) -> DLLString {
+    let (redirect_uri, state, code_challenge) =
+        unsafe { (arg(redirect_uri), arg(state), arg(code_challenge)) };
+    guard(|| Ok(plugin.auth_url(&redirect_uri, &state, &code_challenge)))
+        .ok()
+        .flatten()
+        .map_or(ptr::null(), |url| string(&url))
+}
+
+pub unsafe fn extract_credentials<P: CloudPlugin>(
+    plugin: &P,
+    url: DLLString,
+    code_verifier: DLLString,
+) -> (DLLString, DLLString) {
+    let (url, code_verifier) = unsafe { (arg(url), arg(code_verifier)) };
+    match guard(|| plugin.extract_credentials(&url, &code_verifier)) {
+        Ok(credentials) => (string(&credentials), ptr::null()),
+        Err(e) => (ptr::null(), error(&e)),
+    }
+}
+
+pub fn device_login<P: CloudPlugin>(plugin: &P) -> (DLLDeviceLogin, DLLString) {
+    let empty = (ptr::null(), ptr::null(), ptr::null(), 0, 0);
+    match guard(|| plugin.device_login()) {
+        Ok(DeviceLogin {
+            device_code,
+            user_code,
+            verification_uri,
+            interval,
+            expires_in,
+        }) => (
+            (
+                string(&device_code),
+                string(&user_code),
+                string(&verification_uri),
+                interval.as_secs(),
+                expires_in.as_secs(),
+            ),
+            ptr::null(),
+        ),
+        Err(e) => (empty, error(&e)),
+    }
+}
+
+pub unsafe fn poll_device_login<P: CloudPlugin>(
+    plugin: &P,
+    device_code: DLLString,
+) -> (DLLString, DLLString) {
+    let device_code = unsafe { arg(device_code) };
+    match guard(|| plugin.poll_device_login(&device_code)) {
+        Ok(credentials) => (
+            credentials.as_deref().map_or(ptr::null(), string),
+            ptr::null(),
+        ),
+        Err(e) => (ptr::null(), error(&e)),
+    }
+}
+
+pub unsafe fn refresh<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+) -> (DLLString, DLLString) {
+    let credentials = unsafe { arg(credentials) };
+    match guard(|| plugin.refresh(&credentials)) {
+        Ok(refreshed) => (string(&refreshed), ptr::null()),
+        Err(e) => (ptr::null(), error(&e)),
+    }
+}
+
+pub unsafe fn credentials_expiry<P: CloudPlugin>(plugin: &P, credentials: DLLString) -> u64 {
+    let credentials = unsafe { arg(credentials) };
+    guard(|| Ok(plugin.credentials_expiry(&credentials)))
+        .ok()
+        .flatten()
+        .map_or(0, to_secs)
+}
+
+pub unsafe fn upload<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    tag: DLLString,
+    folder_name: DLLString,
+    date: u64,
+    data: DLLString,
+    len: u64,
+) -> DLLString {
+    let (credentials, tag, folder_name) = unsafe { (arg(credentials), arg(tag), arg(folder_name)) };
+    let data = unsafe { bytes(data, len) };
+    error_or_null(guard(|| {
+        plugin.upload(&credentials, &tag, &folder_name, from_secs(date), data)
+    }))
+}
+
+pub unsafe fn download<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    tag: DLLString,
+    folder_name: DLLString,
+) -> (DLLString, u64, DLLString) {
+    let (credentials, tag, folder_name) = unsafe { (arg(credentials), arg(tag), arg(folder_name)) };
+    match guard(|| plugin.download(&credentials, &tag, &folder_name)) {
+        Ok(data) => (alloc(&data, 0), data.len() as u64, ptr::null()),
+        Err(e) => (ptr::null(), 0, error(&e)),
+    }
+}
+
+pub unsafe fn remove<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    tag: DLLString,
+    folder_name: DLLString,
+) -> DLLString {
+    let (credentials, tag, folder_name) = unsafe { (arg(credentials), arg(tag), arg(folder_name)) };
+    error_or_null(guard(|| plugin.remove(&credentials, &tag, &folder_name)))
+}
+
+pub unsafe fn abort<P: CloudPlugin>(
+    plugin: &P,
+    tag: DLLString,
+    folder_name: DLLString,
+) -> DLLString {
+    let (tag, folder_name) = unsafe { (arg(tag), arg(folder_name)) };
+    error_or_null(guard(|| plugin.abort(&tag, &folder_name)))
+}
+
+pub unsafe fn read_cloud<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+) -> (*const DLLFileDetails, u64, DLLString) {
+    let credentials = unsafe { arg(credentials) };
+    match guard(|| plugin.read_cloud(&credentials)) {
+        Ok(details) => {
+            let (details, count) = details_array(details.iter().map(detail));
+            (details, count, ptr::null())
+        }
+        Err(e) => (ptr::null(), 0, error(&e)),
+    }
+}
+
+fn detail(detail: &FileDetails) -> DLLFileDetails {
+    let (data, len) = detail
+        .data
+        .as_deref()
+        .map_or((ptr::null(), 0), |data| (alloc(data, 0), data.len() as u64));
+    (
+        string(&detail.tag),
+        string(&detail.folder_name),
+        to_secs(detail.last_modified),
+        data,
+        len,
+    )
+}
+
+/// Hands the details to the host, which returns them through `free_file_details`
+fn details_array(details: impl Iterator<Item = DLLFileDetails>) -> (*const DLLFileDetails, u64) {
+    let details: Box<[DLLFileDetails]> = details.collect();
+    let count = details.len() as u64;
+    (Box::into_raw(details) as *const DLLFileDetails, count)
+}
+
+pub unsafe fn read_changes<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    cursor: DLLString,
+) -> (*const DLLFileDetails, u64, DLLString, DLLString) {
+    let (credentials, cursor) = unsafe { (arg(credentials), arg(cursor)) };
+    match guard(|| plugin.read_changes(&credentials, &cursor)) {
+        Ok(Changes {
+            changed,
+            removed,
+            cursor,
+        }) => {
+            let removed = removed
+                .iter()
+                .map(|(tag, folder_name)| (string(tag), string(folder_name), 0, ptr::null(), 0));
+            let (details, count) = details_array(changed.iter().map(detail).chain(removed));
+            (details, count, string(&cursor), ptr::null())
+        }
+        Err(e) => (ptr::null(), 0, ptr::null(), error(&e)),
+    }
+}
+
+/// The host frees the tag and folder name of every detail itself, only the data is left
+pub unsafe fn free_file_details(count: u64, details: *const DLLFileDetails) {
+    if details.is_null() {
+        return;
+    }
+
+    unsafe {
+        let details = Box::from_raw(ptr::slice_from_raw_parts_mut(
+            details as *mut DLLFileDetails,
+            count as usize,
+        ));
+        details.iter().for_each(|detail| free_string(detail.3));
+    }
+}
+
+pub unsafe fn upload_open<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    tag: DLLString,
+    folder_name: DLLString,
+    date: u64,
+    len: u64,
+) -> (DLLHandle, DLLString) {
+    let (credentials, tag, folder_name) = unsafe { (arg(credentials), arg(tag), arg(folder_name)) };
+    match guard(|| plugin.upload_open(&credentials, &tag, &folder_name, from_secs(date), len)) {
+        Ok(stream) => (Box::into_raw(Box::new(stream)) as DLLHandle, ptr::null()),
+        Err(e) => (ptr::null_mut(), error(&e)),
+    }
+}
+
+pub unsafe fn upload_write(handle: DLLHandle, chunk: DLLString, len: u64) -> DLLString {
+    let stream = unsafe { &mut *(handle as *mut Box<dyn UploadStream>) };
+    let chunk = unsafe { bytes(chunk, len) };
+    error_or_null(guard(|| Ok(stream.write_all(chunk)?)))
+}
+
+pub unsafe fn upload_finish(handle: DLLHandle, commit: bool) -> DLLString {
+    let stream = unsafe { *Box::from_raw(handle as *mut Box<dyn UploadStream>) };
+    error_or_null(guard(|| stream.finish(commit)))
+}
+
+pub unsafe fn download_open<P: CloudPlugin>(
+    plugin: &P,
+    credentials: DLLString,
+    tag: DLLString,
+    folder_name: DLLString,
+) -> (DLLHandle, DLLString) {
+    let (credentials, tag, folder_name) = unsafe { (arg(credentials), arg(tag), arg(folder_name)) };
+    match guard(|| plugin.download_open(&credentials, &tag, &folder_name)) {
+        Ok(reader) => (Box::into_raw(Box::new(reader)) as DLLHandle, ptr::null()),
+        Err(e) => (ptr::null_mut(), error(&e)),
+    }
+}
+
+pub unsafe fn download_read(handle: DLLHandle, buffer: *mut c_char, len: u64) -> (u64, DLLString) {
+    let reader = unsafe { &mut *(handle as *mut Box<dyn Read + Send>) };
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, len as usize) };
+    let result = guard(|| {
+        loop {
+            match reader.read(buffer) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return Ok(result?),
+            }
+        }
+    });
+
+    match result {
+        Ok(read) => (read as u64, ptr::null()),
+        Err(e) => (0, error(&e)),
+    }
+}
+
+pub unsafe fn download_close(handle: DLLHandle) {
+    drop(unsafe { Box::from_raw(handle as *mut Box<dyn Read + Send>) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Info;
+    use std::time::SystemTime;
+
+    #[derive(Default)]
+    struct Fixed;
+
+    impl CloudPlugin for Fixed {
+        fn info(&self) -> Info {
+            Info {
+                name: "Fixed".into(),
+                description: String::new(),
+                author: String::new(),
+                icon_url: String::new(),
+            }
+        }
+
+        fn authenticate(&self, _: &str, _: &Settings) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+            None
+        }
+
+        fn extract_credentials(&self, _: &str, _: &str) -> Result<String> {
+            Err(Error::new(ErrorKind::Auth, "login rejected"))
+        }
+
+        fn upload(&self, _: &str, _: &str, _: &str, _: SystemTime, _: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn download(&self, _: &str, tag: &str, folder_name: &str) -> Result<Vec<u8>> {
+            Ok(format!("{tag}/{folder_name}\0binary").into_bytes())
+        }
+
+        fn read_cloud(&self, _: &str) -> Result<Vec<FileDetails>> {
+            Ok(vec![FileDetails {
+                tag: "tag".into(),
+                folder_name: "save".into(),
+                last_modified: from_secs(1_700_000_000),
+                data: Some(vec![0, 1, 2]),
+            }])
+        }
+    }
+
+    /// Reads a string the plugin handed over, the way the host does
+    unsafe fn host_str(raw: DLLString) -> String {
+        unsafe { CStr::from_ptr(raw) }.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn errors_keep_their_kind_and_message() {
+        let raw = error(&Error::new(ErrorKind::NotFound, "missing"));
+        unsafe {
+            assert_eq!(host_str(raw), "missing");
+            assert_eq!(error_kind(raw), ErrorKind::NotFound.code());
+            free_string(raw);
+
+            assert_eq!(error_kind(ptr::null()), ErrorKind::Other.code());
+        }
+
+        let credentials = CString::new("").unwrap();
+        let (credentials, error) =
+            unsafe { extract_credentials(&Fixed, credentials.as_ptr(), ptr::null()) };
+        assert!(credentials.is_null());
+        unsafe {
+            assert_eq!(host_str(error), "login rejected");
+            assert_eq!(error_kind(error), ErrorKind::Auth.code());
+            free_string(error);
+        }
+    }
+
+    #[test]
+    fn downloads_keep_binary_data() {
+        let (tag, folder_name) = (CString::new("tag").unwrap(), CString::new("save").unwrap());
+        let (data, len, error) =
+            unsafe { download(&Fixed, ptr::null(), tag.as_ptr(), folder_name.as_ptr()) };
+
+        assert!(error.is_null());
+        unsafe {
+            assert_eq!(bytes(data, len), b"tag/save\0binary");
+            assert_eq!(error_kind(data), 0);
+            free_string(data);
+        }
+    }
+
+    #[test]
+    fn listings_round_trip() {
+        let (details, count, error) = unsafe { read_cloud(&Fixed, ptr::null()) };
+        assert!(error.is_null());
+        assert_eq!(count, 1);
+
+        let (tag, folder_name, last_modified, data, len) =
+            unsafe { slice::from_raw_parts(details, count as usize)[0] };
+        unsafe {
+            assert_eq!(host_str(tag), "tag");
+            assert_eq!(host_str(folder_name), "save");
+            assert_eq!(from_secs(last_modified), from_secs(1_700_000_000));
+            assert_eq!(bytes(data, len), [0, 1, 2]);
+
+            // the host frees the names, free_file_details the rest
+            free_string(tag);
+            free_string(folder_name);
+            free_file_details(count, details);
+        }
+    }
+
+    #[test]
+    fn capabilities_follow_the_defined_functions() {
+        let bits = capabilities(true, false, false, true, false, false, false);
+        assert_ne!(bits & capabilities::REMOVE, 0);
+        assert_ne!(bits & capabilities::STREAMING, 0);
+        assert_eq!(bits & capabilities::ABORT, 0);
+        assert_eq!(bits & capabilities::REFRESH, 0);
+    }
+}
@@ -0,0 +1,279 @@
+//! Write SaveSync plugins in safe Rust
+//!
+//! Implement [`CloudPlugin`] on a `Default` type and mark the impl with [`plugin`],
+//! which generates every symbol SaveSync loads and the glue that moves data across the ABI
+//!
+//! ```ignore
+//! use savesync_plugin_sdk::{CloudPlugin, FileDetails, Info, Result, plugin};
+//!
+//! #[derive(Default)]
+//! struct MyCloud;
+//!
+//! #[plugin]
+//! impl CloudPlugin for MyCloud {
+//!     // info, authenticate, auth_url, extract_credentials,
+//!     // upload, download and read_cloud go here
+//! }
+//! ```
+//!
+//! Settings a backend needs beyond its login, like a bucket or region, are declared as a JSON
+//! Schema by [`CloudPlugin::settings_schema`]. SaveSync shows them to the user and hands the
+//! values to every `authenticate` call
+//!
+//! Records of the re-exported [`log`] macros end up in the plugin's log file in SaveSync's
+//! `logs` folder, which is where to look when a backend misbehaves
+
+use std::{
+    fmt, io,
+    time::{Duration, SystemTime},
+};
+
+pub use log;
+pub use savesync_plugin_abi as abi;
+/// Generates the exported symbols for an `impl CloudPlugin for T` block
+/// `remove`, `abort`, `read_changes`, `settings_schema`, the device login and the streaming
+/// functions are only exported when the impl defines them
+pub use savesync_plugin_sdk_macros::plugin;
+pub use serde_json;
+
+#[doc(hidden)]
+pub mod ffi;
+
+#[cfg(doctest)]
+mod doctests;
+
+/// Values the user entered for the properties of [`CloudPlugin::settings_schema`]
+pub type Settings = serde_json::Map<String, serde_json::Value>;
+
+/// Shown in SaveSync's plugin list
+pub struct Info {
+    pub name: String,
+    pub description: String,
+    pub author: String,
+    pub icon_url: String,
+}
+
+/// A file stored in the cloud
+/// Attaching `data` saves SaveSync a `download` call
+pub struct FileDetails {
+    pub tag: String,
+    pub folder_name: String,
+    pub last_modified: SystemTime,
+    pub data: Option<Vec<u8>>,
+}
+
+/// A login the user finishes by entering `user_code` at `verification_uri` on another device,
+/// see [`CloudPlugin::device_login`]
+pub struct DeviceLogin {
+    /// Handed back to every `poll_device_login` call
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// How long SaveSync waits between polls
+    pub interval: Duration,
+    pub expires_in: Duration,
+}
+
+/// Files changed since a cursor, see [`CloudPlugin::read_changes`]
+pub struct Changes {
+    pub changed: Vec<FileDetails>,
+    /// (tag, folder_name) of every removed file
+    pub removed: Vec<(String, String)>,
+    /// Handed back to the next `read_changes` call
+    pub cursor: String,
+}
+
+/// Lets SaveSync react to a failure, e.g. by logging in again on Auth
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    Other,
+    /// Credentials are missing, expired or revoked
+    Auth,
+    /// Service asked to slow down, SaveSync retries with backoff
+    RateLimited,
+    /// File does not exist in the cloud
+    NotFound,
+    /// Service could not be reached
+    Network,
+}
+
+impl ErrorKind {
+    pub fn code(self) -> u32 {
+        match self {
+            ErrorKind::Other => abi::error_kind::OTHER,
+            ErrorKind::Auth => abi::error_kind::AUTH,
+            ErrorKind::RateLimited => abi::error_kind::RATE_LIMITED,
+            ErrorKind::NotFound => abi::error_kind::NOT_FOUND,
+            ErrorKind::Network => abi::error_kind::NETWORK,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Error {
+        Error {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn other(message: impl Into<String>) -> Error {
+        Error::new(ErrorKind::Other, message)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        let kind = match e.kind() {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::Auth,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::TimedOut => ErrorKind::Network,
+            _ => ErrorKind::Other,
+        };
+        Error::new(kind, e.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An upload streamed in chunks, see [`CloudPlugin::upload_open`]
+pub trait UploadStream: io::Write + Send {
+    /// Completes the upload, or discards it if `commit` is false
+    fn finish(self: Box<Self>, commit: bool) -> Result<()>;
+}
+
+/// A storage service SaveSync syncs saves to
+/// Every call receives the credentials SaveSync saved for the plugin
+pub trait CloudPlugin: Default + Send + Sync + 'static {
+    fn info(&self) -> Info;
+
+    /// Checks the saved credentials, returning refreshed ones to save in their place
+    /// Keep what is needed of `settings`, later calls do not receive them
+    fn authenticate(&self, credentials: &str, settings: &Settings) -> Result<Option<String>>;
+
+    /// JSON Schema of an object, whose properties SaveSync shows as the plugin's settings
+    /// Only exported when implemented
+    fn settings_schema(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// URL the user is sent to for logging in, redirecting back to `redirect_uri`
+    /// Put `state` and the S256 `code_challenge` in the authorization request, SaveSync
+    /// rejects redirects that do not carry the state back
+    fn auth_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> Option<String>;
+
+    /// Reads the credentials out of the URL the login redirected to
+    /// `code_verifier` goes with the authorization code to the token endpoint
+    fn extract_credentials(&self, url: &str, code_verifier: &str) -> Result<String>;
+
+    /// Starts a login for machines without a browser, like a device authorization grant
+    /// Only exported, together with `poll_device_login`, when implemented
+    fn device_login(&self) -> Result<DeviceLogin> {
+        Err(Error::other("device login is not supported"))
+    }
+
+    /// Credentials once the user finished the login, None while they have not
+    /// Return an [`ErrorKind::RateLimited`] error when the service asks to poll slower
+    fn poll_device_login(&self, _device_code: &str) -> Result<Option<String>> {
+        Err(Error::other("device login is not supported"))
+    }
+
+    /// Trades credentials that are about to expire, or were rejected, for new ones
+    /// Only exported, together with `credentials_expiry`, when implemented
+    fn refresh(&self, _credentials: &str) -> Result<String> {
+        Err(Error::new(ErrorKind::Auth, "refresh is not supported"))
+    }
+
+    /// When the credentials stop working, None if they do not expire
+    /// SaveSync refreshes them shortly before
+    fn credentials_expiry(&self, _credentials: &str) -> Option<SystemTime> {
+        None
+    }
+
+    fn upload(
+        &self,
+        credentials: &str,
+        tag: &str,
+        folder_name: &str,
+        last_modified: SystemTime,
+        data: &[u8],
+    ) -> Result<()>;
+
+    fn download(&self, credentials: &str, tag: &str, folder_name: &str) -> Result<Vec<u8>>;
+
+    fn read_cloud(&self, credentials: &str) -> Result<Vec<FileDetails>>;
+
+    /// Only exported when implemented
+    fn remove(&self, _credentials: &str, _tag: &str, _folder_name: &str) -> Result<()> {
+        Err(Error::other("remove is not supported"))
+    }
+
+    /// Lists the files changed since `cursor`, every file if it is empty
+    /// Lets services with a change feed skip full listings, only exported when implemented
+    fn read_changes(&self, _credentials: &str, _cursor: &str) -> Result<Changes> {
+        Err(Error::other("change feeds are not supported"))
+    }
+
+    /// Stops the running call on the file, only exported when implemented
+    /// Called from another thread than the call it stops
+    fn abort(&self, _tag: &str, _folder_name: &str) -> Result<()> {
+        Err(Error::other("abort is not supported"))
+    }
+
+    /// Only exported, together with `download_open`, when implemented
+    fn upload_open(
+        &self,
+        _credentials: &str,
+        _tag: &str,
+        _folder_name: &str,
+        _last_modified: SystemTime,
+        _len: u64,
+    ) -> Result<Box<dyn UploadStream>> {
+        Err(Error::other("streaming is not supported"))
+    }
+
+    /// Only exported, together with `upload_open`, when implemented
+    fn download_open(
+        &self,
+        _credentials: &str,
+        _tag: &str,
+        _folder_name: &str,
+    ) -> Result<Box<dyn io::Read + Send>> {
+        Err(Error::other("streaming is not supported"))
+    }
+}
+
+/// Tells SaveSync how far the transfer of a file got, `total` is 0 when unknown
+/// SaveSync tracks streamed transfers itself and throttles the updates, so reporting every
+/// chunk of `upload` or `download` is fine
+pub fn report_progress(tag: &str, folder_name: &str, done: u64, total: u64) {
+    ffi::progress(tag, folder_name, done, total)
+}
+
+fn from_secs(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
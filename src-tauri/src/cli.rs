@@ -0,0 +1,102 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    io, thread,
+};
+
+use crate::{
+    listeners::start_device_login,
+    savesync::{
+        notifications::{DesktopNotification, desktop_notify},
+        vault::{self, VaultState},
+    },
+};
+
+/// Command line flag that logs a plugin in with a device code, for machines without a browser
+/// When SaveSync is already running, the login starts there and a notification shows the code
+const DEVICE_LOGIN_FLAG: &str = "--device-login";
+
+/// Returns the plugin filename if SaveSync was started with `--device-login <plugin>`
+pub fn requested_device_login() -> Option<OsString> {
+    device_login_arg(env::args_os())
+}
+
+/// Reads `--device-login <plugin>` out of the arguments of a SaveSync process
+pub fn device_login_arg<S: Into<OsString>>(args: impl IntoIterator<Item = S>) -> Option<OsString> {
+    let mut args = args.into_iter().map(Into::into);
+    args.by_ref().find(|arg| arg == DEVICE_LOGIN_FLAG)?;
+    args.next()
+}
+
+/// Logs the plugin in from the terminal SaveSync was started from, which shows the code
+/// The app keeps running and syncing once the login went through
+pub fn device_login(filename: OsString) {
+    thread::spawn(move || {
+        if vault::state() == VaultState::Locked && !unlock_from_stdin() {
+            return;
+        }
+
+        match start_device_login(&filename) {
+            Some(code) => println!(
+                "To log in to {}, open {} and enter the code {}",
+                filename.to_string_lossy(),
+                code.verification_uri,
+                code.user_code
+            ),
+            None => eprintln!(
+                "{} could not start a device login",
+                filename.to_string_lossy()
+            ),
+        }
+    });
+}
+
+/// Tells the user where the code shows up, called before the single instance check
+/// That check exits this process when SaveSync is already running and hands it the
+/// arguments, and the running instance cannot print to this terminal
+pub fn announce_device_login() {
+    println!(
+        "If SaveSync is already running, the device login starts there and a notification shows the code"
+    );
+}
+
+/// Starts a login another SaveSync process was asked for, showing the code in a notification
+/// The window only shows it on the plugin page, which may not be open
+pub fn forwarded_device_login(filename: &OsStr) {
+    if let Some(code) = start_device_login(filename) {
+        desktop_notify(DesktopNotification {
+            title: Some(format!("Log in to {}", filename.to_string_lossy())),
+            body: Some(format!(
+                "Open {} and enter the code {}",
+                code.verification_uri, code.user_code
+            )),
+            silent: false,
+        });
+    }
+}
+
+/// Asks for the vault passphrase, the new credentials cannot be saved while it is locked
+fn unlock_from_stdin() -> bool {
+    println!("Saved credentials are encrypted, enter the passphrase:");
+    let mut passphrase = String::new();
+    let unlocked = io::stdin()
+        .read_line(&mut passphrase)
+        .map_err(|e| e.to_string())
+        .and_then(|_| vault::unlock(passphrase.trim_end_matches(['\r', '\n'])));
+    unlocked.inspect_err(|e| eprintln!("{e}")).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_device_login_argument() {
+        assert_eq!(
+            device_login_arg(["savesync", "--device-login", "plugin.so"]),
+            Some("plugin.so".into())
+        );
+        assert_eq!(device_login_arg(["savesync", "--device-login"]), None);
+        assert_eq!(device_login_arg(["savesync", "plugin.so"]), None);
+    }
+}
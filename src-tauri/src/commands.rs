@@ -5,22 +5,39 @@ use std::io;
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tauri::command;
 
-use crate::listeners::collect_filter_from_cloud;
+use crate::listeners::{self, collect_filter_from_cloud};
 use crate::savesync::watch::{drop_watchers, watched_folders};
 use crate::savesync::{
-    config_paths,
-    plugin::{Plugin, PluginInfo},
+    backend::{DeviceCode, PluginInfo, load_backend},
+    builtin::{
+        builtin_infos, local_folder,
+        s3::{self, S3Login},
+        webdav::{self, WebDavLogin},
+    },
+    config_paths, emitter,
+    manifest::{manifest_path, read_manifest, signature_path, verify},
     store::PathMapping,
+    vault::{self, VaultState},
 };
-use crate::{app_store, read_app_state};
+use crate::{app_store, loaded_plugin, read_app_state, write_app_state};
 
 #[command]
 pub fn get_plugins() -> Vec<PluginInfo> {
-    config_paths::get_pluginfiles()
+    builtin_infos()
         .into_iter()
-        .filter_map(|path| unsafe { Plugin::new(&path) }.and_then(|x| x.info()))
+        .chain(
+            config_paths::get_pluginfiles()
+                .into_iter()
+                .filter_map(|filename| {
+                    read_manifest(&config_paths::plugin().join(&filename))
+                        .inspect_err(|e| emitter::plugin_error(&filename, e))
+                        .ok()
+                        .map(|manifest| manifest.info(&filename))
+                }),
+        )
         .collect()
 }
 
@@ -79,6 +96,70 @@ pub fn set_mapping(map: PathMapping) {
     collect_filter_from_cloud(move |key| keys.contains(key));
 }
 
+/// Syncs every tag against a fresh cloud listing
+#[command]
+pub fn refresh_cloud() {
+    if let Some(plugin) = loaded_plugin() {
+        plugin.invalidate_listing();
+    }
+    collect_filter_from_cloud(|_| true);
+}
+
+#[derive(Serialize)]
+pub struct PluginSettings {
+    schema: Value,
+    values: Value,
+}
+
+/// Settings schema of the plugin and the values saved for it, None if it has no settings
+/// Plugins other than the loaded one are loaded just to read their schema
+#[command]
+pub fn get_plugin_settings(filepath: OsString) -> Option<PluginSettings> {
+    let loaded = app_store()
+        .plugin()
+        .is_some_and(|plugin| plugin == filepath);
+    let schema = match loaded_plugin()
+        .filter(|_| loaded)
+        .map(|plugin| plugin.settings_schema())
+    {
+        Some(schema) => schema,
+        None => load_backend(&filepath)?.settings_schema(),
+    }?;
+
+    Some(PluginSettings {
+        schema,
+        values: app_store().plugin_settings(&filepath),
+    })
+}
+
+/// Saves the settings, authenticating the loaded plugin again so it picks them up
+/// A fresh copy is authenticated and swapped in, running calls share the loaded one
+/// Returns false if the plugin rejected them
+#[command]
+pub fn set_plugin_settings(filepath: OsString, values: Value) -> bool {
+    app_store().set_plugin_settings(&filepath, values.clone());
+
+    let loaded = app_store()
+        .plugin()
+        .is_some_and(|plugin| plugin == filepath);
+    if !loaded || loaded_plugin().is_none() {
+        return true;
+    }
+
+    let Some(mut plugin) = load_backend(&filepath) else {
+        return false;
+    };
+    if !plugin.authenticate(&values) {
+        return false;
+    }
+    write_app_state(|s| {
+        if s.plugin.is_some() {
+            s.plugin = Some(plugin.into());
+        }
+    });
+    true
+}
+
 #[command]
 pub fn get_watched_folders() -> Vec<(String, OsString)> {
     watched_folders()
@@ -87,19 +168,78 @@ pub fn get_watched_folders() -> Vec<(String, OsString)> {
 #[command]
 pub fn add_plugin(filepath: OsString) {
     let path = PathBuf::from(filepath);
-    fs::copy(
-        &path,
-        config_paths::plugin().join(path.file_name().unwrap()),
-    )
-    .unwrap();
+    let filename = path.file_name().unwrap();
+    if let Err(e) = verify(&path) {
+        emitter::plugin_error(filename, &e);
+        return;
+    }
+
+    let dest = config_paths::plugin().join(filename);
+    [
+        (manifest_path(&path), manifest_path(&dest)),
+        (signature_path(&path), signature_path(&dest)),
+        (path, dest),
+    ]
+    .into_iter()
+    .for_each(|(from, to)| {
+        fs::copy(from, to).unwrap();
+    });
+}
+
+/// Trusts plugins signed with the hex encoded ed25519 `key`
+#[command]
+pub fn add_trusted_key(key: String) {
+    app_store().add_trusted_key(key.trim());
+}
+
+#[command]
+pub fn set_local_folder(path: String) {
+    let _ = local_folder::set_root(&path);
+}
+
+#[command]
+pub fn set_webdav_login(login: WebDavLogin) {
+    let _ = webdav::set_login(&login);
+}
+
+#[command]
+pub fn set_s3_login(login: S3Login) {
+    let _ = s3::set_login(&login);
 }
 
 #[command]
 pub fn logged_in(filepath: OsString) -> bool {
-    fs::exists(config_paths::create_credential_path(filepath)).unwrap_or_default()
+    vault::exists(&filepath)
 }
 
 #[command]
 pub fn logout(filepath: OsString) {
-    let _ = fs::remove_file(config_paths::create_credential_path(filepath));
+    let _ = vault::remove(&filepath);
+}
+
+/// Logs in without a browser on this machine, the returned code is entered on another device
+#[command]
+pub fn start_device_login(filepath: OsString) -> Option<DeviceCode> {
+    listeners::start_device_login(&filepath)
+}
+
+#[command]
+pub fn vault_state() -> VaultState {
+    vault::state()
+}
+
+#[command]
+pub fn unlock_vault(passphrase: String) -> Result<(), String> {
+    vault::unlock(&passphrase)
+}
+
+/// Encrypts the saved credentials, the passphrase is asked for on every start from then on
+#[command]
+pub fn enable_vault(passphrase: String) -> Result<(), String> {
+    vault::enable(&passphrase)
+}
+
+#[command]
+pub fn disable_vault() -> Result<(), String> {
+    vault::disable()
 }
@@ -0,0 +1,196 @@
+use std::{
+    ffi::{OsStr, OsString},
+    thread,
+};
+
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use tauri::Manager;
+
+use crate::{
+    app_handle, app_store,
+    listeners::{collect_filter_from_cloud, finish_login},
+    loaded_plugin, read_app_state,
+    savesync::{conflict_files::resolve_conflict, watch::cloud_name},
+};
+
+/// Scheme registered under plugins.deep-link in tauri.conf.json
+const SCHEME: &str = "savesync";
+
+/// Redirect of browser logins when chosen over the redirect server, or no port of it is free
+pub const AUTH_REDIRECT: &str = "savesync://auth";
+
+/// What a savesync:// link asks for
+enum Action {
+    /// savesync://auth?..., the redirect of a browser login
+    Auth(String),
+    /// savesync://sync/<tag>
+    Sync(String),
+    /// savesync://resolve/<tag>/<folder>?keep=local|cloud
+    Resolve {
+        tag: String,
+        folder: OsString,
+        keep: String,
+    },
+}
+
+impl Action {
+    fn parse(url: &Url) -> Option<Action> {
+        if url.scheme() != SCHEME {
+            return None;
+        }
+
+        // some platforms hand links over with a trailing slash
+        let segments: Vec<String> = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect();
+
+        match (url.host_str()?, segments.as_slice()) {
+            ("auth", []) => Some(Action::Auth(url.to_string())),
+            ("sync", [tag]) => Some(Action::Sync(tag.clone())),
+            ("resolve", [tag, folder]) => {
+                let keep = url
+                    .query_pairs()
+                    .find(|(key, _)| key == "keep")
+                    .map(|(_, keep)| keep.into_owned())
+                    .filter(|keep| keep == "local" || keep == "cloud")?;
+                Some(Action::Resolve {
+                    tag: tag.clone(),
+                    folder: folder.into(),
+                    keep,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn run(self) {
+        match self {
+            Action::Auth(url) => finish_login(url),
+            Action::Sync(tag) | Action::Resolve { tag, .. } if !is_mapped(&tag) => {
+                println!("Ignored a link for {tag}, which is not mapped to a folder")
+            }
+            Action::Sync(tag) => sync(tag),
+            Action::Resolve { tag, folder, keep } => resolve(tag, folder, keep),
+        }
+    }
+}
+
+/// Carries out the savesync:// links SaveSync was opened with, each on its own thread
+pub fn open(urls: impl IntoIterator<Item = Url>) {
+    for url in urls {
+        match Action::parse(&url) {
+            Some(action) => {
+                thread::spawn(move || action.run());
+            }
+            None => println!("Ignored the unknown link {url}"),
+        }
+    }
+}
+
+/// The savesync:// links among the arguments of another SaveSync process
+pub fn links_in<S: AsRef<str>>(args: &[S]) -> Vec<Url> {
+    args.iter()
+        .filter_map(|arg| Url::parse(arg.as_ref()).ok())
+        .filter(|url| url.scheme() == SCHEME)
+        .collect()
+}
+
+/// Links name tags themselves, which may not be mapped on this machine
+fn is_mapped(tag: &str) -> bool {
+    app_store().get_mapping(tag).is_some()
+}
+
+/// Syncs the folders of `tag` against a fresh cloud listing
+fn sync(tag: String) {
+    let loaded = loaded_plugin()
+        .inspect(|plugin| plugin.invalidate_listing())
+        .is_some();
+    if !loaded {
+        println!("No plugin is loaded to sync {tag} with");
+        return;
+    }
+
+    collect_filter_from_cloud(move |key| *key == tag);
+}
+
+/// Answers the conflict on `folder`, named as in the cloud or as the local folder
+fn resolve(tag: String, folder: OsString, keep: String) {
+    let waiting =
+        |item: &OsString| read_app_state(|s| s.buffers.contains_key(&(tag.clone(), item.clone())));
+    let item = [folder.clone(), cloud_name(&tag, &folder).into_os_string()]
+        .into_iter()
+        .find(waiting);
+
+    match item {
+        Some(item) => {
+            close_conflict_window(&tag, &item);
+            resolve_conflict((tag, item, keep));
+        }
+        None => println!(
+            "{} in {tag} has no conflict waiting",
+            folder.to_string_lossy()
+        ),
+    }
+}
+
+/// Closes the window asking about the same conflict, the link answered it
+fn close_conflict_window(tag: &str, item: &OsStr) {
+    let item = item.to_string_lossy();
+    if let Some(window) = app_handle().get_webview_window("conflicting")
+        && window.url().is_ok_and(|url| {
+            let param = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            param("tag").as_deref() == Some(tag) && param("folder").as_deref() == Some(&*item)
+        })
+    {
+        let _ = window.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Option<Action> {
+        Action::parse(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn parses_known_links() {
+        assert!(matches!(
+            parse("savesync://auth?code=c&state=s"),
+            Some(Action::Auth(url)) if url == "savesync://auth?code=c&state=s"
+        ));
+        assert!(matches!(
+            parse("savesync://sync/My%20Game/"),
+            Some(Action::Sync(tag)) if tag == "My Game"
+        ));
+        assert!(matches!(
+            parse("savesync://resolve/tag/slot1?keep=cloud"),
+            Some(Action::Resolve { tag, folder, keep })
+                if tag == "tag" && folder == "slot1" && keep == "cloud"
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_links() {
+        for url in [
+            "https://sync/tag",
+            "savesync://sync",
+            "savesync://sync/tag/extra",
+            "savesync://resolve/tag/slot1",
+            "savesync://resolve/tag/slot1?keep=both",
+            "savesync://delete/tag",
+        ] {
+            assert!(parse(url).is_none(), "{url}");
+        }
+    }
+}
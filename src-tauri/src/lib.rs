@@ -1,19 +1,25 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod cli;
 mod commands;
+mod deep_link;
 mod listeners;
 mod savesync;
 
 use commands::{
-    add_plugin, filetree, get_mapping, get_plugins, get_watched_folders, logged_in, logout,
-    set_mapping,
+    add_plugin, add_trusted_key, disable_vault, enable_vault, filetree, get_mapping,
+    get_plugin_settings, get_plugins, get_watched_folders, logged_in, logout, refresh_cloud,
+    set_local_folder, set_mapping, set_plugin_settings, set_s3_login, set_webdav_login,
+    start_device_login, unlock_vault, vault_state,
 };
 use listeners::emit_listeners;
-use savesync::{plugin::Plugin, store::AppStore};
+use savesync::{backend::CloudBackend, oauth::PendingLogin, store::AppStore};
 use std::{
     collections::{HashMap, HashSet},
     ffi::OsString,
     ops::Deref,
+    path::PathBuf,
     sync::{Arc, OnceLock, RwLock},
+    time::SystemTime,
 };
 use tauri::{
     AppHandle, Manager, RunEvent, WindowEvent,
@@ -29,19 +35,12 @@ static APP_STORE: OnceLock<Arc<AppStore>> = OnceLock::new();
 
 pub struct AppState {
     pub tags: HashSet<String>,
-    pub buffers: HashMap<(String, OsString), Vec<u8>>,
-    pub plugin: Option<Plugin>,
-    pub server_port: Option<u16>,
-}
-
-impl AppState {
-    pub fn plugin_mut_ref(&mut self) -> &mut Plugin {
-        self.plugin.as_mut().unwrap()
-    }
-
-    pub fn plugin_ref(&self) -> &Plugin {
-        self.plugin.as_ref().unwrap()
-    }
+    /// Staged downloads waiting on a conflict resolution, with the cloud copy's last_modified
+    pub buffers: HashMap<(String, OsString), (PathBuf, SystemTime)>,
+    /// Shared with running calls, see loaded_plugin
+    pub plugin: Option<Arc<dyn CloudBackend>>,
+    /// Browser login whose redirect the local server is waiting for
+    pub login: Option<PendingLogin>,
 }
 
 impl Default for AppState {
@@ -50,20 +49,24 @@ impl Default for AppState {
             tags: HashSet::new(),
             buffers: HashMap::new(),
             plugin: None,
-            server_port: None,
+            login: None,
         }
     }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    if let Some(filename) = savesync::plugin_host::requested_plugin() {
+        return savesync::plugin_host::serve(&filename);
+    }
+    let device_login = cli::requested_device_login();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_oauth::init())
         .invoke_handler(tauri::generate_handler![
             get_plugins,
@@ -73,7 +76,19 @@ pub fn run() {
             filetree,
             add_plugin,
             logout,
-            logged_in
+            logged_in,
+            set_local_folder,
+            set_webdav_login,
+            set_s3_login,
+            add_trusted_key,
+            refresh_cloud,
+            get_plugin_settings,
+            set_plugin_settings,
+            vault_state,
+            unlock_vault,
+            enable_vault,
+            disable_vault,
+            start_device_login
         ])
         .on_menu_event(|app, event| match event.id.as_ref() {
             "show_window" => {
@@ -106,8 +121,17 @@ pub fn run() {
 
             #[cfg(desktop)]
             {
+                if device_login.is_some() {
+                    cli::announce_device_login();
+                }
                 app.handle()
-                    .plugin(tauri_plugin_single_instance::init(|app, _, _| {
+                    .plugin(tauri_plugin_single_instance::init(|app, args, _| {
+                        // the login runs in this instance, the other process cannot show the code
+                        if let Some(filename) = cli::device_login_arg(&args) {
+                            cli::forwarded_device_login(&filename);
+                        }
+                        // links the other process was opened with are carried out here
+                        deep_link::open(deep_link::links_in(&args));
                         app.get_webview_window("main")
                             .expect("No main window found")
                             .set_focus()
@@ -119,13 +143,24 @@ pub fn run() {
                     None,
                 ))?;
 
-                app.deep_link().on_open_url(|e| {
-                    println!("Urls: {:?}", e.urls());
-                });
+                // installed builds register the scheme through their bundle
+                #[cfg(any(windows, target_os = "linux"))]
+                app.deep_link()
+                    .register_all()
+                    .inspect_err(|e| println!("Unable to register savesync:// links: {e}"))
+                    .ok();
+
+                app.deep_link().on_open_url(|e| deep_link::open(e.urls()));
             }
 
             let _ = APP_STORE.set(Arc::new(AppStore::new(app)));
             let _ = APP_INSTANCE.set(app.app_handle().to_owned());
+            if let Some(filename) = device_login {
+                cli::device_login(filename);
+            }
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                deep_link::open(urls);
+            }
             Ok(())
         })
         .manage(RwLock::new(AppState::default()))
@@ -192,3 +227,9 @@ where
             .deref(),
     )
 }
+
+/// Shares the loaded plugin, so its calls are made without holding the app state lock
+/// A plugin call may hang until the watchdog stops it, which must not block other state users
+pub fn loaded_plugin() -> Option<Arc<dyn CloudBackend>> {
+    read_app_state(|s| s.plugin.clone())
+}
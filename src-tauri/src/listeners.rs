@@ -1,27 +1,39 @@
 use crate::{
-    app_store, read_app_state,
+    app_store,
+    deep_link::AUTH_REDIRECT,
+    loaded_plugin, read_app_state,
     savesync::{
+        backend::{
+            Capabilities, CloudBackend, DeviceCode, FileDetails, load_backend,
+            wait_for_device_login,
+        },
+        builtin::is_builtin,
+        cancellation::{self, CancelToken, Transfer},
         config_paths,
         conflict_files::{resolve_conflict, store_buffer},
         emitter,
-        fs_utils::recurse_directories,
-        plugin::{FileDetails, Plugin},
+        fs_utils::last_modified,
+        notifications::{DesktopNotification, desktop_notify},
+        oauth::PendingLogin,
+        progress::Tracked,
+        sync_state::{self, Changed, hash_file},
+        vault::{self, VaultState},
         watch::{
-            dump_watchers, handle_buffer, strip_zip_extension, toggle_watch, upload_file,
-            watch_folder,
+            cloud_name, dump_watchers, handle_buffer, strip_zip_extension, toggle_watch,
+            upload_file, watch_folder,
         },
+        zip_utils::stage_buffer,
     },
     write_app_state,
 };
 use serde::Deserialize;
 use serde_json::from_str;
 use std::{
-    cmp::{
-        Ordering::{Equal, Greater, Less},
-        max,
-    },
+    cell::OnceCell,
     ffi::{OsStr, OsString},
-    ops::RangeInclusive,
+    fs::{self, File},
+    path::PathBuf,
+    sync::Arc,
     thread,
     time::SystemTime,
 };
@@ -32,7 +44,7 @@ use tauri_plugin_opener::open_url;
 pub fn emit_listeners(app: &tauri::App) {
     let arr: Vec<(&str, fn(Event))> = vec![
         ("init", init_listener),
-        // ("abort", abort_listener),
+        ("abort", abort_listener),
         ("sync", sync_listener),
         ("unload", unload_listener),
         ("saved_plugin", saved_plugin_listener),
@@ -50,54 +62,194 @@ fn init_listener(event: Event) {
 
 // async to prevent UI thread from freezing
 pub fn init_func(path: &OsStr) {
-    if let Some(mut plugin) = unsafe { Plugin::new(path) } {
+    if let Some(mut plugin) = load_backend(path) {
         app_store().set_plugin(path);
 
-        if plugin.authenticate() {
-            write_app_state(move |s| s.plugin = Some(plugin));
+        if plugin.authenticate(&app_store().plugin_settings(path)) {
+            write_app_state(move |s| s.plugin = Some(plugin.into()));
+            init_download_folders();
+        } else if let Some((login, url)) = begin_login(plugin.as_ref()) {
+            // set before the browser opens, the redirect is checked against it
+            write_app_state(|s| {
+                s.plugin = Some(plugin.into());
+                s.login = Some(login);
+            });
+            let _ = open_url(url, None::<&str>);
+        };
+    }
+}
+
+/// Starts a login the user finishes by entering the returned code on another device
+/// Polled on its own thread, the backend becomes the plugin once the login went through
+pub fn start_device_login(path: &OsStr) -> Option<DeviceCode> {
+    let mut plugin = load_backend(path)?;
+    if !plugin.supports(Capabilities::DEVICE_LOGIN) {
+        emitter::plugin_error(path, "This plugin cannot log in with a device code");
+        return None;
+    }
+    if !credentials_writable(path) {
+        return None;
+    }
+    let code = plugin.device_login()?;
+    emitter::device_code(path, &code);
+
+    let path = path.to_owned();
+    let polled = code.clone();
+    thread::spawn(move || {
+        if wait_for_device_login(plugin.as_mut(), &polled)
+            && plugin.authenticate(&app_store().plugin_settings(&path))
+        {
+            app_store().set_plugin(&path);
+            write_app_state(move |s| s.plugin = Some(plugin.into()));
             init_download_folders();
         } else {
-            let port = start_server();
-            if let Some(url) = plugin.auth_url(&format!("http://localhost:{port}")) {
-                let _ = open_url(url, None::<&str>);
-                write_app_state(|s| {
-                    s.plugin = Some(plugin);
-                    s.server_port = Some(port);
-                });
-            }
+            emitter::plugin_error(&path, "The device login was denied or expired");
+        }
+    });
+    Some(code)
+}
+
+/// Sends the user through the browser login again after the backend rejected its credentials,
+/// or could not refresh them, telling them why a browser window opened
+/// Runs on its own thread, errors are usually reported while the app state is locked
+pub fn reauthenticate() {
+    thread::spawn(|| {
+        // None while a login is already waiting for its redirect
+        let plugin = read_app_state(|s| s.login.is_none().then(|| s.plugin.clone()).flatten());
+        // built without the lock held, the plugin may take a while to answer
+        let url = plugin
+            .and_then(|plugin| begin_login(plugin.as_ref()))
+            .and_then(|(login, url)| {
+                write_app_state(|s| match s.login {
+                    // another login started meanwhile and keeps the redirect
+                    Some(_) => {
+                        if let Some(port) = login.port {
+                            let _ = tauri_plugin_oauth::cancel(port);
+                        }
+                        None
+                    }
+                    None => {
+                        s.login = Some(login);
+                        Some(url)
+                    }
+                })
+            });
+
+        let body = match url {
+            Some(_) => "Please log in again in the browser window SaveSync opened",
+            None if read_app_state(|s| s.login.is_some()) => return,
+            // backends without a browser login are logged in again from the plugin list
+            None => "Please log in again from the plugin list in SaveSync",
         };
+        desktop_notify(DesktopNotification {
+            title: Some("Your cloud login expired".into()),
+            body: Some(body.into()),
+            silent: false,
+        });
+        if let Some(url) = url {
+            let _ = open_url(url, None::<&str>);
+        }
+    });
+}
+
+/// Starts the redirect server and builds the login URL of `plugin` with a fresh state
+/// and PKCE pair, None if the backend has no browser login
+/// Redirects to savesync://auth instead when chosen in the settings or no port is free
+fn begin_login(plugin: &dyn CloudBackend) -> Option<(PendingLogin, String)> {
+    if !credentials_writable(&app_store().plugin().unwrap_or_default()) {
+        return None;
+    }
+
+    let port = match app_store().oauth_deep_link() {
+        true => None,
+        false => start_server(),
+    };
+    let redirect_uri = port.map_or(AUTH_REDIRECT.into(), |port| {
+        format!("http://localhost:{port}")
+    });
+    let login = PendingLogin::new(port);
+    let url = plugin
+        .auth_url(&redirect_uri, login.state(), &login.challenge())
+        .and_then(|url| {
+            login
+                .bind(url, plugin.sets_state())
+                .inspect_err(|e| {
+                    emitter::plugin_error(&app_store().plugin().unwrap_or_default(), e)
+                })
+                .ok()
+        });
+
+    match url {
+        Some(url) => Some((login, url)),
+        None => {
+            if let Some(port) = port {
+                let _ = tauri_plugin_oauth::cancel(port);
+            }
+            None
+        }
     }
 }
 
-const PORTS: RangeInclusive<u16> = 5000..=5009;
+/// A login is lost while the vault is locked, so the user is asked to unlock it first
+fn credentials_writable(path: &OsStr) -> bool {
+    let locked = vault::state() == VaultState::Locked;
+    if locked {
+        emitter::plugin_error(
+            path,
+            "Unlock the saved credentials in Settings before logging in",
+        );
+    }
+    !locked
+}
 
-pub fn start_server() -> u16 {
+fn start_server() -> Option<u16> {
     tauri_plugin_oauth::start_with_config(
         OauthConfig {
             redirect_uri: None,
-            ports: Some(PORTS.collect()),
+            ports: Some(app_store().oauth_ports()),
             response: None,
         },
-        move |url| {
-            let result = write_app_state(|s| {
-                let mut plugin = s.plugin.take().unwrap();
-                let result = plugin.process_save_credentials(&url);
-                if result {
-                    s.plugin = Some(plugin);
-                }
-                result
-            });
-            stop_server();
-            if result {
-                init_download_folders();
-            }
-        },
+        finish_login,
     )
-    .unwrap()
+    .inspect_err(|e| println!("Unable to start the login redirect server: {e}"))
+    .ok()
 }
 
-fn stop_server() {
-    write_app_state(|s| tauri_plugin_oauth::cancel(s.server_port.take().unwrap()).unwrap());
+/// Saves the credentials from the redirect `url` of the running browser login,
+/// which arrives through the redirect server or a savesync://auth link
+pub fn finish_login(url: String) {
+    // anything may connect to the port or open a link, only the redirect of the running login is let through
+    // taken in one go, so concurrent redirects cannot both finish the login
+    let taken = write_app_state(|s| {
+        let login = s.login.take_if(|login| login.accepts(&url))?;
+        Some((login, s.plugin.take()))
+    });
+    let Some((login, plugin)) = taken else {
+        println!("Ignored a login redirect without the expected state");
+        return;
+    };
+
+    if let Some(port) = login.port {
+        let _ = tauri_plugin_oauth::cancel(port);
+    }
+    let Some(mut plugin) = plugin else {
+        println!("Ignored a login redirect, no plugin is loaded");
+        return;
+    };
+
+    // calls still running share the plugin, the credentials then go to a freshly loaded one
+    if Arc::get_mut(&mut plugin).is_none() {
+        let Some(fresh) = load_backend(&app_store().plugin().unwrap_or_default()) else {
+            return;
+        };
+        plugin = fresh.into();
+    }
+    let saved = Arc::get_mut(&mut plugin)
+        .is_some_and(|plugin| plugin.process_save_credentials(&url, login.verifier()));
+    if saved {
+        write_app_state(|s| s.plugin = Some(plugin));
+        init_download_folders();
+    }
 }
 
 pub fn init_download_folders() {
@@ -109,29 +261,26 @@ pub fn collect_filter_from_cloud<F>(lambda: F) -> thread::JoinHandle<()>
 where
     F: Fn(&String) -> bool + Send + 'static,
 {
-    let last_sync = app_store().last_sync();
-
     thread::spawn(move || {
-        if let Some(details) = write_app_state(|s| {
-            let plugin = s.plugin_mut_ref();
-            plugin.read_cloud().inspect(|details| {
+        // listed without the state lock, a hung plugin call must not block its other users
+        if let Some(details) = loaded_plugin().and_then(|plugin| plugin.read_cloud()) {
+            write_app_state(|s| {
                 if s.tags.is_empty() {
                     s.tags = details.iter().map(|f| f.tag.clone()).collect();
                 }
-            })
-        }) {
+            });
+
             details
                 .into_iter()
                 .filter(|FileDetails { tag, .. }| lambda(tag))
                 .for_each(|f| {
-                    let last_sync = last_sync.clone();
                     thread::Builder::new()
                         .name(format!(
                             "tag: {}, folder: {}",
                             f.tag,
                             f.folder_name.to_string_lossy()
                         ))
-                        .spawn(move || process_cloud_details(f, last_sync))
+                        .spawn(move || process_cloud_details(f))
                         .unwrap();
                 });
         }
@@ -145,94 +294,126 @@ fn process_cloud_details(
         last_modified: cloud_date,
         data,
     }: FileDetails,
-    last_sync: SystemTime,
 ) {
     if let Some(path) = app_store().get_mapping(&tag) {
         let fileinfo = strip_zip_extension(&item);
-        let data = data.or_else(|| {
-            read_app_state(|s| {
-                s.plugin_ref()
-                    .download(tag.as_bytes(), item.as_encoded_bytes())
-            })
-        });
+        let local_path = path.join(fileinfo.value());
+        // registered once a download starts, uploads register their own
+        let transfer = OnceCell::new();
+        let fetch = || {
+            let transfer = transfer.get_or_init(|| Transfer::begin(&tag, &item));
+            stage_download(&tag, &item, data, transfer.token())
+        };
+
+        let local_date = last_modified(&local_path);
+        let record = sync_state::get(&tag, &item);
+        let changed = sync_state::changed(
+            record.as_ref(),
+            app_store().last_sync(),
+            local_date,
+            cloud_date,
+        );
 
-        let local_date = recurse_directories(
-            &path.join(fileinfo.value()),
-            SystemTime::UNIX_EPOCH,
-            &mut |_, _, e| e.metadata()?.modified(),
-            &max,
-        )
-        .unwrap_or(SystemTime::UNIX_EPOCH);
-
-        // 6 permutations
-        // local < syncd < cloud (Download)
-        // cloud < syncd < local (Upload)
-        // syncd < local < cloud (Conflict)
-        // syncd < cloud < local (Conflict)
-        //
-        // cloud < local < syncd (Ignore)
-        // local < cloud < syncd (Ignore)
-
-        match (last_sync.cmp(&local_date), last_sync.cmp(&cloud_date)) {
-            (k, Less) => {
-                println!("Less branch");
-                if let Some(buf) = data {
-                    match k {
-                        Less => {
-                            println!("Both less");
-                            store_buffer(&tag, &item, buf);
+        match changed {
+            Changed::Cloud | Changed::Both => {
+                println!("Cloud changed");
+                if let Some(buf) = fetch() {
+                    let hash = hash_file(&buf);
+                    // the cloud copy was only touched again, its content is what was last synced
+                    let unchanged = sync_state::same_content(record.as_ref(), &hash);
+                    match (changed, unchanged) {
+                        (Changed::Both, true) => {
+                            println!("Cloud unchanged, uploading");
+                            let _ = fs::remove_file(buf);
+                            upload_file(&tag, &fileinfo);
+                        }
+                        (Changed::Both, false) => {
+                            println!("Both changed");
+                            store_buffer(&tag, &item, buf, cloud_date);
                             emitter::conflicting_files(&tag, &item, (local_date, cloud_date));
                             return;
                         }
-                        _ => {
+                        (_, true) => {
+                            let _ = fs::remove_file(buf);
+                            sync_state::record(&tag, &item, local_date, cloud_date, hash);
+                        }
+                        (_, false) => {
                             println!("Extracting");
                             handle_buffer(&path, &fileinfo, buf);
+                            sync_state::record(
+                                &tag,
+                                &item,
+                                last_modified(&local_path),
+                                cloud_date,
+                                hash,
+                            );
                         }
                     }
                 }
             }
-            (Less, Equal | Greater) => {
-                println!("Less with equal or greater");
+            Changed::Local => {
+                println!("Local changed, uploading");
                 upload_file(&tag, &fileinfo)
             }
-            (i, j) => {
-                println!("{i:?}, {j:?}");
+            Changed::Neither => {
+                println!("{changed:?}");
                 if local_date == SystemTime::UNIX_EPOCH
-                    && let Some(buf) = data
+                    && let Some(buf) = fetch()
                 {
+                    let hash = hash_file(&buf);
                     handle_buffer(&path, &fileinfo, buf);
+                    sync_state::record(&tag, &item, last_modified(&local_path), cloud_date, hash);
                 }
             }
         }
 
         let val = fileinfo.value();
+        // the cancelled download left the local copy behind, watching it would upload over the cloud
+        if transfer.get().is_some_and(Transfer::is_cancelled) {
+            emitter::sync_result(&tag, val.as_os_str(), false);
+            return;
+        }
         watch_folder(&tag, &val);
         emitter::sync_result(&tag, &val.as_os_str(), true);
     }
 }
 
-/// Fails silently, plugin does not need to implement abort()
-/// If a message is returned, it is logged to the logs folder
-/*
-fn abort_listener(event: Event) {
-    let mut filename: OsString = from_str(event.payload()).unwrap();
-
-    if let Err(err) = Plugin::new(&filename).map_or(Ok(()), |plugin| plugin.abort()) {
-        emitter::abort_result(&err);
+/// Stages the inline data from read_cloud, or streams the object from the cloud
+/// None once `token` is cancelled, so nothing local is overwritten
+fn stage_download(
+    tag: &str,
+    item: &OsStr,
+    data: Option<Vec<u8>>,
+    token: &CancelToken,
+) -> Option<PathBuf> {
+    if token.is_cancelled() {
+        return None;
+    }
+    if let Some(data) = data {
+        return stage_buffer(&data).ok();
+    }
 
-        filename.push(".txt");
+    let plugin = loaded_plugin()?;
+    let path = config_paths::staging_file();
+    let mut file = File::create(&path).ok()?;
+    let mut writer = token.guard(&mut file);
+    // sizes are not listed, so streamed downloads report bytes without a total
+    let downloaded = match plugin.supports(Capabilities::STREAMING) {
+        true => plugin.download_stream(
+            tag.as_bytes(),
+            item.as_encoded_bytes(),
+            &mut Tracked::new(tag, item, 0, &mut writer),
+        ),
+        false => plugin.download_stream(tag.as_bytes(), item.as_encoded_bytes(), &mut writer),
+    };
 
-        if let Ok(mut file) = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(true)
-            .open(config_paths::logs().join(filename))
-        {
-            let _ = file.write_all(&err.as_bytes());
-        }
+    if downloaded && !token.is_cancelled() {
+        Some(path)
+    } else {
+        let _ = fs::remove_file(path);
+        None
     }
 }
-*/
 
 #[derive(Deserialize)]
 struct SyncStruct {
@@ -240,6 +421,24 @@ struct SyncStruct {
     foldername: OsString,
 }
 
+/// Stops the upload or download of a folder, the transfer fails at its next chunk
+/// Backends that can also stop a call stuck waiting on the service are asked to
+fn abort_listener(event: Event) {
+    let SyncStruct { tag, foldername } = from_str(event.payload()).unwrap();
+
+    thread::spawn(move || {
+        let item = cloud_name(&tag, &foldername);
+        if !cancellation::cancel(&tag, item.as_os_str()) {
+            emitter::abort_result(&format!("{} is not syncing", foldername.to_string_lossy()));
+            return;
+        }
+
+        if let Some(plugin) = loaded_plugin() {
+            plugin.abort(tag.as_bytes(), item.as_os_str().as_encoded_bytes());
+        }
+    });
+}
+
 fn sync_listener(event: Event) {
     let SyncStruct { tag, foldername } = from_str(event.payload()).unwrap();
 
@@ -258,10 +457,15 @@ fn unload_listener(_: Event) {
     dump_watchers();
 }
 
+/// Waits for unlock_vault while the credentials are locked, the frontend emits it again after
 fn saved_plugin_listener(_: Event) {
+    if vault::state() == VaultState::Locked {
+        return;
+    }
+
     app_store()
         .plugin()
-        .filter(|p| !p.is_empty() && config_paths::plugin().join(p).exists())
+        .filter(|p| !p.is_empty() && (is_builtin(p) || config_paths::plugin().join(p).exists()))
         .map(|p| init_func(&p));
 }
 
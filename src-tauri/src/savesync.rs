@@ -1,9 +1,20 @@
+pub mod backend;
+pub mod builtin;
+pub mod cancellation;
 pub mod config_paths;
 pub mod conflict_files;
 pub mod emitter;
 pub mod fs_utils;
+pub mod manifest;
 pub mod notifications;
+pub mod oauth;
 pub mod plugin;
+pub mod plugin_host;
+pub mod plugin_log;
+pub mod progress;
 pub mod store;
+pub mod sync_state;
+pub mod vault;
 pub mod watch;
+pub mod watchdog;
 pub mod zip_utils;
@@ -0,0 +1,377 @@
+use savesync_plugin_abi::{capabilities, error_kind};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    ffi::{OsStr, OsString},
+    io::{Read, Write},
+    ops::BitOr,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{app_store, listeners::reauthenticate};
+
+use super::{
+    builtin::load_builtin,
+    emitter, manifest,
+    plugin::{InProcess, Plugin},
+    plugin_host::{self, PluginProcess},
+};
+
+/// Rate limited calls are retried this many times before giving up
+const MAX_RETRIES: u32 = 5;
+/// Wait before the first retry, doubled after every attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Device logins are polled this often when the backend names no interval, as in RFC 8628
+const DEFAULT_DEVICE_POLL: Duration = Duration::from_secs(5);
+
+/// Optional features a backend advertises
+/// Dynamic plugins report these through their `capabilities` export
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    /// Backend implements `remove`
+    pub const REMOVE: Capabilities = Capabilities(capabilities::REMOVE);
+    /// `read_cloud` may attach file data to its details
+    pub const INLINE_DATA: Capabilities = Capabilities(capabilities::INLINE_DATA);
+    /// Backend implements `abort`
+    pub const ABORT: Capabilities = Capabilities(capabilities::ABORT);
+    /// Backend transfers archives in chunks instead of whole buffers
+    pub const STREAMING: Capabilities = Capabilities(capabilities::STREAMING);
+    /// Backend reports the progress of transfers it gets whole, streamed ones are tracked by the host
+    pub const PROGRESS: Capabilities = Capabilities(capabilities::PROGRESS);
+    /// Backend lists the files changed since a cursor instead of the whole cloud
+    pub const CHANGES: Capabilities = Capabilities(capabilities::CHANGES);
+    /// Backend writes its own records to its log file
+    pub const LOGGING: Capabilities = Capabilities(capabilities::LOGGING);
+    /// Backend declares settings through a JSON Schema
+    pub const SETTINGS: Capabilities = Capabilities(capabilities::SETTINGS);
+    /// Backend logs in with a code entered on another device, without a local browser
+    pub const DEVICE_LOGIN: Capabilities = Capabilities(capabilities::DEVICE_LOGIN);
+    /// Backend refreshes credentials before they expire and once after they were rejected
+    pub const REFRESH: Capabilities = Capabilities(capabilities::REFRESH);
+
+    pub fn from_bits(bits: u64) -> Capabilities {
+        Capabilities(bits)
+    }
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn without(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+/// Kind of failure a backend reports alongside its message
+/// Dynamic plugins return these codes through their `error_kind` export
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum ErrorKind {
+    Other = error_kind::OTHER,
+    /// Credentials are missing, expired or revoked
+    Auth = error_kind::AUTH,
+    /// Service asked to slow down
+    RateLimited = error_kind::RATE_LIMITED,
+    /// File does not exist in the cloud
+    NotFound = error_kind::NOT_FOUND,
+    /// Service could not be reached
+    Network = error_kind::NETWORK,
+}
+
+impl ErrorKind {
+    /// Unknown codes from newer plugins are treated as Other
+    pub fn from_code(code: u32) -> ErrorKind {
+        match code {
+            error_kind::AUTH => ErrorKind::Auth,
+            error_kind::RATE_LIMITED => ErrorKind::RateLimited,
+            error_kind::NOT_FOUND => ErrorKind::NotFound,
+            error_kind::NETWORK => ErrorKind::Network,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackendError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl BackendError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> BackendError {
+        BackendError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Gets file's last modified date
+/// Plugin developers can optionally attach the
+/// file buffer to reduce API calls where possible
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileDetails {
+    pub tag: String,
+    pub folder_name: OsString,
+    pub last_modified: SystemTime,
+    pub data: Option<Vec<u8>>,
+}
+
+/// Code the user enters at `verification_uri` on another device to log in
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub interval: Duration,
+    pub expires_in: Duration,
+}
+
+/// Where a device login stands after a poll
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DevicePoll {
+    Pending,
+    /// Service asked to be polled less often
+    SlowDown,
+    LoggedIn,
+    /// Login was denied or expired
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PluginInfo {
+    pub name: String,
+    pub description: String,
+    pub author: String,
+    pub icon_url: String,
+    pub filename: OsString,
+}
+
+/// Storage SaveSync syncs saves to
+/// Every sync path goes through this trait, the DLL plugin is one implementation
+pub trait CloudBackend: Send + Sync {
+    fn supports(&self, capability: Capabilities) -> bool;
+
+    fn info(&self) -> Option<PluginInfo>;
+
+    /// Returns true if the saved credentials are valid
+    /// `settings` holds the values saved for `settings_schema`
+    fn authenticate(&mut self, settings: &Value) -> bool;
+
+    /// JSON Schema of the backend's settings, None if it has none
+    fn settings_schema(&self) -> Option<Value> {
+        None
+    }
+
+    /// Whether auth_url puts the state it is given in the URL itself
+    /// Plugins built before ABI version 4 are not given one
+    fn sets_state(&self) -> bool {
+        true
+    }
+
+    /// URL the user is sent to for logging in, redirecting back to `redirect_uri`
+    /// `state` and the PKCE `code_challenge` belong in the authorization request
+    fn auth_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> Option<String>;
+
+    /// Extracts and saves credentials from the OAuth redirect URL, whose state was checked
+    fn process_save_credentials(&mut self, url: &str, code_verifier: &str) -> bool;
+
+    /// Starts a login finished on another device, None if the backend has none
+    fn device_login(&self) -> Option<DeviceCode> {
+        None
+    }
+
+    /// Checks on the login started by device_login, saving the credentials once it finished
+    fn poll_device_login(&mut self, _device_code: &str) -> DevicePoll {
+        DevicePoll::Failed
+    }
+
+    /// Stops the running call on the file, false if the backend cannot
+    /// Transfers are also stopped by failing their reader or writer, see cancellation
+    fn abort(&self, tag: &[u8], folder_name: &[u8]) -> bool;
+
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool;
+
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>>;
+
+    /// Uploads `len` bytes read from `reader`
+    /// Backends without Capabilities::STREAMING buffer the whole archive
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        reader: Box<dyn Read + Send>,
+        len: u64,
+    ) -> bool {
+        upload_buffered(self, tag, folder_name, date, reader, len)
+    }
+
+    /// Downloads the object into `writer`
+    /// Backends without Capabilities::STREAMING buffer the whole archive
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        download_buffered(self, tag, folder_name, writer)
+    }
+
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool;
+
+    fn read_cloud(&self) -> Option<Vec<FileDetails>>;
+
+    /// Drops any cached cloud listing, so the next read_cloud asks the service
+    fn invalidate_listing(&self) {}
+}
+
+pub fn upload_buffered<B>(
+    backend: &B,
+    tag: &[u8],
+    folder_name: &[u8],
+    date: SystemTime,
+    mut reader: Box<dyn Read + Send>,
+    len: u64,
+) -> bool
+where
+    B: CloudBackend + ?Sized,
+{
+    let mut buffer = Vec::with_capacity(len as usize);
+    reader.read_to_end(&mut buffer).is_ok() && backend.upload(tag, folder_name, date, &buffer)
+}
+
+pub fn download_buffered<B>(
+    backend: &B,
+    tag: &[u8],
+    folder_name: &[u8],
+    writer: &mut dyn Write,
+) -> bool
+where
+    B: CloudBackend + ?Sized,
+{
+    backend
+        .download(tag, folder_name)
+        .is_some_and(|buffer| writer.write_all(&buffer).is_ok())
+}
+
+/// Polls the device login until the user finished it, false once it failed or expired
+pub fn wait_for_device_login<B>(backend: &mut B, code: &DeviceCode) -> bool
+where
+    B: CloudBackend + ?Sized,
+{
+    let deadline = Instant::now() + code.expires_in;
+    let mut interval = match code.interval.is_zero() {
+        true => DEFAULT_DEVICE_POLL,
+        false => code.interval,
+    };
+
+    while Instant::now() < deadline {
+        thread::sleep(interval);
+        match backend.poll_device_login(&code.device_code) {
+            DevicePoll::Pending => {}
+            DevicePoll::SlowDown => interval += DEFAULT_DEVICE_POLL,
+            DevicePoll::LoggedIn => return true,
+            DevicePoll::Failed => return false,
+        }
+    }
+    false
+}
+
+/// Loads a backend by filename, out of process if plugin isolation is turned on
+/// Built-in backends are always loaded in process, plugins only once their manifest is verified
+pub fn load_backend(filename: &OsStr) -> Option<Box<dyn CloudBackend>> {
+    if let Some(backend) = load_builtin(filename) {
+        Some(backend)
+    } else if app_store().isolate_plugins() {
+        // verified before every start of the child, restarts included
+        PluginProcess::new(filename).map(|p| Box::new(p) as Box<dyn CloudBackend>)
+    } else if let Err(e) = manifest::verify_plugin(filename) {
+        emitter::plugin_error(filename, &e);
+        None
+    } else {
+        unsafe { Plugin::new(filename) }
+            .map(|p| Box::new(InProcess::new(p)) as Box<dyn CloudBackend>)
+    }
+}
+
+/// Runs `call` again with exponential backoff while the service rate limits it
+pub fn with_backoff<T>(
+    mut call: impl FnMut() -> Result<T, BackendError>,
+) -> Result<T, BackendError> {
+    let mut delay = INITIAL_BACKOFF;
+    let mut retries = 0;
+
+    loop {
+        match call() {
+            Err(e) if e.kind == ErrorKind::RateLimited && retries < MAX_RETRIES => {
+                thread::sleep(delay);
+                delay *= 2;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Shows the error and reacts to its kind
+/// Rejected credentials send the user through the login flow again
+pub fn report_error(filename: &OsStr, error: &BackendError) {
+    if plugin_host::is_child() {
+        plugin_host::forward_error(error);
+        return;
+    }
+
+    emitter::plugin_error(filename, &error.message);
+    if error.kind == ErrorKind::Auth {
+        reauthenticate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_capabilities() {
+        let both = Capabilities::REMOVE | Capabilities::INLINE_DATA;
+        assert!(both.contains(Capabilities::REMOVE));
+        assert!(both.contains(Capabilities::INLINE_DATA));
+        assert!(!both.contains(Capabilities::REMOVE | Capabilities::ABORT));
+    }
+
+    #[test]
+    fn retries_rate_limited_calls_only() {
+        let mut calls = 0;
+        let result = with_backoff(|| {
+            calls += 1;
+            match calls {
+                1 => Err(BackendError::new(ErrorKind::RateLimited, "slow down")),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        let mut calls = 0;
+        let result: Result<(), _> = with_backoff(|| {
+            calls += 1;
+            Err(BackendError::new(ErrorKind::Network, "offline"))
+        });
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Network);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn unknown_error_codes_are_other() {
+        for kind in [ErrorKind::Auth, ErrorKind::NotFound, ErrorKind::Network] {
+            assert_eq!(ErrorKind::from_code(kind as u32), kind);
+        }
+        assert_eq!(ErrorKind::from_code(u32::MAX), ErrorKind::Other);
+    }
+}
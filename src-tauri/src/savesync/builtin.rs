@@ -0,0 +1,108 @@
+pub mod local_folder;
+pub mod s3;
+pub mod webdav;
+
+use reqwest::{
+    StatusCode,
+    blocking::{Client, RequestBuilder, Response},
+};
+use std::{ffi::OsStr, time::Duration};
+
+use crate::app_store;
+
+use super::backend::{
+    BackendError, CloudBackend, ErrorKind, PluginInfo, report_error, with_backoff,
+};
+
+/// Idle time before TCP keepalive probes whether the server is still there
+const KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Filenames of the backends compiled into SaveSync
+/// None of them carry a library extension, so they never clash with plugin files
+const BUILTINS: [&str; 3] = [local_folder::FILENAME, s3::FILENAME, webdav::FILENAME];
+
+pub fn is_builtin(filename: &OsStr) -> bool {
+    BUILTINS.iter().any(|name| filename == *name)
+}
+
+pub fn load_builtin(filename: &OsStr) -> Option<Box<dyn CloudBackend>> {
+    match filename.to_str()? {
+        local_folder::FILENAME => Some(Box::new(local_folder::LocalFolder::load())),
+        s3::FILENAME => Some(Box::new(s3::S3::load())),
+        webdav::FILENAME => Some(Box::new(webdav::WebDav::load())),
+        _ => None,
+    }
+}
+
+pub fn builtin_infos() -> Vec<PluginInfo> {
+    BUILTINS
+        .iter()
+        .filter_map(|name| load_builtin(OsStr::new(name))?.info())
+        .collect()
+}
+
+/// Client of the HTTP backends, which gives up connecting after the plugin timeout
+/// Transfers take as long as they need, connections that went dead are dropped
+/// by TCP keepalive, and on Linux once sent data goes unacknowledged for the timeout
+fn http_client() -> Client {
+    let timeout = app_store().plugin_timeout();
+    let builder = Client::builder()
+        .timeout(None)
+        .connect_timeout(timeout)
+        .tcp_keepalive(KEEPALIVE);
+    #[cfg(target_os = "linux")]
+    let builder = builder.tcp_user_timeout(timeout);
+
+    builder.build().unwrap_or_default()
+}
+
+/// Sends the request for an HTTP backend, reporting transport errors and
+/// statuses that are neither successful nor in `allowed`
+/// Rate limited requests are sent again with backoff, unless their body is streamed
+fn send(filename: &str, request: RequestBuilder, allowed: &[StatusCode]) -> Option<Response> {
+    let mut next = Some(request);
+
+    with_backoff(|| {
+        let request = next.take().unwrap();
+        next = request.try_clone();
+
+        request
+            .send()
+            .map_err(|e| {
+                let kind = match e.is_connect() || e.is_timeout() {
+                    true => ErrorKind::Network,
+                    false => ErrorKind::Other,
+                };
+                BackendError::new(kind, e.to_string())
+            })
+            .and_then(|response| {
+                let status = response.status();
+                if status.is_success() || allowed.contains(&status) {
+                    Ok(response)
+                } else {
+                    Err(BackendError::new(
+                        status_kind(status),
+                        format!("{} returned {status}", response.url()),
+                    ))
+                }
+            })
+            .map_err(|e| match e.kind {
+                // a streamed body is consumed, so there is nothing left to retry with
+                ErrorKind::RateLimited if next.is_none() => {
+                    BackendError::new(ErrorKind::Other, e.message)
+                }
+                _ => e,
+            })
+    })
+    .inspect_err(|e| report_error(OsStr::new(filename), e))
+    .ok()
+}
+
+fn status_kind(status: StatusCode) -> ErrorKind {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorKind::Auth,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => ErrorKind::RateLimited,
+        StatusCode::NOT_FOUND => ErrorKind::NotFound,
+        _ => ErrorKind::Other,
+    }
+}
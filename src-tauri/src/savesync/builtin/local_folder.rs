@@ -0,0 +1,297 @@
+use serde_json::Value;
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::savesync::{
+    backend::{Capabilities, CloudBackend, FileDetails, PluginInfo},
+    emitter,
+    fs_utils::FolderItems,
+    vault,
+};
+
+pub const FILENAME: &str = "savesync-local-folder";
+
+/// Folder under the root holding sidecars and partial objects, apart from the objects
+const META_DIR: &str = ".savesync";
+
+/// Extension of the sidecar holding the last modified time of an object
+const SIDECAR_EXTENSION: &str = ".lastmodified";
+
+/// Extension of an object still being written
+const PARTIAL_EXTENSION: &str = ".partial";
+
+/// Stores every object as `<root>/<tag>/<folder_name>`, and the files kept about it
+/// under `<root>/.savesync/<tag>/`
+/// The root is kept in the credentials file, so logging in is choosing a folder
+pub struct LocalFolder {
+    root: Option<PathBuf>,
+}
+
+impl LocalFolder {
+    pub fn load() -> LocalFolder {
+        LocalFolder {
+            root: vault::read(OsStr::new(FILENAME)).map(PathBuf::from),
+        }
+    }
+
+    fn emit_error(&self, description: &str) {
+        emitter::plugin_error(OsStr::new(FILENAME), description);
+    }
+
+    fn report<T>(&self, result: io::Result<T>) -> Option<T> {
+        result.inspect_err(|e| self.emit_error(&e.to_string())).ok()
+    }
+
+    fn object_paths(&self, tag: &[u8], folder_name: &[u8]) -> Option<ObjectPaths> {
+        Some(ObjectPaths::new(
+            self.root.as_ref()?,
+            os_str(tag),
+            os_str(folder_name),
+        ))
+    }
+}
+
+/// An object and the files kept about it
+struct ObjectPaths {
+    object: PathBuf,
+    sidecar: PathBuf,
+    partial: PathBuf,
+}
+
+impl ObjectPaths {
+    fn new(root: &Path, tag: &OsStr, folder_name: &OsStr) -> ObjectPaths {
+        let meta = root.join(META_DIR).join(tag);
+        ObjectPaths {
+            object: root.join(tag).join(folder_name),
+            sidecar: meta.join(suffixed(folder_name, SIDECAR_EXTENSION)),
+            partial: meta.join(suffixed(folder_name, PARTIAL_EXTENSION)),
+        }
+    }
+}
+
+/// Saves the folder objects are stored in
+pub fn set_root(path: &str) -> io::Result<()> {
+    vault::write(OsStr::new(FILENAME), path)
+}
+
+/// Bytes handed to backends come from OsStr::as_encoded_bytes on this platform
+fn os_str(bytes: &[u8]) -> &OsStr {
+    unsafe { OsStr::from_encoded_bytes_unchecked(bytes) }
+}
+
+fn suffixed(name: &OsStr, suffix: &str) -> OsString {
+    let mut name = name.to_owned();
+    name.push(suffix);
+    name
+}
+
+fn read_sidecar(path: &Path) -> Option<SystemTime> {
+    fs::read_to_string(path)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn write_object(paths: &ObjectPaths, date: SystemTime, reader: &mut dyn Read) -> io::Result<()> {
+    for dir in [paths.object.parent(), paths.partial.parent()]
+        .into_iter()
+        .flatten()
+    {
+        fs::create_dir_all(dir)?;
+    }
+
+    // write then rename so a half-written object is never picked up
+    let written = File::create(&paths.partial)
+        .and_then(|mut file| io::copy(reader, &mut file))
+        .and_then(|_| {
+            // before the rename, so the new object is never listed with the old time
+            fs::write(
+                &paths.sidecar,
+                date.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+                    .to_string(),
+            )
+        })
+        .and_then(|_| fs::rename(&paths.partial, &paths.object));
+
+    if written.is_err() {
+        let _ = fs::remove_file(&paths.partial);
+        // the old object, if any, falls back to its own modified time
+        let _ = fs::remove_file(&paths.sidecar);
+    }
+    written
+}
+
+fn list_objects(root: &Path) -> io::Result<Vec<FileDetails>> {
+    root.get_folders()?
+        .into_iter()
+        .filter(|tag| tag.file_name() != META_DIR)
+        .try_fold(Vec::new(), |mut details, tag| {
+            for file in tag.path().get_files()? {
+                let paths = ObjectPaths::new(root, &tag.file_name(), &file.file_name());
+
+                details.push(FileDetails {
+                    tag: tag.file_name().to_string_lossy().into(),
+                    folder_name: file.file_name(),
+                    last_modified: match read_sidecar(&paths.sidecar) {
+                        Some(date) => date,
+                        None => file.metadata()?.modified()?,
+                    },
+                    data: None,
+                });
+            }
+            Ok(details)
+        })
+}
+
+impl CloudBackend for LocalFolder {
+    fn supports(&self, capability: Capabilities) -> bool {
+        (Capabilities::REMOVE | Capabilities::STREAMING).contains(capability)
+    }
+
+    fn info(&self) -> Option<PluginInfo> {
+        Some(PluginInfo {
+            name: "Local folder".into(),
+            description: "Syncs saves to a folder on this computer or a mounted network share"
+                .into(),
+            author: "SaveSync".into(),
+            icon_url: String::new(),
+            filename: OsString::from(FILENAME),
+        })
+    }
+
+    fn authenticate(&mut self, _: &Value) -> bool {
+        self.root = LocalFolder::load().root;
+
+        match &self.root {
+            Some(root) if root.is_dir() => true,
+            Some(root) => {
+                self.emit_error(&format!("{} is not available", root.to_string_lossy()));
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+        self.emit_error("Choose a folder to sync to before logging in");
+        None
+    }
+
+    fn process_save_credentials(&mut self, _: &str, _: &str) -> bool {
+        false
+    }
+
+    fn abort(&self, _tag: &[u8], _folder_name: &[u8]) -> bool {
+        false
+    }
+
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
+        self.object_paths(tag, folder_name)
+            .and_then(|paths| self.report(write_object(&paths, date, &mut &buffer[..])))
+            .is_some()
+    }
+
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
+        self.report(fs::read(self.object_paths(tag, folder_name)?.object))
+    }
+
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        mut reader: Box<dyn Read + Send>,
+        _: u64,
+    ) -> bool {
+        self.object_paths(tag, folder_name)
+            .and_then(|paths| self.report(write_object(&paths, date, &mut reader)))
+            .is_some()
+    }
+
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        self.object_paths(tag, folder_name)
+            .and_then(|paths| {
+                self.report(
+                    File::open(paths.object).and_then(|mut file| io::copy(&mut file, writer)),
+                )
+            })
+            .is_some()
+    }
+
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        let Some(paths) = self.object_paths(tag, folder_name) else {
+            return false;
+        };
+        let _ = fs::remove_file(&paths.sidecar);
+
+        match fs::remove_file(&paths.object) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => self.report::<()>(Err(e)).is_some(),
+            _ => true,
+        }
+    }
+
+    fn read_cloud(&self) -> Option<Vec<FileDetails>> {
+        self.report(list_objects(self.root.as_ref()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh directory under the system temp dir, unique to the test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "savesync-local-folder-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("connection lost"))
+        }
+    }
+
+    #[test]
+    fn failed_write_removes_partial() {
+        let paths = ObjectPaths::new(&scratch("failed"), "tag".as_ref(), "save".as_ref());
+
+        assert!(write_object(&paths, SystemTime::now(), &mut FailingReader).is_err());
+        assert!(!paths.partial.exists());
+        assert!(!paths.sidecar.exists());
+        assert!(!paths.object.exists());
+    }
+
+    #[test]
+    fn listing_skips_only_the_meta_folder() {
+        let root = scratch("listing");
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let paths = ObjectPaths::new(&root, "tag".as_ref(), "save".as_ref());
+        write_object(&paths, date, &mut &b"data"[..]).unwrap();
+        // user files may carry the extensions used for sidecars and partial objects
+        fs::write(root.join("tag").join("notes.partial"), b"notes").unwrap();
+
+        let mut details = list_objects(&root).unwrap();
+        details.sort_by(|a, b| a.folder_name.cmp(&b.folder_name));
+        assert_eq!(details.len(), 2);
+        assert_eq!(details[0].folder_name, "notes.partial");
+        assert_eq!(details[1].tag, "tag");
+        assert_eq!(details[1].folder_name, "save");
+        assert_eq!(details[1].last_modified, date);
+    }
+}
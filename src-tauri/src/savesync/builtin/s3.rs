@@ -0,0 +1,818 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use reqwest::{
+    Method, StatusCode, Url,
+    blocking::{Body, Client, RequestBuilder},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::savesync::{
+    backend::{Capabilities, CloudBackend, FileDetails, PluginInfo},
+    config_paths, emitter, vault,
+};
+
+use super::{http_client, send};
+
+pub const FILENAME: &str = "savesync-s3";
+
+/// Object metadata holding the local modified time, in seconds since the epoch
+const MTIME_HEADER: &str = "x-amz-meta-savesync-mtime";
+
+/// SHA-256 of an empty payload, signed on requests without a body
+const EMPTY_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Lets streamed uploads skip hashing the archive before sending it
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Uploads larger than this go up in parts, a single PUT is capped at 5 GiB
+const MULTIPART_THRESHOLD: u64 = 64 << 20;
+
+/// Size of every part but the last, S3 takes up to 10000 parts of at least 5 MiB
+const PART_SIZE: u64 = 16 << 20;
+
+/// Characters SigV4 leaves unencoded
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Namespace of ListObjectsV2 answers
+const NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// Bucket details saved in the credentials file
+#[derive(Serialize, Deserialize)]
+pub struct S3Login {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// Stores every object under the key `<tag>/<folder_name>`
+/// Requests use path-style addressing, which MinIO, B2 and Garage all accept
+pub struct S3 {
+    client: Client,
+    login: Option<S3Login>,
+    /// Modified time of every object by endpoint, bucket and key, with the ETag it was read for
+    /// Listings leave out metadata, so only objects whose ETag changed are asked about again
+    mtimes: Mutex<HashMap<String, StoredMtime>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredMtime {
+    etag: String,
+    mtime: SystemTime,
+}
+
+/// One `<Contents>` of a listing
+struct Object {
+    key: String,
+    etag: String,
+    last_modified: SystemTime,
+}
+
+/// One page of a listing and the token of the next one
+struct Listing {
+    objects: Vec<Object>,
+    continuation: Option<String>,
+}
+
+impl S3 {
+    pub fn load() -> S3 {
+        S3 {
+            client: http_client(),
+            login: vault::read(OsStr::new(FILENAME)).and_then(|s| serde_json::from_str(&s).ok()),
+            mtimes: Mutex::new(
+                fs::read(mtimes_path())
+                    .ok()
+                    .and_then(|json| serde_json::from_slice(&json).ok())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn emit_error(&self, description: &str) {
+        emitter::plugin_error(OsStr::new(FILENAME), description);
+    }
+
+    /// Builds a request signed with AWS Signature Version 4
+    /// The caller attaches the body matching `payload_hash`
+    fn signed(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+        payload_hash: &str,
+    ) -> Option<RequestBuilder> {
+        let login = self.login.as_ref()?;
+        let parsed = Url::parse(&login.endpoint)
+            .inspect_err(|e| self.emit_error(&e.to_string()))
+            .ok()?;
+        let host = match parsed.port() {
+            Some(port) => format!("{}:{port}", parsed.host_str()?),
+            None => parsed.host_str()?.to_owned(),
+        };
+
+        let path = canonical_path(&parsed, &login.bucket, key);
+
+        let mut query: Vec<String> = query
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(k, UNRESERVED),
+                    utf8_percent_encode(v, UNRESERVED)
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query.join("&");
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut signed_headers: Vec<(String, String)> = [
+            ("host", host.clone()),
+            ("x-amz-content-sha256", payload_hash.to_owned()),
+            ("x-amz-date", amz_date.clone()),
+        ]
+        .into_iter()
+        .chain(headers.iter().cloned())
+        .map(|(k, v)| (k.to_lowercase(), v.trim().to_owned()))
+        .collect();
+        signed_headers.sort();
+
+        let canonical_headers: String = signed_headers
+            .iter()
+            .map(|(k, v)| format!("{k}:{v}\n"))
+            .collect();
+        let header_names = signed_headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{header_names}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", login.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [date.as_str(), &login.region, "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", login.secret_key).into_bytes(),
+                |key, part| hmac(&key, part.as_bytes()),
+            );
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let origin = format!("{}://{host}", parsed.scheme());
+        let url = match query.is_empty() {
+            true => format!("{origin}{path}"),
+            false => format!("{origin}{path}?{query}"),
+        };
+
+        Some(
+            signed_headers
+                .into_iter()
+                .filter(|(k, _)| k != "host")
+                .fold(self.client.request(method, url), |request, (k, v)| {
+                    request.header(k, v)
+                })
+                .header(
+                    "Authorization",
+                    format!(
+                        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={header_names}, Signature={signature}",
+                        login.access_key
+                    ),
+                ),
+        )
+    }
+
+    fn list_objects(&self) -> Option<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2")];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+
+            let body = send(
+                FILENAME,
+                self.signed(Method::GET, "", &query, &[], EMPTY_HASH)?,
+                &[],
+            )?
+            .text()
+            .ok()?;
+            let listing = parse_listing(&body)
+                .inspect_err(|e| self.emit_error(&format!("Invalid bucket listing: {e}")))
+                .ok()?;
+
+            objects.extend(listing.objects);
+            match listing.continuation {
+                Some(next) => token = Some(next),
+                None => return Some(objects),
+            }
+        }
+    }
+
+    fn put_request(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        payload_hash: &str,
+    ) -> Option<RequestBuilder> {
+        let mtime = date
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+
+        self.signed(
+            Method::PUT,
+            &object_key(tag, folder_name),
+            &[],
+            &[(MTIME_HEADER, mtime)],
+            payload_hash,
+        )
+    }
+
+    fn get_request(&self, tag: &[u8], folder_name: &[u8]) -> Option<RequestBuilder> {
+        self.signed(
+            Method::GET,
+            &object_key(tag, folder_name),
+            &[],
+            &[],
+            EMPTY_HASH,
+        )
+    }
+
+    /// Reads the modified time SaveSync stored in the object's metadata
+    fn stored_mtime(&self, key: &str) -> Option<SystemTime> {
+        let response = send(
+            FILENAME,
+            self.signed(Method::HEAD, key, &[], &[], EMPTY_HASH)?,
+            &[],
+        )?;
+        let secs = response.headers().get(MTIME_HEADER)?.to_str().ok()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?))
+    }
+
+    /// Key of the object in `mtimes`, which keeps the times of other logins' buckets apart
+    fn mtime_key(&self, key: &str) -> String {
+        match &self.login {
+            Some(login) => format!(
+                "{}/{}/{key}",
+                login.endpoint.trim_end_matches('/'),
+                login.bucket
+            ),
+            None => key.to_owned(),
+        }
+    }
+
+    /// Modified time of the listed object, asked with a HEAD only if its ETag is new
+    fn mtime(&self, object: &Object) -> SystemTime {
+        if let Some(stored) = self
+            .mtimes
+            .lock()
+            .unwrap()
+            .get(&self.mtime_key(&object.key))
+            && stored.etag == object.etag
+        {
+            return stored.mtime;
+        }
+
+        match self.stored_mtime(&object.key) {
+            Some(mtime) => {
+                self.remember(&object.key, Some(&object.etag), mtime);
+                mtime
+            }
+            None => object.last_modified,
+        }
+    }
+
+    /// Saves the modified time of `key`, dropping it when the ETag is unknown
+    fn remember(&self, key: &str, etag: Option<&str>, mtime: SystemTime) {
+        let key = self.mtime_key(key);
+        let mut mtimes = self.mtimes.lock().unwrap();
+        match etag {
+            Some(etag) => mtimes.insert(
+                key,
+                StoredMtime {
+                    etag: etag.trim_matches('"').to_owned(),
+                    mtime,
+                },
+            ),
+            None => mtimes.remove(&key),
+        };
+        serde_json::to_vec(&*mtimes)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(mtimes_path(), json))
+            .inspect_err(|e| println!("Unable to save the S3 modified times: {e}"))
+            .ok();
+    }
+
+    fn put(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        payload_hash: &str,
+        body: Body,
+    ) -> bool {
+        let Some(response) = self
+            .put_request(tag, folder_name, date, payload_hash)
+            .and_then(|request| send(FILENAME, request.body(body), &[]))
+        else {
+            return false;
+        };
+
+        let etag = response.headers().get("ETag").and_then(|e| e.to_str().ok());
+        self.remember(&object_key(tag, folder_name), etag, date);
+        true
+    }
+
+    /// Uploads `reader` in parts of PART_SIZE, discarding the parts sent so far if one fails
+    fn put_multipart(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        mut reader: Box<dyn Read + Send>,
+    ) -> bool {
+        let key = object_key(tag, folder_name);
+        let Some(upload_id) = self.create_multipart(&key, date) else {
+            return false;
+        };
+
+        let completed = self
+            .put_parts(&key, &upload_id, &mut reader)
+            .and_then(|etags| self.complete_multipart(&key, &upload_id, &etags));
+        match completed {
+            Some(etag) => {
+                self.remember(&key, Some(&etag), date);
+                true
+            }
+            None => {
+                let _ = self
+                    .signed(
+                        Method::DELETE,
+                        &key,
+                        &[("uploadId", &upload_id)],
+                        &[],
+                        EMPTY_HASH,
+                    )
+                    .and_then(|request| send(FILENAME, request, &[StatusCode::NOT_FOUND]));
+                false
+            }
+        }
+    }
+
+    /// Starts a multipart upload carrying the modified time, returning its upload ID
+    fn create_multipart(&self, key: &str, date: SystemTime) -> Option<String> {
+        let mtime = date
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+        let request = self.signed(
+            Method::POST,
+            key,
+            &[("uploads", "")],
+            &[(MTIME_HEADER, mtime)],
+            EMPTY_HASH,
+        )?;
+        let body = send(FILENAME, request, &[])?.text().ok()?;
+
+        answer_field(&body, "InitiateMultipartUploadResult", "UploadId")
+            .inspect_err(|e| self.emit_error(e))
+            .ok()
+    }
+
+    /// Sends the parts read from `reader`, returning their ETags in order
+    fn put_parts(&self, key: &str, upload_id: &str, reader: &mut dyn Read) -> Option<Vec<String>> {
+        let mut etags = Vec::new();
+
+        loop {
+            let mut part = Vec::with_capacity(PART_SIZE as usize);
+            reader
+                .take(PART_SIZE)
+                .read_to_end(&mut part)
+                .inspect_err(|e| self.emit_error(&e.to_string()))
+                .ok()?;
+            if part.is_empty() {
+                return Some(etags);
+            }
+
+            let number = (etags.len() + 1).to_string();
+            let last = (part.len() as u64) < PART_SIZE;
+            let request = self.signed(
+                Method::PUT,
+                key,
+                &[("partNumber", &number), ("uploadId", upload_id)],
+                &[],
+                UNSIGNED_PAYLOAD,
+            )?;
+            let response = send(FILENAME, request.body(part), &[])?;
+            etags.push(response.headers().get("ETag")?.to_str().ok()?.to_owned());
+
+            if last {
+                return Some(etags);
+            }
+        }
+    }
+
+    /// Joins the parts into the object, returning its ETag
+    fn complete_multipart(&self, key: &str, upload_id: &str, etags: &[String]) -> Option<String> {
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>",
+                    i + 1
+                )
+            })
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+        let hash = hex::encode(Sha256::digest(body.as_bytes()));
+
+        let request = self.signed(Method::POST, key, &[("uploadId", upload_id)], &[], &hash)?;
+        let answer = send(FILENAME, request.body(body), &[])?.text().ok()?;
+
+        answer_field(&answer, "CompleteMultipartUploadResult", "ETag")
+            .inspect_err(|e| self.emit_error(e))
+            .ok()
+    }
+}
+
+/// Saves the bucket details used to log in
+pub fn set_login(login: &S3Login) -> io::Result<()> {
+    vault::write(OsStr::new(FILENAME), &serde_json::to_string(login)?)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Path of `key` in the bucket, as it is signed and requested
+/// Endpoints behind a proxy may carry a path of their own, like https://host/minio
+fn canonical_path(endpoint: &Url, bucket: &str, key: &str) -> String {
+    let prefix: Vec<String> = endpoint
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .collect();
+
+    prefix
+        .iter()
+        .map(String::as_str)
+        .chain([bucket])
+        .chain(key.split('/').filter(|s| !s.is_empty()))
+        .map(|segment| format!("/{}", utf8_percent_encode(segment, UNRESERVED)))
+        .collect()
+}
+
+fn mtimes_path() -> PathBuf {
+    config_paths::config().join("s3_mtimes.json")
+}
+
+/// Reads a page of a ListObjectsV2 answer
+fn parse_listing(body: &str) -> Result<Listing, roxmltree::Error> {
+    let document = roxmltree::Document::parse(body)?;
+    let text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.has_tag_name((NAMESPACE, name)))
+            .and_then(|child| child.text())
+            .map(str::trim)
+            .map(str::to_owned)
+    };
+    let root = document.root_element();
+
+    Ok(Listing {
+        objects: root
+            .children()
+            .filter(|node| node.has_tag_name((NAMESPACE, "Contents")))
+            .filter_map(|contents| {
+                Some(Object {
+                    key: text(contents, "Key")?,
+                    etag: text(contents, "ETag")
+                        .unwrap_or_default()
+                        .trim_matches('"')
+                        .to_owned(),
+                    last_modified: text(contents, "LastModified")
+                        .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                        .map_or(SystemTime::UNIX_EPOCH, SystemTime::from),
+                })
+            })
+            .collect(),
+        continuation: text(root, "NextContinuationToken"),
+    })
+}
+
+/// Reads the `field` of an answer whose root element is `root`
+/// S3 may report a failure in the body of a successful answer, whose root is Error instead
+fn answer_field(body: &str, root: &str, field: &str) -> Result<String, String> {
+    let document = roxmltree::Document::parse(body).map_err(|e| e.to_string())?;
+    let element = document.root_element();
+    let text = |name: &str| {
+        element
+            .children()
+            .find(|child| child.tag_name().name() == name)
+            .and_then(|child| child.text())
+            .map(str::trim)
+    };
+
+    if !element.has_tag_name((NAMESPACE, root)) {
+        return Err(text("Message").unwrap_or("Unexpected answer").to_owned());
+    }
+    text(field)
+        .map(str::to_owned)
+        .ok_or_else(|| format!("{root} without {field}"))
+}
+
+fn object_key(tag: &[u8], folder_name: &[u8]) -> String {
+    format!(
+        "{}/{}",
+        String::from_utf8_lossy(tag),
+        String::from_utf8_lossy(folder_name)
+    )
+}
+
+impl CloudBackend for S3 {
+    fn supports(&self, capability: Capabilities) -> bool {
+        (Capabilities::REMOVE | Capabilities::STREAMING).contains(capability)
+    }
+
+    fn info(&self) -> Option<PluginInfo> {
+        Some(PluginInfo {
+            name: "S3-compatible storage".into(),
+            description: "Syncs saves to an S3-compatible bucket such as MinIO, B2 or Garage"
+                .into(),
+            author: "SaveSync".into(),
+            icon_url: String::new(),
+            filename: OsString::from(FILENAME),
+        })
+    }
+
+    fn authenticate(&mut self, _: &Value) -> bool {
+        self.login = S3::load().login;
+
+        self.signed(Method::HEAD, "", &[], &[], EMPTY_HASH)
+            .and_then(|request| send(FILENAME, request, &[]))
+            .is_some()
+    }
+
+    fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+        self.emit_error("Enter your bucket details before logging in");
+        None
+    }
+
+    fn process_save_credentials(&mut self, _: &str, _: &str) -> bool {
+        false
+    }
+
+    fn abort(&self, _tag: &[u8], _folder_name: &[u8]) -> bool {
+        false
+    }
+
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
+        if buffer.len() as u64 > MULTIPART_THRESHOLD {
+            let reader = Box::new(io::Cursor::new(buffer.to_vec()));
+            return self.put_multipart(tag, folder_name, date, reader);
+        }
+
+        let hash = hex::encode(Sha256::digest(buffer));
+        self.put(tag, folder_name, date, &hash, Body::from(buffer.to_vec()))
+    }
+
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
+        send(FILENAME, self.get_request(tag, folder_name)?, &[])?
+            .bytes()
+            .inspect_err(|e| self.emit_error(&e.to_string()))
+            .ok()
+            .map(|bytes| bytes.to_vec())
+    }
+
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        reader: Box<dyn Read + Send>,
+        len: u64,
+    ) -> bool {
+        if len > MULTIPART_THRESHOLD {
+            return self.put_multipart(tag, folder_name, date, reader);
+        }
+
+        let body = Body::sized(reader, len);
+        self.put(tag, folder_name, date, UNSIGNED_PAYLOAD, body)
+    }
+
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        self.get_request(tag, folder_name)
+            .and_then(|request| send(FILENAME, request, &[]))
+            .and_then(|mut response| {
+                response
+                    .copy_to(writer)
+                    .inspect_err(|e| self.emit_error(&e.to_string()))
+                    .ok()
+            })
+            .is_some()
+    }
+
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        let key = object_key(tag, folder_name);
+        let removed = self
+            .signed(Method::DELETE, &key, &[], &[], EMPTY_HASH)
+            .and_then(|request| send(FILENAME, request, &[StatusCode::NOT_FOUND]))
+            .is_some();
+        if removed {
+            self.remember(&key, None, SystemTime::UNIX_EPOCH);
+        }
+        removed
+    }
+
+    fn read_cloud(&self) -> Option<Vec<FileDetails>> {
+        Some(
+            self.list_objects()?
+                .into_iter()
+                .filter_map(|object| {
+                    let (tag, folder_name) = object.key.split_once('/')?;
+                    Some(FileDetails {
+                        tag: tag.into(),
+                        folder_name: folder_name.into(),
+                        last_modified: self.mtime(&object),
+                        data: None,
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listing_pages() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Name>saves</Name>
+  <IsTruncated>true</IsTruncated>
+  <Contents>
+    <Key>My Game/slot &amp; 1</Key>
+    <LastModified>2023-11-14T22:13:20.000Z</LastModified>
+    <ETag>&quot;9a0364b9e99bb480dd25e1f0284c8555&quot;</ETag>
+    <Size>7</Size>
+  </Contents>
+  <NextContinuationToken>a&lt;b</NextContinuationToken>
+</ListBucketResult>"#;
+
+        let listing = parse_listing(body).unwrap();
+        assert_eq!(listing.objects.len(), 1);
+        assert_eq!(listing.objects[0].key, "My Game/slot & 1");
+        assert_eq!(listing.objects[0].etag, "9a0364b9e99bb480dd25e1f0284c8555");
+        assert_eq!(
+            listing.objects[0].last_modified,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(listing.continuation.as_deref(), Some("a<b"));
+    }
+
+    #[test]
+    fn signs_the_endpoint_path() {
+        let path = |endpoint: &str| {
+            canonical_path(&Url::parse(endpoint).unwrap(), "saves", "My Game/slot 1")
+        };
+
+        assert_eq!(path("https://host"), "/saves/My%20Game/slot%201");
+        assert_eq!(path("https://host/"), "/saves/My%20Game/slot%201");
+        assert_eq!(
+            path("https://host/minio/"),
+            "/minio/saves/My%20Game/slot%201"
+        );
+        assert_eq!(
+            path("https://host/s3%20proxy"),
+            "/s3%20proxy/saves/My%20Game/slot%201"
+        );
+    }
+
+    #[test]
+    fn reads_multipart_answers() {
+        let initiated = r#"<?xml version="1.0" encoding="UTF-8"?>
+<InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Bucket>saves</Bucket>
+  <Key>My Game/slot 1</Key>
+  <UploadId>VXBsb2FkIElE</UploadId>
+</InitiateMultipartUploadResult>"#;
+        assert_eq!(
+            answer_field(initiated, "InitiateMultipartUploadResult", "UploadId").unwrap(),
+            "VXBsb2FkIElE"
+        );
+
+        let completed = r#"<CompleteMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <ETag>&quot;3858f62230ac3c915f300c664312c11f-2&quot;</ETag>
+</CompleteMultipartUploadResult>"#;
+        assert_eq!(
+            answer_field(completed, "CompleteMultipartUploadResult", "ETag").unwrap(),
+            "\"3858f62230ac3c915f300c664312c11f-2\""
+        );
+
+        // sent with a 200 status once the upload already started answering
+        let failed = r#"<Error><Code>InternalError</Code><Message>Try again</Message></Error>"#;
+        assert_eq!(
+            answer_field(failed, "CompleteMultipartUploadResult", "ETag").unwrap_err(),
+            "Try again"
+        );
+    }
+
+    #[test]
+    fn keeps_modified_times_of_buckets_apart() {
+        let s3 = |bucket: &str| S3 {
+            client: Client::new(),
+            login: Some(S3Login {
+                endpoint: "https://host/".into(),
+                bucket: bucket.into(),
+                region: "us-east-1".into(),
+                access_key: String::new(),
+                secret_key: String::new(),
+            }),
+            mtimes: Mutex::default(),
+        };
+
+        assert_eq!(
+            s3("saves").mtime_key("tag/slot"),
+            "https://host/saves/tag/slot"
+        );
+        assert_ne!(
+            s3("saves").mtime_key("tag/slot"),
+            s3("other").mtime_key("tag/slot")
+        );
+    }
+
+    /// Runs against the bucket named by SAVESYNC_S3_ENDPOINT, SAVESYNC_S3_BUCKET,
+    /// SAVESYNC_S3_ACCESS_KEY and SAVESYNC_S3_SECRET_KEY, e.g. a local MinIO container
+    #[test]
+    #[ignore = "needs a MinIO server"]
+    fn round_trips_against_minio() {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let s3 = S3 {
+            client: Client::new(),
+            login: Some(S3Login {
+                endpoint: var("SAVESYNC_S3_ENDPOINT"),
+                bucket: var("SAVESYNC_S3_BUCKET"),
+                region: std::env::var("SAVESYNC_S3_REGION").unwrap_or("us-east-1".into()),
+                access_key: var("SAVESYNC_S3_ACCESS_KEY"),
+                secret_key: var("SAVESYNC_S3_SECRET_KEY"),
+            }),
+            mtimes: Mutex::default(),
+        };
+        let tag = format!("savesync-test-{}", std::process::id());
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let find = |details: Vec<FileDetails>| {
+            details
+                .into_iter()
+                .find(|d| d.tag == tag && d.folder_name == "save 1")
+        };
+
+        assert!(s3.upload(tag.as_bytes(), b"save 1", date, b"archive"));
+        assert_eq!(s3.download(tag.as_bytes(), b"save 1").unwrap(), b"archive");
+        assert_eq!(find(s3.read_cloud().unwrap()).unwrap().last_modified, date);
+
+        // a listing without the remembered ETag reads the time from the metadata
+        s3.mtimes.lock().unwrap().clear();
+        assert_eq!(find(s3.read_cloud().unwrap()).unwrap().last_modified, date);
+
+        assert!(s3.remove(tag.as_bytes(), b"save 1"));
+        assert!(find(s3.read_cloud().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,413 @@
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, percent_encode};
+use reqwest::{
+    Method, StatusCode,
+    blocking::{Body, Client, RequestBuilder},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    ffi::{OsStr, OsString},
+    io::{self, Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use crate::savesync::{
+    backend::{Capabilities, CloudBackend, FileDetails, PluginInfo},
+    emitter, vault,
+};
+
+use super::{http_client, send};
+
+pub const FILENAME: &str = "savesync-webdav";
+
+const DAV: &str = "DAV:";
+
+/// Namespace of the dead property holding the local modified time
+const NAMESPACE: &str = "urn:savesync";
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:s="urn:savesync"><d:prop><d:getlastmodified/><d:resourcetype/><s:mtime/></d:prop></d:propfind>"#;
+
+/// Server details saved in the credentials file
+/// `password` may be an app password, which Nextcloud recommends for sync clients
+#[derive(Serialize, Deserialize)]
+pub struct WebDavLogin {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Stores every object at `<url>/<tag>/<folder_name>`
+pub struct WebDav {
+    client: Client,
+    login: Option<WebDavLogin>,
+}
+
+/// One `<response>` of a PROPFIND multistatus
+struct Entry {
+    href: String,
+    collection: bool,
+    last_modified: Option<SystemTime>,
+}
+
+impl WebDav {
+    pub fn load() -> WebDav {
+        WebDav {
+            client: http_client(),
+            login: vault::read(OsStr::new(FILENAME)).and_then(|s| serde_json::from_str(&s).ok()),
+        }
+    }
+
+    fn emit_error(&self, description: &str) {
+        emitter::plugin_error(OsStr::new(FILENAME), description);
+    }
+
+    fn url(&self, segments: &[&[u8]]) -> Option<String> {
+        let base = self.login.as_ref()?.url.trim_end_matches('/');
+
+        Some(segments.iter().fold(base.to_owned(), |url, segment| {
+            format!("{url}/{}", percent_encode(segment, NON_ALPHANUMERIC))
+        }))
+    }
+
+    fn request(&self, method: Method, url: &str) -> Option<RequestBuilder> {
+        let login = self.login.as_ref()?;
+        Some(
+            self.client
+                .request(method, url)
+                .basic_auth(&login.username, Some(&login.password)),
+        )
+    }
+
+    fn propfind(&self, url: &str, depth: &str) -> Option<Vec<Entry>> {
+        let request = self
+            .request(Method::from_bytes(b"PROPFIND").unwrap(), url)?
+            .header("Depth", depth)
+            .header("Content-Type", "application/xml")
+            .body(PROPFIND_BODY);
+        let body = send(FILENAME, request, &[])?.text().ok()?;
+
+        let entries = parse_multistatus(&body)
+            .inspect_err(|e| self.emit_error(&format!("{url} answered with invalid XML: {e}")))
+            .ok()?;
+        // the collection itself is listed alongside its members
+        Some(
+            entries
+                .into_iter()
+                .filter(|entry| !is_same_resource(url, &entry.href))
+                .collect(),
+        )
+    }
+
+    /// Stores the local modified time as a dead property, as only Nextcloud and ownCloud
+    /// honour X-OC-Mtime and other servers date the file by its upload
+    /// Servers without dead properties fall back to getlastmodified
+    fn set_mtime(&self, url: &str, date: SystemTime) {
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propertyupdate xmlns:d="DAV:" xmlns:s="{NAMESPACE}"><d:set><d:prop><s:mtime>{}</s:mtime></d:prop></d:set></d:propertyupdate>"#,
+            seconds(date)
+        );
+        let unsupported = [
+            StatusCode::FORBIDDEN,
+            StatusCode::METHOD_NOT_ALLOWED,
+            StatusCode::NOT_IMPLEMENTED,
+        ];
+
+        self.request(Method::from_bytes(b"PROPPATCH").unwrap(), url)
+            .map(|r| r.header("Content-Type", "application/xml").body(body))
+            .and_then(|r| send(FILENAME, r, &unsupported));
+    }
+
+    fn put(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, body: Body) -> bool {
+        let (Some(collection), Some(url)) = (self.url(&[tag]), self.url(&[tag, folder_name]))
+        else {
+            return false;
+        };
+
+        // X-OC-Mtime lets Nextcloud and ownCloud keep the local modified time
+        let uploaded = self.make_collection(&collection)
+            && self
+                .request(Method::PUT, &url)
+                .map(|r| r.header("X-OC-Mtime", seconds(date)).body(body))
+                .and_then(|r| send(FILENAME, r, &[]))
+                .is_some();
+        if uploaded {
+            self.set_mtime(&url, date);
+        }
+        uploaded
+    }
+
+    fn make_collection(&self, url: &str) -> bool {
+        self.request(Method::from_bytes(b"MKCOL").unwrap(), url)
+            .and_then(|request| send(FILENAME, request, &[StatusCode::METHOD_NOT_ALLOWED]))
+            .is_some()
+    }
+}
+
+/// Saves the server details used to log in
+pub fn set_login(login: &WebDavLogin) -> io::Result<()> {
+    vault::write(OsStr::new(FILENAME), &serde_json::to_string(login)?)
+}
+
+/// Reads every `<response>` of a PROPFIND multistatus, whatever prefixes the server picked
+fn parse_multistatus(body: &str) -> Result<Vec<Entry>, roxmltree::Error> {
+    let document = roxmltree::Document::parse(body)?;
+
+    Ok(document
+        .descendants()
+        .filter(|node| node.has_tag_name((DAV, "response")))
+        .filter_map(|response| {
+            let text = |name: (&str, &str)| {
+                response
+                    .descendants()
+                    .find(|node| node.has_tag_name(name))
+                    .and_then(|node| node.text())
+                    .map(str::trim)
+            };
+            let mtime = text((NAMESPACE, "mtime"))
+                .and_then(|secs| secs.parse().ok())
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+            let last_modified = text((DAV, "getlastmodified"))
+                .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok())
+                .map(SystemTime::from);
+
+            Some(Entry {
+                href: decode(text((DAV, "href"))?),
+                collection: response
+                    .descendants()
+                    .any(|node| node.has_tag_name((DAV, "collection"))),
+                last_modified: mtime.or(last_modified),
+            })
+        })
+        .collect())
+}
+
+fn seconds(date: SystemTime) -> u64 {
+    date.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn decode(href: &str) -> String {
+    percent_decode_str(href).decode_utf8_lossy().into_owned()
+}
+
+fn is_same_resource(url: &str, href: &str) -> bool {
+    decode(url)
+        .trim_end_matches('/')
+        .ends_with(href.trim_end_matches('/'))
+}
+
+fn name(href: &str) -> String {
+    href.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .into()
+}
+
+impl CloudBackend for WebDav {
+    fn supports(&self, capability: Capabilities) -> bool {
+        (Capabilities::REMOVE | Capabilities::STREAMING).contains(capability)
+    }
+
+    fn info(&self) -> Option<PluginInfo> {
+        Some(PluginInfo {
+            name: "WebDAV".into(),
+            description: "Syncs saves to a WebDAV server such as Nextcloud".into(),
+            author: "SaveSync".into(),
+            icon_url: String::new(),
+            filename: OsString::from(FILENAME),
+        })
+    }
+
+    fn authenticate(&mut self, _: &Value) -> bool {
+        self.login = WebDav::load().login;
+        let Some(url) = self.url(&[]) else {
+            return false;
+        };
+
+        let request = self
+            .request(Method::from_bytes(b"PROPFIND").unwrap(), &url)
+            .map(|r| r.header("Depth", "0").body(PROPFIND_BODY));
+
+        match request.and_then(|r| send(FILENAME, r, &[StatusCode::NOT_FOUND])) {
+            Some(response) if response.status() == StatusCode::NOT_FOUND => {
+                self.make_collection(&url)
+            }
+            response => response.is_some(),
+        }
+    }
+
+    fn auth_url(&self, _: &str, _: &str, _: &str) -> Option<String> {
+        self.emit_error("Enter your WebDAV server details before logging in");
+        None
+    }
+
+    fn process_save_credentials(&mut self, _: &str, _: &str) -> bool {
+        false
+    }
+
+    fn abort(&self, _tag: &[u8], _folder_name: &[u8]) -> bool {
+        false
+    }
+
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
+        self.put(tag, folder_name, date, Body::from(buffer.to_vec()))
+    }
+
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
+        let request = self.request(Method::GET, &self.url(&[tag, folder_name])?)?;
+        send(FILENAME, request, &[])?
+            .bytes()
+            .inspect_err(|e| self.emit_error(&e.to_string()))
+            .ok()
+            .map(|bytes| bytes.to_vec())
+    }
+
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        reader: Box<dyn Read + Send>,
+        len: u64,
+    ) -> bool {
+        self.put(tag, folder_name, date, Body::sized(reader, len))
+    }
+
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        self.url(&[tag, folder_name])
+            .and_then(|url| self.request(Method::GET, &url))
+            .and_then(|r| send(FILENAME, r, &[]))
+            .and_then(|mut response| {
+                response
+                    .copy_to(writer)
+                    .inspect_err(|e| self.emit_error(&e.to_string()))
+                    .ok()
+            })
+            .is_some()
+    }
+
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        self.url(&[tag, folder_name])
+            .and_then(|url| self.request(Method::DELETE, &url))
+            .and_then(|r| send(FILENAME, r, &[StatusCode::NOT_FOUND]))
+            .is_some()
+    }
+
+    fn read_cloud(&self) -> Option<Vec<FileDetails>> {
+        let tags = self.propfind(&self.url(&[])?, "1")?;
+
+        tags.into_iter()
+            .filter(|entry| entry.collection)
+            .map(|entry| name(&entry.href))
+            .try_fold(Vec::new(), |mut details, tag| {
+                let files = self.propfind(&self.url(&[tag.as_bytes()])?, "1")?;
+                details.extend(
+                    files
+                        .into_iter()
+                        .filter(|f| !f.collection)
+                        .map(|f| FileDetails {
+                            tag: tag.clone(),
+                            folder_name: name(&f.href).into(),
+                            last_modified: f.last_modified.unwrap_or(SystemTime::UNIX_EPOCH),
+                            data: None,
+                        }),
+                );
+                Some(details)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multistatus_with_any_prefix() {
+        let body = r#"<?xml version="1.0"?>
+<D:multistatus xmlns:D="DAV:" xmlns:x="urn:savesync">
+  <D:response>
+    <D:href>/dav/My%20Game/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection/></D:resourcetype></D:prop></D:propstat>
+  </D:response>
+  <D:response>
+    <D:href>/dav/My%20Game/slot&amp;1</D:href>
+    <D:propstat>
+      <D:prop>
+        <D:getlastmodified>Tue, 14 Nov 2023 22:13:20 GMT</D:getlastmodified>
+        <D:resourcetype/>
+        <x:mtime>1600000000</x:mtime>
+      </D:prop>
+    </D:propstat>
+  </D:response>
+  <D:response>
+    <D:href>/dav/My%20Game/slot2</D:href>
+    <D:propstat><D:prop><D:getlastmodified>Tue, 14 Nov 2023 22:13:20 GMT</D:getlastmodified></D:prop></D:propstat>
+    <D:propstat><D:prop><x:mtime/></D:prop><D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>
+  </D:response>
+</D:multistatus>"#;
+
+        let entries = parse_multistatus(body).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].collection);
+        assert_eq!(entries[0].href, "/dav/My Game/");
+        assert!(!entries[1].collection);
+        assert_eq!(name(&entries[1].href), "slot&1");
+        assert_eq!(
+            entries[1].last_modified,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+        );
+        assert_eq!(
+            entries[2].last_modified,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_xml() {
+        assert!(parse_multistatus("<d:multistatus xmlns:d=\"DAV:\">").is_err());
+    }
+
+    /// Runs against the server named by SAVESYNC_WEBDAV_URL, SAVESYNC_WEBDAV_USERNAME and
+    /// SAVESYNC_WEBDAV_PASSWORD, e.g. `rclone serve webdav` or Apache mod_dav in a container
+    #[test]
+    #[ignore = "needs a local WebDAV server"]
+    fn round_trips_against_server() {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let webdav = WebDav {
+            client: Client::new(),
+            login: Some(WebDavLogin {
+                url: var("SAVESYNC_WEBDAV_URL"),
+                username: var("SAVESYNC_WEBDAV_USERNAME"),
+                password: var("SAVESYNC_WEBDAV_PASSWORD"),
+            }),
+        };
+        let tag = format!("savesync-test-{}", std::process::id());
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        assert!(webdav.make_collection(&webdav.url(&[]).unwrap()));
+        assert!(webdav.upload(tag.as_bytes(), b"save 1", date, b"archive"));
+
+        let details = webdav.read_cloud().unwrap();
+        let uploaded = details
+            .iter()
+            .find(|d| d.tag == tag && d.folder_name == "save 1")
+            .unwrap();
+        // needs a server that keeps dead properties or honours X-OC-Mtime
+        assert_eq!(uploaded.last_modified, date);
+        assert_eq!(
+            webdav.download(tag.as_bytes(), b"save 1").unwrap(),
+            b"archive"
+        );
+
+        assert!(webdav.remove(tag.as_bytes(), b"save 1"));
+        let collection = webdav.url(&[tag.as_bytes()]).unwrap();
+        webdav
+            .request(Method::DELETE, &collection)
+            .and_then(|r| send(FILENAME, r, &[]))
+            .unwrap();
+    }
+}
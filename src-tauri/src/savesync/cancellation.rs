@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io::{self, Read, Write},
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// In-flight transfers by (tag, name of the file in the cloud)
+static TRANSFERS: LazyLock<Mutex<HashMap<(String, OsString), CancelToken>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Shared flag telling a transfer to stop at its next chunk
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Wraps a reader or writer so it fails once the token is cancelled
+    pub fn guard<T>(&self, inner: T) -> Cancellable<T> {
+        Cancellable {
+            inner,
+            token: self.clone(),
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        match self.is_cancelled() {
+            true => Err(io::Error::other("Sync cancelled")),
+            false => Ok(()),
+        }
+    }
+}
+
+pub struct Cancellable<T> {
+    inner: T,
+    token: CancelToken,
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.token.check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<W: Write> Write for Cancellable<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.token.check()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Registration of an in-flight transfer, removed when dropped
+pub struct Transfer {
+    key: (String, OsString),
+    token: CancelToken,
+}
+
+impl Transfer {
+    /// Registers a transfer of `cloud_name`, replacing the token of an earlier one
+    pub fn begin(tag: &str, cloud_name: &OsStr) -> Transfer {
+        let transfer = Transfer {
+            key: (tag.to_owned(), cloud_name.to_owned()),
+            token: CancelToken::default(),
+        };
+        TRANSFERS
+            .lock()
+            .unwrap()
+            .insert(transfer.key.clone(), transfer.token.clone());
+        transfer
+    }
+
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        let mut transfers = TRANSFERS.lock().unwrap();
+        // a newer transfer of the same file may have replaced this one
+        if transfers
+            .get(&self.key)
+            .is_some_and(|token| Arc::ptr_eq(&token.0, &self.token.0))
+        {
+            transfers.remove(&self.key);
+        }
+    }
+}
+
+/// Finds the in-flight transfer whose tag and cloud name encode to the given bytes
+/// Lets names handed back by plugins be matched without decoding bytes they may have altered
+pub fn running(tag: &[u8], cloud_name: &[u8]) -> Option<(String, OsString)> {
+    TRANSFERS
+        .lock()
+        .unwrap()
+        .keys()
+        .find(|(t, name)| t.as_bytes() == tag && name.as_encoded_bytes() == cloud_name)
+        .cloned()
+}
+
+/// Cancels the transfer of `cloud_name`, false if none is in flight
+pub fn cancel(tag: &str, cloud_name: &OsStr) -> bool {
+    TRANSFERS
+        .lock()
+        .unwrap()
+        .get(&(tag.to_owned(), cloud_name.to_owned()))
+        .inspect(|token| token.cancel())
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guarded_reader_fails_once_cancelled() {
+        let token = CancelToken::default();
+        let mut reader = token.guard(&b"archive"[..]);
+        let mut buffer = [0; 3];
+
+        assert_eq!(reader.read(&mut buffer).unwrap(), 3);
+        token.cancel();
+        assert!(reader.read(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn cancels_the_newest_transfer_of_a_file() {
+        let item = OsStr::new("cancellation-test");
+        let first = Transfer::begin("tag", item);
+        let second = Transfer::begin("tag", item);
+
+        // the replaced transfer leaves the newer one registered
+        drop(first);
+        assert!(cancel("tag", item));
+        assert!(second.is_cancelled());
+
+        drop(second);
+        assert!(!cancel("tag", item));
+    }
+
+    #[test]
+    fn finds_running_transfers_by_their_bytes() {
+        let item = OsStr::new("running-test");
+        let transfer = Transfer::begin("tag", item);
+
+        assert_eq!(
+            running(b"tag", b"running-test"),
+            Some(("tag".to_owned(), item.to_owned()))
+        );
+        assert_eq!(running(b"tag", b"running-test\xff"), None);
+
+        drop(transfer);
+        assert_eq!(running(b"tag", b"running-test"), None);
+    }
+}
@@ -1,25 +1,56 @@
 use std::{
-    ffi::OsString, fs, path::{Path, PathBuf}
+    env::consts::DLL_EXTENSION,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
 };
 
 use super::fs_utils::FolderItems;
 
+/// Lists files in the plugins folder with the platform's library extension
+/// (.dll on Windows, .so on Linux, .dylib on macOS)
 pub fn get_pluginfiles() -> Vec<OsString> {
     let path = plugin();
     path.get_files()
         .expect(&format!("Unable to read {}", path.to_string_lossy()))
         .iter()
         .map(fs::DirEntry::file_name)
+        .filter(|name| Path::new(name).extension() == Some(DLL_EXTENSION.as_ref()))
         .collect()
 }
 
-#[cfg(not(debug_assertions))]
+/// PathBuf pointing to %APPDATA%
+#[cfg(all(not(debug_assertions), windows))]
 pub fn appdata() -> PathBuf {
     use std::env;
     Path::new(&env::var("APPDATA").expect("Unable to find APPDATA environment variable")).into()
 }
 
-/// PathBuf pointing to %APPDATA%
+/// PathBuf pointing to ~/Library/Application Support
+#[cfg(all(not(debug_assertions), target_os = "macos"))]
+pub fn appdata() -> PathBuf {
+    use std::env;
+    Path::new(&env::var("HOME").expect("Unable to find HOME environment variable"))
+        .join("Library")
+        .join("Application Support")
+}
+
+/// PathBuf pointing to $XDG_CONFIG_HOME, falling back to ~/.config
+#[cfg(all(not(debug_assertions), unix, not(target_os = "macos")))]
+pub fn appdata() -> PathBuf {
+    use std::env;
+    env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            Path::new(&env::var("HOME").expect("Unable to find HOME environment variable"))
+                .join(".config")
+        })
+}
+
+/// PathBuf pointing to the repository root during development
 #[cfg(debug_assertions)]
 pub fn appdata() -> PathBuf {
     Path::new("..").into()
@@ -41,14 +72,34 @@ pub fn plugin() -> PathBuf {
 }
 
 /// PathBuf pointing to logs folder in SaveSync
-// pub fn logs() -> PathBuf {
-//     create_dir_if_not_exist(config().join("logs"))
-// }
+pub fn logs() -> PathBuf {
+    create_dir_if_not_exist(config().join("logs"))
+}
 
 pub fn temp(tag: &str) -> PathBuf {
     create_dir_if_not_exist(config().join("temp").join(tag))
 }
 
+/// PathBuf pointing to staging folder in SaveSync, holding archives in transit
+pub fn staging() -> PathBuf {
+    create_dir_if_not_exist(config().join("staging"))
+}
+
+/// Unique PathBuf in the staging folder, the caller removes the file when done
+pub fn staging_file() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    staging().join(format!(
+        "{}-{}-{}",
+        process::id(),
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
 pub fn create_credential_path(mut plugin_name: OsString) -> PathBuf {
     plugin_name.push(".auth");
     creds().join(&plugin_name)
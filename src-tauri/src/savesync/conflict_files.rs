@@ -1,4 +1,9 @@
-use std::ffi::{OsStr, OsString};
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::PathBuf,
+    time::SystemTime,
+};
 
 use tauri_plugin_opener::OpenerExt;
 
@@ -10,29 +15,52 @@ use crate::{
 
 use super::{
     config_paths::temp,
+    fs_utils::last_modified,
+    sync_state::{self, hash_file},
     watch::{upload_file, watch_folder},
 };
 
-pub fn store_buffer(tag: &str, foldername: &OsStr, buffer: Vec<u8>) {
-    write_app_state(|s| s.buffers.insert((tag.into(), foldername.into()), buffer));
+pub fn store_buffer(tag: &str, foldername: &OsStr, archive: PathBuf, cloud_date: SystemTime) {
+    write_app_state(|s| {
+        s.buffers
+            .insert((tag.into(), foldername.into()), (archive, cloud_date))
+    });
 }
 
-fn retrieve_buffer(tag: &str, foldername: &OsStr) -> Vec<u8> {
+fn retrieve_buffer(tag: &str, foldername: &OsStr) -> (PathBuf, SystemTime) {
     write_app_state(|s| s.buffers.remove(&(tag.into(), foldername.into())).unwrap())
 }
 
 pub fn resolve_conflict((tag, foldername, resolution): (String, OsString, String)) {
     let fileinfo = strip_zip_extension(&foldername);
+    let (buf, cloud_date) = retrieve_buffer(&tag, &foldername);
     if resolution == "local" {
+        let _ = fs::remove_file(buf);
         upload_file(&tag, &fileinfo);
         watch_folder(&tag, &fileinfo.value());
         return;
     }
-    let buf = retrieve_buffer(&tag, &foldername);
 
+    let hash = hash_file(&buf);
+    let local_path = app_store().resolve_path(&tag, fileinfo.value());
     if resolution == "cloud" {
         handle_buffer(app_store().get_mapping(&tag).unwrap(), &fileinfo, buf);
+        sync_state::record(
+            &tag,
+            &foldername,
+            last_modified(&local_path),
+            cloud_date,
+            hash,
+        );
     } else if resolution == "none" {
+        // merged by hand, so the next local change is uploaded over the cloud copy
+        sync_state::record(
+            &tag,
+            &foldername,
+            last_modified(&local_path),
+            cloud_date,
+            hash,
+        );
         let path = temp(&tag);
         handle_buffer(&path, &fileinfo, buf);
         app_handle()
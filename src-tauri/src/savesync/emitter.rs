@@ -3,26 +3,43 @@ use std::{ffi::OsStr, time::SystemTime};
 use serde::Serialize;
 use tauri::Emitter;
 
-use crate::app_handle;
+use crate::{
+    app_handle,
+    savesync::{
+        backend::{BackendError, DeviceCode, ErrorKind},
+        plugin_host,
+    },
+};
 
 pub fn plugin_error(title: &OsStr, description: &str) {
-    app_emit("plugin_error", (title, description))
+    if plugin_host::is_child() {
+        plugin_host::forward_error(&BackendError::new(ErrorKind::Other, description));
+    } else {
+        app_emit("plugin_error", (title, description))
+    }
+}
+
+/// Code of a device login, shown until the login went through
+pub fn device_code(title: &OsStr, code: &DeviceCode) {
+    app_emit("device_code", (title, code));
 }
 
 pub fn init_result() {
     app_emit("init_result", ());
 }
 
-/*
 pub fn abort_result(err: &str) {
     app_emit("abort_result", &err);
 }
-*/
 
 pub fn sync_result(tag: &str, foldername: &OsStr, synced: bool) {
     app_emit("sync_result", (tag, foldername, synced));
 }
 
+pub fn transfer_progress(tag: &str, foldername: &OsStr, done: u64, total: u64) {
+    app_emit("transfer_progress", (tag, foldername, done, total));
+}
+
 pub fn conflicting_files(tag: &str, foldername: &OsStr, diff: (SystemTime, SystemTime)) {
     app_emit("conflicting_files", (tag, foldername, diff));
 }
@@ -1,21 +1,23 @@
 use std::{
-    fs::{read_dir, DirEntry, FileType},
+    cmp::max,
+    fs::{self, read_dir, DirEntry, FileType},
     io,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 /// fs_utils internal representation of a Result<Vec<DirEntry>, std::io::Error>
 type DirResult = Result<Vec<DirEntry>, std::io::Error>;
 
 pub trait FolderItems {
-    // fn get_folders(&self) -> DirResult;
+    fn get_folders(&self) -> DirResult;
     fn get_files(&self) -> DirResult;
 }
 
 impl FolderItems for Path {
-    // fn get_folders(&self) -> DirResult {
-    //     iter_dir_entries(self, FileType::is_dir)
-    // }
+    fn get_folders(&self) -> DirResult {
+        iter_dir_entries(self, FileType::is_dir)
+    }
 
     fn get_files(&self) -> DirResult {
         iter_dir_entries(self, FileType::is_file)
@@ -34,6 +36,24 @@ where
         .collect()
 }
 
+/// Newest modification time of the files under `path`, or of `path` itself if it is a file
+/// UNIX_EPOCH when nothing is there
+pub fn last_modified(path: &Path) -> SystemTime {
+    if path.is_file() {
+        return fs::metadata(path)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+    }
+
+    recurse_directories(
+        path,
+        SystemTime::UNIX_EPOCH,
+        &mut |_, _, e| e.metadata()?.modified(),
+        &max,
+    )
+    .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
 pub fn recurse_directories<V, F, A>(
     path: &Path,
     dvalue: V,
@@ -0,0 +1,127 @@
+use ed25519_dalek::{Signature, VerifyingKey};
+use savesync_plugin_abi::{ABI_VERSION, MIN_ABI_VERSION};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::app_store;
+
+use super::{backend::PluginInfo, config_paths};
+
+/// Describes a plugin library without loading it
+/// Lives next to the library as `<library>.manifest.json`
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    pub abi_version: u32,
+    /// Hex encoded SHA-256 of the library file
+    pub sha256: String,
+    /// Hex encoded ed25519 key the manifest was signed with
+    pub publisher_key: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub icon_url: String,
+}
+
+impl Manifest {
+    pub fn info(&self, filename: &OsStr) -> PluginInfo {
+        PluginInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            author: self.author.clone(),
+            icon_url: self.icon_url.clone(),
+            filename: filename.to_owned(),
+        }
+    }
+}
+
+/// `<library>.manifest.json`
+pub fn manifest_path(library: &Path) -> PathBuf {
+    sidecar(library, ".manifest.json")
+}
+
+/// `<library>.manifest.sig`, the hex encoded signature of the manifest file's bytes
+pub fn signature_path(library: &Path) -> PathBuf {
+    sidecar(library, ".manifest.sig")
+}
+
+fn sidecar(library: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(library);
+    path.push(suffix);
+    path.into()
+}
+
+/// Reads the manifest once its signature checks out against a trusted publisher key
+/// Only the sidecar files are read, so this is safe to run while listing plugins
+pub fn read_manifest(library: &Path) -> Result<Manifest, String> {
+    let bytes = fs::read(manifest_path(library))
+        .map_err(|e| format!("Unable to read plugin manifest: {e}"))?;
+    let manifest: Manifest =
+        serde_json::from_slice(&bytes).map_err(|e| format!("Plugin manifest is malformed: {e}"))?;
+
+    if !app_store()
+        .trusted_keys()
+        .iter()
+        .any(|key| key.eq_ignore_ascii_case(&manifest.publisher_key))
+    {
+        return Err(format!(
+            "Plugin publisher {} is not trusted",
+            manifest.publisher_key
+        ));
+    }
+
+    let key = decode::<32>(&manifest.publisher_key)
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or("Plugin publisher key is invalid")?;
+    let signature = fs::read_to_string(signature_path(library))
+        .ok()
+        .and_then(|s| decode::<64>(s.trim()))
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or("Plugin manifest signature is missing or invalid")?;
+
+    key.verify_strict(&bytes, &signature)
+        .map_err(|_| "Plugin manifest signature does not match".to_owned())?;
+
+    Ok(manifest)
+}
+
+/// Checks the manifest and that the library is the one it describes
+/// Must pass before the library is loaded
+pub fn verify(library: &Path) -> Result<Manifest, String> {
+    let manifest = read_manifest(library)?;
+
+    if !(MIN_ABI_VERSION..=ABI_VERSION).contains(&manifest.abi_version) {
+        return Err(format!(
+            "{} {} was built for ABI version {}, but SaveSync supports versions {MIN_ABI_VERSION} to {ABI_VERSION}",
+            manifest.name, manifest.version, manifest.abi_version
+        ));
+    }
+
+    let checksum = sha256_file(library).map_err(|e| e.to_string())?;
+    if !checksum.eq_ignore_ascii_case(&manifest.sha256) {
+        return Err("Plugin file does not match the checksum in its manifest".into());
+    }
+
+    Ok(manifest)
+}
+
+/// Verifies the plugin installed as `filename`
+pub fn verify_plugin(filename: &OsStr) -> Result<Manifest, String> {
+    verify(&config_paths::plugin().join(filename))
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    Ok(hex::encode(Sha256::digest(fs::read(path)?)))
+}
+
+fn decode<const N: usize>(hex_str: &str) -> Option<[u8; N]> {
+    hex::decode(hex_str).ok()?.try_into().ok()
+}
@@ -0,0 +1,146 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+/// A browser login waiting for its redirect
+pub struct PendingLogin {
+    /// Port of the local redirect server, None when the redirect comes as a savesync://auth link
+    pub port: Option<u16>,
+    state: String,
+    verifier: String,
+}
+
+impl PendingLogin {
+    pub fn new(port: Option<u16>) -> PendingLogin {
+        PendingLogin {
+            port,
+            state: random_string(16),
+            // 32 bytes encode to 43 characters, the shortest verifier RFC 7636 allows
+            verifier: random_string(32),
+        }
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub fn verifier(&self) -> &str {
+        &self.verifier
+    }
+
+    /// S256 challenge of the verifier
+    pub fn challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(&self.verifier))
+    }
+
+    /// Ties the login to the URL the backend built
+    /// A backend given the state must have put it in the URL, older plugins are not given
+    /// one, so it replaces any state of their own
+    pub fn bind(&self, url: String, sets_state: bool) -> Result<String, String> {
+        let mut parsed = Url::parse(&url).map_err(|e| format!("Login URL is invalid: {e}"))?;
+
+        if sets_state {
+            return match query_state(&parsed) {
+                Some(state) if state == self.state => Ok(url),
+                _ => Err("Login URL does not carry the state SaveSync gave the plugin".into()),
+            };
+        }
+
+        let pairs: Vec<(String, String)> = parsed
+            .query_pairs()
+            .filter(|(key, _)| key != "state")
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        parsed
+            .query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair("state", &self.state);
+        Ok(parsed.into())
+    }
+
+    /// Whether the redirect `url` carries the state of this login
+    pub fn accepts(&self, url: &str) -> bool {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| query_state(&url))
+            .is_some_and(|state| state == self.state)
+    }
+}
+
+fn query_state(url: &Url) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == "state")
+        .map(|(_, value)| value.into_owned())
+}
+
+fn random_string(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login() -> PendingLogin {
+        PendingLogin {
+            port: None,
+            state: "expected".into(),
+            verifier: "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".into(),
+        }
+    }
+
+    #[test]
+    fn challenge_is_s256_of_the_verifier() {
+        // example of RFC 7636 appendix B
+        assert_eq!(
+            login().challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn url_must_carry_the_given_state() {
+        let login = login();
+        let url = "https://auth.example/authorize?client_id=id&state=expected";
+        assert_eq!(login.bind(url.into(), true).unwrap(), url);
+
+        for url in [
+            "https://auth.example/authorize?client_id=id&state=forged",
+            "https://auth.example/authorize?client_id=id",
+            "not a url",
+        ] {
+            assert!(login.bind(url.into(), true).is_err(), "{url}");
+        }
+        assert_eq!(login.state(), "expected");
+    }
+
+    #[test]
+    fn older_plugins_get_the_state_added() {
+        let login = login();
+        let url = login
+            .bind(
+                "https://auth.example/authorize?client_id=id&state=own".into(),
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            url,
+            "https://auth.example/authorize?client_id=id&state=expected"
+        );
+        assert_eq!(login.state(), "expected");
+    }
+
+    #[test]
+    fn only_the_login_state_is_accepted() {
+        let login = login();
+        assert!(login.accepts("http://localhost:1/?code=c&state=expected"));
+        assert!(login.accepts("savesync://auth?code=c&state=expected"));
+        assert!(!login.accepts("http://localhost:1/?code=c&state=other"));
+        assert!(!login.accepts("http://localhost:1/?code=c"));
+    }
+}
@@ -1,40 +1,112 @@
 use libloading::{Library, Symbol};
-use serde::{Deserialize, Serialize};
+use savesync_plugin_abi::{
+    ABI_VERSION, DLLFileDetails, DLLFileDetailsV1, DLLHandle, DLLString, MIN_ABI_VERSION, symbols,
+};
+use serde_json::Value;
 use std::{
+    cell::RefCell,
     ffi::{CStr, CString, OsStr, OsString, c_char},
-    fs,
-    time::{Duration, SystemTime},
+    io::{self, Read, Write},
+    path::Path,
+    slice,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::savesync::emitter;
 
-use super::config_paths;
+use super::{
+    backend::{
+        BackendError, Capabilities, CloudBackend, DeviceCode, DevicePoll, ErrorKind, FileDetails,
+        PluginInfo, download_buffered, report_error, upload_buffered, with_backoff,
+    },
+    config_paths, plugin_log, progress,
+    vault::{self, VaultState},
+    watchdog::{self, Watch},
+};
+
+/// Size of the chunks streamed to and from plugins
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// How long read_cloud reuses a listing before asking the plugin again
+const LISTING_TTL: Duration = Duration::from_secs(60);
+
+/// How long before they expire credentials are refreshed
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Opaque handle of an in-flight streaming transfer, owned by the plugin
+pub struct StreamHandle(DLLHandle);
+
+/// States of a watched call
+const RUNNING: u8 = 0;
+const RETURNED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+
+thread_local! {
+    /// Stops the caller waiting on the call running on this worker thread, see InProcess
+    static ABANDON: RefCell<Option<Arc<dyn Fn() + Send + Sync>>> = const { RefCell::new(None) };
+}
+
+/// Watched call into the library
+/// A call that timed out holds the plugin up until it returns after all
+struct Call {
+    watch: Watch,
+    state: Arc<AtomicU8>,
+    hung: Arc<AtomicUsize>,
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if self
+            .state
+            .compare_exchange(RUNNING, RETURNED, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            self.hung.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
 
-type DLLString = *const c_char;
-type DLLFileDetails = *const (DLLString, DLLString, u64, DLLString);
+/// Cloud listing reused between read_cloud calls
+#[derive(Default)]
+struct Listing {
+    details: Vec<FileDetails>,
+    /// When the details were read, None once they went stale
+    fetched: Option<Instant>,
+    /// Position in the plugin's change feed, only with Capabilities::CHANGES
+    cursor: Option<String>,
+}
+
+/// Saved credentials of the plugin
+#[derive(Default)]
+struct Credentials {
+    token: Option<String>,
+    /// As reported by the plugin, None when they do not expire or it cannot refresh them
+    expires_at: Option<SystemTime>,
+}
 
 pub struct Plugin {
     library: Library,
     filename: OsString,
-    credentials: Option<String>,
-    details: Option<Vec<FileDetails>>,
-}
-
-/// Gets file's last modified date
-/// Plugin developers can optionally attach the
-/// file buffer to reduce API calls where possible
-#[derive(Clone)]
-pub struct FileDetails {
-    pub tag: String,
-    pub folder_name: OsString,
-    pub last_modified: SystemTime,
-    pub data: Option<Vec<u8>>,
+    /// Held while refreshing, so concurrent calls wait for the new credentials
+    credentials: Mutex<Credentials>,
+    listing: Mutex<Listing>,
+    capabilities: Capabilities,
+    abi_version: u32,
+    /// Calls past their timeout that are still running, no calls are made while there are any
+    /// as a thread stuck in the library cannot be stopped
+    hung: Arc<AtomicUsize>,
 }
 
 impl Plugin {
     unsafe fn free_string(&self, raw_str: DLLString) {
         unsafe {
-            if let Some(f) = self.get_function::<unsafe extern "C" fn(DLLString)>(b"free_string") {
+            if let Some(f) = self.get_function::<symbols::FreeString>(b"free_string") {
                 f(raw_str)
             }
         }
@@ -55,16 +127,37 @@ impl Plugin {
         }
     }
 
-    unsafe fn test_error_empty(&self, raw_str: DLLString) -> bool {
-        unsafe {
-            self.create_string(raw_str)
-                .map(|e| self.emit_error(e))
-                .is_none()
+    /// Turns a returned error string into a BackendError, reading its kind before it is freed
+    /// Plugins without an `error_kind` export only report ErrorKind::Other
+    unsafe fn take_error(&self, raw_str: DLLString) -> Result<(), BackendError> {
+        if raw_str.is_null() {
+            return Ok(());
         }
+
+        let kind = self
+            .lookup::<symbols::ErrorKind>(b"error_kind")
+            .map_or(ErrorKind::Other, |f| {
+                ErrorKind::from_code(unsafe { f(raw_str) })
+            });
+        let message = unsafe { self.create_string(raw_str) }.unwrap_or_default();
+        Err(BackendError::new(kind, message))
+    }
+
+    unsafe fn test_error_empty(&self, raw_str: DLLString) -> bool {
+        unsafe { self.reported(self.take_error(raw_str)) }.is_some()
+    }
+
+    fn reported<T>(&self, result: Result<T, BackendError>) -> Option<T> {
+        result.inspect_err(|e| report_error(&self.filename, e)).ok()
     }
 
     fn credentials(&self) -> String {
-        self.credentials.clone().unwrap_or_default()
+        self.credentials
+            .lock()
+            .unwrap()
+            .token
+            .clone()
+            .unwrap_or_default()
     }
 
     pub fn filename(&self) -> OsString {
@@ -76,44 +169,571 @@ impl Plugin {
     }
 
     fn get_function<T>(&self, symbol: &[u8]) -> Option<Symbol<'_, T>> {
-        unsafe {
-            self.library
-                .get::<T>(symbol)
-                .inspect_err(|_| {
-                    self.emit_error(format!(
-                        "{} function not found",
-                        String::from_utf8_lossy(symbol)
-                    ))
-                })
-                .ok()
+        self.reported(self.require(symbol))
+    }
+
+    /// Looks up a symbol the call cannot do without, leaving the reporting to the caller
+    fn require<T>(&self, symbol: &[u8]) -> Result<Symbol<'_, T>, BackendError> {
+        self.lookup(symbol).ok_or_else(|| {
+            BackendError::new(
+                ErrorKind::Other,
+                format!("{} function not found", String::from_utf8_lossy(symbol)),
+            )
+        })
+    }
+
+    /// Looks up an optional symbol without reporting it as missing
+    fn lookup<T>(&self, symbol: &[u8]) -> Option<Symbol<'_, T>> {
+        unsafe { self.library.get::<T>(symbol).ok() }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    /// Watches a call into the library, None while an earlier call hangs
+    fn watch(&self, call: &'static str) -> Option<Call> {
+        if self.hung.load(Ordering::Acquire) > 0 {
+            return None;
+        }
+
+        let state = Arc::new(AtomicU8::new(RUNNING));
+        let hung = self.hung.clone();
+        let filename = self.filename();
+        let abandon = ABANDON.with_borrow(Clone::clone);
+        let watch = watchdog::watch(&self.filename, call, {
+            let (state, hung) = (state.clone(), hung.clone());
+            move || {
+                if state
+                    .compare_exchange(RUNNING, TIMED_OUT, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                {
+                    hung.fetch_add(1, Ordering::AcqRel);
+                    emitter::plugin_error(
+                        &filename,
+                        "Plugin calls are stopped until the hung call returns, turn on plugin isolation to have hung plugins restarted instead",
+                    );
+                    if let Some(abandon) = abandon {
+                        abandon();
+                    }
+                }
+            }
+        });
+        Some(Call { watch, state, hung })
+    }
+
+    /// Watches a call transferring the file, which times out once it stops making progress
+    fn watch_transfer(&self, call: &'static str, tag: &[u8], folder_name: &[u8]) -> Option<Call> {
+        let call = self.watch(call)?;
+        call.watch.transferring(tag, folder_name);
+        Some(call)
+    }
+
+    /// Checks the plugin's ABI version and reads its capabilities
+    /// Plugins without a `capabilities` export have them inferred from their symbols
+    fn negotiate(&mut self) -> Result<(), String> {
+        let version = self
+            .lookup::<symbols::AbiVersion>(b"abi_version")
+            .map_or(1, |f| unsafe { f() });
+
+        if !(MIN_ABI_VERSION..=ABI_VERSION).contains(&version) {
+            return Err(format!(
+                "Plugin was built for ABI version {version}, but SaveSync supports versions {MIN_ABI_VERSION} to {ABI_VERSION}"
+            ));
+        }
+        self.abi_version = version;
+
+        let exported: [(Capabilities, &[&[u8]]); 7] = [
+            (Capabilities::REMOVE, &[b"remove"]),
+            (Capabilities::ABORT, &[b"abort"]),
+            (
+                Capabilities::STREAMING,
+                &[
+                    b"upload_open",
+                    b"upload_write",
+                    b"upload_finish",
+                    b"download_open",
+                    b"download_read",
+                    b"download_close",
+                ],
+            ),
+            (Capabilities::PROGRESS, &[b"set_progress_callback"]),
+            (Capabilities::CHANGES, &[b"read_changes"]),
+            (Capabilities::LOGGING, &[b"set_log_callback"]),
+            (Capabilities::SETTINGS, &[b"settings_schema"]),
+        ];
+        let missing = |symbols: &[&[u8]]| {
+            symbols
+                .iter()
+                .find(|symbol| self.lookup::<*const ()>(symbol).is_none())
+                .map(|symbol| String::from_utf8_lossy(symbol).into_owned())
+        };
+
+        let mut capabilities = match self.lookup::<symbols::Capabilities>(b"capabilities") {
+            Some(f) => Capabilities::from_bits(unsafe { f() }),
+            None => exported
+                .iter()
+                .filter(|(_, symbols)| missing(symbols).is_none())
+                .fold(Capabilities::INLINE_DATA, |caps, (cap, _)| caps | *cap),
+        };
+
+        // version 1 data stops at the first NUL byte, which corrupts archives
+        if version < 2 {
+            capabilities = capabilities.without(Capabilities::INLINE_DATA);
         }
+
+        let result = exported
+            .iter()
+            .filter(|(cap, _)| capabilities.contains(*cap))
+            .find_map(|(_, symbols)| missing(symbols))
+            .map_or(Ok(()), |symbol| {
+                Err(format!("Plugin advertises {symbol} but does not export it"))
+            });
+
+        self.capabilities = capabilities;
+        result
     }
 
     pub unsafe fn new(servicename: &OsStr) -> Option<Plugin> {
-        let library: Library = unsafe {
-            let library =
-                libloading::os::windows::Library::new(config_paths::plugin().join(servicename))
-                    .inspect_err(|e| emitter::plugin_error(servicename, &e.to_string()))
-                    .ok()?;
-            library
-                .pin()
-                .inspect_err(|e| emitter::plugin_error(servicename, &e.to_string()))
-                .ok()?;
-            library.into()
-        };
+        let library = unsafe { load_library(&config_paths::plugin().join(servicename)) }
+            .inspect_err(|e| emitter::plugin_error(servicename, &e.to_string()))
+            .ok()?;
 
-        Some(Plugin {
+        let mut plugin = Plugin {
             library,
             filename: servicename.to_owned(),
-            credentials: Plugin::read_creds(servicename),
-            details: None,
+            credentials: Mutex::new(Credentials::default()),
+            listing: Mutex::new(Listing::default()),
+            capabilities: Capabilities::from_bits(0),
+            abi_version: MIN_ABI_VERSION,
+            hung: Arc::new(AtomicUsize::new(0)),
+        };
+
+        plugin
+            .negotiate()
+            .inspect_err(|e| emitter::plugin_error(servicename, e))
+            .ok()?;
+
+        if plugin.supports(Capabilities::PROGRESS)
+            && let Some(f) = plugin.lookup::<symbols::SetProgressCallback>(b"set_progress_callback")
+        {
+            unsafe { f(host_progress) };
+        }
+
+        if plugin.supports(Capabilities::LOGGING)
+            && let Some(f) = plugin.lookup::<symbols::SetLogCallback>(b"set_log_callback")
+        {
+            let context = plugin_log::register(servicename);
+            unsafe { f(host_log, context as DLLHandle) };
+        }
+
+        if let Some(credentials) = Plugin::read_creds(servicename) {
+            let _watch = plugin.watch("credentials_expiry")?;
+            let expires_at = plugin.expiry(&credentials);
+            *plugin.credentials.get_mut().unwrap() = Credentials {
+                token: Some(credentials),
+                expires_at,
+            };
+        }
+
+        Some(plugin)
+    }
+
+    /// Calls `read_cloud` with the file details layout `D` of the plugin's ABI version
+    unsafe fn read_cloud_as<D>(
+        &self,
+        access_token: &CStr,
+        parse: impl Fn(&D) -> (DLLString, DLLString, u64, Option<Vec<u8>>),
+    ) -> Result<Vec<FileDetails>, BackendError> {
+        unsafe {
+            let (ptr, count, possible_err) =
+                self.require::<symbols::ReadCloud<D>>(b"read_cloud")?(access_token.as_ptr());
+
+            self.take_error(possible_err)?;
+            self.take_details(ptr, count, parse)
+        }
+    }
+
+    /// Copies the details and hands them back through `free_file_details`
+    unsafe fn take_details<D>(
+        &self,
+        ptr: *const D,
+        count: u64,
+        parse: impl Fn(&D) -> (DLLString, DLLString, u64, Option<Vec<u8>>),
+    ) -> Result<Vec<FileDetails>, BackendError> {
+        unsafe {
+            let details = (0..count as usize)
+                .map(|i| {
+                    let (tag, folder_name, date, data) = parse(&*ptr.add(i));
+                    FileDetails {
+                        tag: self.create_string(tag).unwrap(),
+                        folder_name: self.create_string(folder_name).unwrap().into(),
+                        last_modified: SystemTime::UNIX_EPOCH + Duration::from_secs(date),
+                        data: data.filter(|_| self.supports(Capabilities::INLINE_DATA)),
+                    }
+                })
+                .collect();
+
+            self.require::<symbols::FreeFileDetails<D>>(b"free_file_details")?(count, ptr);
+
+            Ok(details)
+        }
+    }
+
+    /// Lists the whole cloud
+    fn read_listing(&self) -> Result<Vec<FileDetails>, BackendError> {
+        let _watch = self.watch("read_cloud").ok_or_else(unresponsive)?;
+
+        self.authorized(|access_token| {
+            with_backoff(|| unsafe {
+                if self.abi_version >= 2 {
+                    self.read_cloud_as::<DLLFileDetails>(access_token, |detail| {
+                        parse_details(detail)
+                    })
+                } else {
+                    self.read_cloud_as::<DLLFileDetailsV1>(access_token, |detail| {
+                        (detail.0, detail.1, detail.2, None)
+                    })
+                }
+            })
         })
     }
 
-    pub fn info(&self) -> Option<PluginInfo> {
-        let ptr = unsafe {
-            self.get_function::<unsafe extern "C" fn() -> (DLLString, DLLString, DLLString, DLLString)>(b"info")?()
+    /// Lists the files changed since `cursor` with the cursor to continue from,
+    /// removed files are listed with a last_modified of UNIX_EPOCH
+    fn read_changes(&self, cursor: &str) -> Result<(Vec<FileDetails>, String), BackendError> {
+        let cursor = CString::new(cursor).unwrap_or_default();
+        let _watch = self.watch("read_changes").ok_or_else(unresponsive)?;
+
+        self.authorized(|access_token| {
+            with_backoff(|| unsafe {
+                let (ptr, count, next, possible_err) = self
+                    .require::<symbols::ReadChanges>(b"read_changes")?(
+                    access_token.as_ptr(),
+                    cursor.as_ptr(),
+                );
+
+                self.take_error(possible_err)?;
+                let details =
+                    self.take_details::<DLLFileDetails>(ptr, count, |d| parse_details(d))?;
+                Ok((details, self.create_string(next).unwrap_or_default()))
+            })
+        })
+    }
+
+    pub fn upload_open(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        len: u64,
+    ) -> Option<StreamHandle> {
+        let tagname = CString::new(tag).unwrap_or_default();
+        let filename = CString::new(folder_name).unwrap_or_default();
+        let _watch = self.watch("upload_open")?;
+
+        self.reported(self.authorized(|access_token| {
+            with_backoff(|| unsafe {
+                let (handle, possible_err) = self.require::<symbols::UploadOpen>(b"upload_open")?(
+                    access_token.as_ptr(),
+                    tagname.as_ptr(),
+                    filename.as_ptr(),
+                    date.duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs(),
+                    len,
+                );
+
+                self.take_error(possible_err).map(|_| StreamHandle(handle))
+            })
+        }))
+    }
+
+    pub fn upload_write(&self, handle: &StreamHandle, chunk: &[u8]) -> bool {
+        let Some(_watch) = self.watch("upload_write") else {
+            return false;
+        };
+
+        unsafe {
+            self.get_function::<symbols::UploadWrite>(b"upload_write")
+                .map(|f| f(handle.0, chunk.as_ptr() as DLLString, chunk.len() as u64))
+                .is_some_and(|ptr| self.test_error_empty(ptr))
+        }
+    }
+
+    /// Completes the upload, or discards it if `commit` is false
+    pub fn upload_finish(&self, handle: StreamHandle, commit: bool) -> bool {
+        if commit {
+            self.invalidate_listing();
+        }
+        let Some(_watch) = self.watch("upload_finish") else {
+            return false;
+        };
+
+        unsafe {
+            self.get_function::<symbols::UploadFinish>(b"upload_finish")
+                .map(|f| f(handle.0, commit))
+                .is_some_and(|ptr| self.test_error_empty(ptr))
+        }
+    }
+
+    pub fn download_open(&self, tag: &[u8], folder_name: &[u8]) -> Option<StreamHandle> {
+        let tagname = CString::new(tag).unwrap_or_default();
+        let filename = CString::new(folder_name).unwrap_or_default();
+        let _watch = self.watch("download_open")?;
+
+        self.reported(self.authorized(|access_token| {
+            with_backoff(|| unsafe {
+                let (handle, possible_err) = self
+                    .require::<symbols::DownloadOpen>(b"download_open")?(
+                    access_token.as_ptr(),
+                    tagname.as_ptr(),
+                    filename.as_ptr(),
+                );
+
+                self.take_error(possible_err).map(|_| StreamHandle(handle))
+            })
+        }))
+    }
+
+    /// Fills `chunk` with the next bytes of the download, 0 marks the end
+    pub fn download_read(&self, handle: &StreamHandle, chunk: &mut [u8]) -> Option<usize> {
+        let _watch = self.watch("download_read")?;
+
+        unsafe {
+            let (read, possible_err) = self
+                .get_function::<symbols::DownloadRead>(b"download_read")?(
+                handle.0,
+                chunk.as_mut_ptr() as *mut c_char,
+                chunk.len() as u64,
+            );
+
+            self.test_error_empty(possible_err)
+                .then_some(read.min(chunk.len() as u64) as usize)
+        }
+    }
+
+    pub fn download_close(&self, handle: StreamHandle) {
+        let Some(_watch) = self.watch("download_close") else {
+            return;
+        };
+
+        unsafe {
+            if let Some(f) = self.get_function::<symbols::DownloadClose>(b"download_close") {
+                f(handle.0)
+            }
+        }
+    }
+
+    fn read_creds(filename: &OsStr) -> Option<String> {
+        vault::read(filename)
+    }
+
+    /// Keeps the credentials for this session and saves them, false if they could not be saved
+    fn write_creds(&self, credentials: &str) -> bool {
+        let expires_at = self.expiry(credentials);
+        *self.credentials.lock().unwrap() = Credentials {
+            token: Some(credentials.into()),
+            expires_at,
+        };
+
+        vault::write(&self.filename, credentials)
+            .inspect_err(|e| match vault::state() {
+                VaultState::Locked => self.emit_error(
+                    "Unlock the saved credentials in Settings, then log in again".into(),
+                ),
+                _ => self.emit_error(format!("Unable to save credentials: {e}")),
+            })
+            .is_ok()
+    }
+
+    /// When the plugin says `credentials` expire, None without Capabilities::REFRESH
+    fn expiry(&self, credentials: &str) -> Option<SystemTime> {
+        if !self.supports(Capabilities::REFRESH) {
+            return None;
+        }
+        let credentials = CString::new(credentials).unwrap_or_default();
+
+        let secs = unsafe {
+            self.lookup::<symbols::CredentialsExpiry>(b"credentials_expiry")?(credentials.as_ptr())
+        };
+        (secs != 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Runs `call` with the credentials, refreshing them first when they are about to expire
+    /// and once more when the service rejects them
+    fn authorized<T>(
+        &self,
+        mut call: impl FnMut(&CStr) -> Result<T, BackendError>,
+    ) -> Result<T, BackendError> {
+        if !self.supports(Capabilities::REFRESH) {
+            return call(&CString::new(self.credentials()).unwrap_or_default());
+        }
+
+        let (token, expiring) = {
+            let credentials = self.credentials.lock().unwrap();
+            let expiring = credentials
+                .expires_at
+                .is_some_and(|at| at <= SystemTime::now() + REFRESH_MARGIN);
+            (credentials.token.clone().unwrap_or_default(), expiring)
+        };
+        if expiring {
+            self.refresh(&token)?;
+        }
+
+        let token = self.credentials();
+        match call(&CString::new(token.as_str()).unwrap_or_default()) {
+            Err(e) if e.kind == ErrorKind::Auth => {
+                self.refresh(&token)?;
+                call(&CString::new(self.credentials()).unwrap_or_default())
+            }
+            result => result,
+        }
+    }
+
+    /// Trades `stale` for new credentials, unless another call already did
+    /// Failures are Auth errors, so reporting them asks the user to log in again
+    fn refresh(&self, stale: &str) -> Result<(), BackendError> {
+        let mut credentials = self.credentials.lock().unwrap();
+        if credentials.token.as_deref().unwrap_or_default() != stale {
+            return Ok(());
+        }
+
+        let stale = CString::new(stale).unwrap_or_default();
+        let (refreshed, err) =
+            unsafe { self.require::<symbols::Refresh>(b"refresh")?(stale.as_ptr()) };
+        let refreshed = unsafe { self.create_string(refreshed) };
+        let refreshed = unsafe { self.take_error(err) }
+            .and_then(|_| {
+                refreshed.ok_or_else(|| {
+                    BackendError::new(ErrorKind::Other, "refresh returned no credentials")
+                })
+            })
+            .map_err(|e| {
+                BackendError::new(
+                    ErrorKind::Auth,
+                    format!(
+                        "Login expired and could not be refreshed, please log in again: {}",
+                        e.message
+                    ),
+                )
+            })?;
+
+        vault::write(&self.filename, &refreshed)
+            .inspect_err(|e| self.emit_error(format!("Unable to save refreshed credentials: {e}")))
+            .ok();
+        *credentials = Credentials {
+            expires_at: self.expiry(&refreshed),
+            token: Some(refreshed),
+        };
+        Ok(())
+    }
+
+    /// CloudBackend's login calls take &mut self, these let InProcess make them on a worker thread
+    pub fn authenticate(&self, settings: &Value) -> bool {
+        let credentials = CString::new(self.credentials()).unwrap_or_default();
+        let settings = CString::new(settings.to_string()).unwrap_or_default();
+        let Some(_watch) = self.watch("authenticate") else {
+            return false;
+        };
+
+        let result = match self.abi_version >= 3 {
+            true => self
+                .get_function::<symbols::Authenticate>(b"authenticate")
+                .map(|f| unsafe { f(credentials.as_ptr(), settings.as_ptr()) }),
+            false => self
+                .get_function::<symbols::AuthenticateV2>(b"authenticate")
+                .map(|f| unsafe { f(credentials.as_ptr()) }),
+        };
+        result.is_some_and(|(new_token, err)| unsafe {
+            self.create_string(new_token)
+                .map(|creds| self.write_creds(&creds));
+            err.is_null()
+        })
+    }
+
+    pub fn process_save_credentials(&self, url: &str, code_verifier: &str) -> bool {
+        let cstring = CString::new(url).unwrap_or_default();
+        let code_verifier = CString::new(code_verifier).unwrap_or_default();
+        let Some(_watch) = self.watch("extract_credentials") else {
+            return false;
+        };
+
+        let extracted = unsafe {
+            match self.abi_version >= 4 {
+                true => self
+                    .get_function::<symbols::ExtractCredentials>(b"extract_credentials")
+                    .map(|f| f(cstring.as_ptr(), code_verifier.as_ptr())),
+                false => self
+                    .get_function::<symbols::ExtractCredentialsV3>(b"extract_credentials")
+                    .map(|f| f(cstring.as_ptr())),
+            }
+        };
+        let saved = unsafe {
+            extracted.is_some_and(|(res, possible_err)| {
+                let result = self.test_error_empty(possible_err);
+                match self.create_string(res) {
+                    Some(credentials) if result => self.write_creds(&credentials),
+                    _ => result,
+                }
+            })
+        };
+
+        // the new credentials may belong to another account
+        if saved {
+            *self.listing.lock().unwrap() = Listing::default();
+        }
+        saved
+    }
+
+    pub fn poll_device_login(&self, device_code: &str) -> DevicePoll {
+        if !self.supports(Capabilities::DEVICE_LOGIN) {
+            return DevicePoll::Failed;
+        }
+        let device_code = CString::new(device_code).unwrap_or_default();
+        let Some(_watch) = self.watch("poll_device_login") else {
+            return DevicePoll::Failed;
+        };
+
+        let Some((credentials, err)) = (unsafe {
+            self.get_function::<symbols::PollDeviceLogin>(b"poll_device_login")
+                .map(|f| f(device_code.as_ptr()))
+        }) else {
+            return DevicePoll::Failed;
         };
+        let credentials = unsafe { self.create_string(credentials) };
+
+        match unsafe { self.take_error(err) } {
+            Err(e) if e.kind == ErrorKind::RateLimited => DevicePoll::SlowDown,
+            // not reported as an auth error, which would start a browser login on top
+            Err(e) => {
+                self.emit_error(e.message);
+                DevicePoll::Failed
+            }
+            Ok(()) => match credentials {
+                None => DevicePoll::Pending,
+                Some(credentials) if self.write_creds(&credentials) => {
+                    *self.listing.lock().unwrap() = Listing::default();
+                    DevicePoll::LoggedIn
+                }
+                Some(_) => DevicePoll::Failed,
+            },
+        }
+    }
+}
+
+impl CloudBackend for Plugin {
+    fn supports(&self, capability: Capabilities) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    fn info(&self) -> Option<PluginInfo> {
+        let _watch = self.watch("info")?;
+        let ptr = unsafe { self.get_function::<symbols::Info>(b"info")?() };
 
         let (name, description, author, icon_url) = ptr;
 
@@ -127,203 +747,569 @@ impl Plugin {
             }
         };
 
-        unsafe {
-            self.get_function::<unsafe extern "C" fn((DLLString, DLLString, DLLString, DLLString))>(
-                b"free_info",
-            )?(ptr)
-        };
+        unsafe { self.get_function::<symbols::FreeInfo>(b"free_info")?(ptr) };
 
         Some(info)
     }
 
-    fn read_creds(filename: &OsStr) -> Option<String> {
-        fs::read_to_string(config_paths::create_credential_path(filename.to_owned())).ok()
+    fn authenticate(&mut self, settings: &Value) -> bool {
+        Plugin::authenticate(self, settings)
     }
 
-    fn write_creds(&mut self, credentials: &str) -> std::io::Result<()> {
-        self.credentials = Some(credentials.into());
+    fn settings_schema(&self) -> Option<Value> {
+        if !self.supports(Capabilities::SETTINGS) {
+            return None;
+        }
+        let _watch = self.watch("settings_schema")?;
 
-        fs::write(
-            config_paths::create_credential_path(self.filename()),
-            credentials,
-        )
+        let schema = unsafe {
+            self.create_string(self
+                .get_function::<symbols::SettingsSchema>(b"settings_schema")?(
+            ))
+        }?;
+        serde_json::from_str(&schema)
+            .inspect_err(|e| self.emit_error(format!("Invalid settings schema: {e}")))
+            .ok()
     }
 
-    pub fn authenticate(&mut self) -> bool {
-        let credentials = CString::new(self.credentials()).unwrap_or_default();
-
-        self.get_function::<unsafe extern "C" fn(DLLString) -> (DLLString, DLLString)>(
-            b"authenticate",
-        )
-        .map(|f| unsafe { f(credentials.as_ptr()) })
-        .is_some_and(|(new_token, err)| unsafe {
-            self.create_string(new_token)
-                .map(|creds| self.write_creds(&creds));
-            err.is_null()
-        })
+    fn sets_state(&self) -> bool {
+        self.abi_version >= 4
     }
 
-    pub fn auth_url(&self, redirect_uri: &str) -> Option<String> {
+    fn auth_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> Option<String> {
         let redirect_uri = CString::new(redirect_uri).unwrap_or_default();
+        let state = CString::new(state).unwrap_or_default();
+        let code_challenge = CString::new(code_challenge).unwrap_or_default();
+        let _watch = self.watch("auth_url")?;
 
-        unsafe {
-            self.create_string(self
-                .get_function::<unsafe extern "C" fn(DLLString) -> DLLString>(
-                    b"auth_url",
-                )?(redirect_uri.as_ptr()))
+        // older plugins build their URL without state, the host adds it to theirs
+        let url = unsafe {
+            match self.abi_version >= 4 {
+                true => self.get_function::<symbols::AuthUrl>(b"auth_url")?(
+                    redirect_uri.as_ptr(),
+                    state.as_ptr(),
+                    code_challenge.as_ptr(),
+                ),
+                false => {
+                    self.get_function::<symbols::AuthUrlV3>(b"auth_url")?(redirect_uri.as_ptr())
+                }
+            }
+        };
+        unsafe { self.create_string(url) }
+    }
+
+    fn process_save_credentials(&mut self, url: &str, code_verifier: &str) -> bool {
+        Plugin::process_save_credentials(self, url, code_verifier)
+    }
+
+    fn device_login(&self) -> Option<DeviceCode> {
+        if !self.supports(Capabilities::DEVICE_LOGIN) {
+            return None;
+        }
+        let _watch = self.watch("device_login")?;
+
+        let ((device_code, user_code, verification_uri, interval, expires_in), err) =
+            unsafe { self.get_function::<symbols::DeviceLogin>(b"device_login")?() };
+        let strings = unsafe {
+            [device_code, user_code, verification_uri].map(|raw| self.create_string(raw))
+        };
+        if !unsafe { self.test_error_empty(err) } {
+            return None;
         }
+
+        let [Some(device_code), Some(user_code), Some(verification_uri)] = strings else {
+            self.emit_error("device_login returned no code or verification URL".into());
+            return None;
+        };
+        Some(DeviceCode {
+            device_code,
+            user_code,
+            verification_uri,
+            interval: Duration::from_secs(interval),
+            expires_in: Duration::from_secs(expires_in),
+        })
     }
 
-    pub fn process_save_credentials(&mut self, url: &str) -> bool {
-        let cstring = CString::new(url).unwrap_or_default();
+    fn poll_device_login(&mut self, device_code: &str) -> DevicePoll {
+        Plugin::poll_device_login(self, device_code)
+    }
+
+    fn abort(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        if !self.supports(Capabilities::ABORT) {
+            return false;
+        }
+        let tagname = CString::new(tag).unwrap_or_default();
+        let filename = CString::new(folder_name).unwrap_or_default();
+        let Some(_watch) = self.watch("abort") else {
+            return false;
+        };
 
         unsafe {
-            self.get_function::<unsafe extern "C" fn(DLLString) -> (DLLString, DLLString)>(
-                b"extract_credentials",
-            )
-            .map(|f| f(cstring.as_ptr()))
-            .is_some_and(|(res, possible_err)| {
-                let result = self.test_error_empty(possible_err);
-                if result && let Some(credentials) = self.create_string(res) {
-                    let _ = self.write_creds(&credentials);
-                };
-                result
-            })
+            self.get_function::<symbols::Abort>(b"abort")
+                .is_some_and(|f| self.test_error_empty(f(tagname.as_ptr(), filename.as_ptr())))
         }
     }
 
-    pub fn abort(&self) -> bool {
-        true
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
+        let tagname = CString::new(tag).unwrap_or_default();
+        let filename = CString::new(folder_name).unwrap_or_default();
+        let Some(_watch) = self.watch_transfer("upload", tag, folder_name) else {
+            return false;
+        };
+
+        self.reported(self.authorized(|access_token| {
+            with_backoff(|| unsafe {
+                let f = self.require::<symbols::Upload>(b"upload")?;
+
+                self.take_error(f(
+                    access_token.as_ptr(),
+                    tagname.as_ptr(),
+                    filename.as_ptr(),
+                    date.duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs(),
+                    buffer.as_ptr() as *const i8,
+                    buffer.len() as u64,
+                ))
+            })
+        }))
+        .inspect(|_| self.invalidate_listing())
+        .is_some()
     }
 
-    pub fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
-        let access_token = CString::new(self.credentials()).unwrap_or_default();
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
         let tagname = CString::new(tag).unwrap_or_default();
         let filename = CString::new(folder_name).unwrap_or_default();
+        let _watch = self.watch_transfer("download", tag, folder_name)?;
 
-        unsafe {
-            self.get_function::<unsafe extern "C" fn(
-                DLLString,
-                DLLString,
-                DLLString,
-                u64,
-                DLLString,
-                u64,
-            ) -> DLLString>(b"upload")
-                .map(|f| {
-                    f(
+        self.reported(self.authorized(|access_token| {
+            with_backoff(|| {
+                let (ptr, count, possible_err) = unsafe {
+                    self.require::<symbols::Download>(b"download")?(
                         access_token.as_ptr(),
                         tagname.as_ptr(),
                         filename.as_ptr(),
-                        date.duration_since(SystemTime::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs(),
-                        buffer.as_ptr() as *const i8,
-                        buffer.len() as u64,
                     )
-                })
-                .is_some_and(|ptr| self.test_error_empty(ptr))
+                };
+
+                unsafe { self.take_error(possible_err)? };
+
+                let mut v = Vec::new();
+                let u8_ptr = ptr as *const u8;
+
+                for i in 0..count as isize {
+                    v.push(unsafe { *u8_ptr.offset(i) });
+                }
+
+                unsafe { self.free_string(ptr) };
+
+                Ok(v)
+            })
+        }))
+    }
+
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        mut reader: Box<dyn Read + Send>,
+        len: u64,
+    ) -> bool {
+        if !self.supports(Capabilities::STREAMING) {
+            return upload_buffered(self, tag, folder_name, date, reader, len);
         }
+
+        let Some(handle) = self.upload_open(tag, folder_name, date, len) else {
+            return false;
+        };
+        let completed = pump(&mut reader, |chunk| self.upload_write(&handle, chunk))
+            .inspect_err(|e| self.emit_error(e.to_string()))
+            .unwrap_or_default();
+
+        self.upload_finish(handle, completed) && completed
     }
 
-    pub fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
-        let access_token = CString::new(self.credentials()).unwrap_or_default();
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        if !self.supports(Capabilities::STREAMING) {
+            return download_buffered(self, tag, folder_name, writer);
+        }
+
+        let Some(handle) = self.download_open(tag, folder_name) else {
+            return false;
+        };
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let completed = loop {
+            match self.download_read(&handle, &mut chunk) {
+                Some(0) => break true,
+                Some(read) => {
+                    if let Err(e) = writer.write_all(&chunk[..read]) {
+                        self.emit_error(e.to_string());
+                        break false;
+                    }
+                }
+                None => break false,
+            }
+        };
+
+        self.download_close(handle);
+        completed
+    }
+
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
         let tagname = CString::new(tag).unwrap_or_default();
         let filename = CString::new(folder_name).unwrap_or_default();
+        let Some(_watch) = self.watch("remove") else {
+            return false;
+        };
 
-        let (ptr, count, possible_err) = unsafe {
-            self.get_function::<unsafe extern "C" fn(
-                DLLString,
-                DLLString,
-                DLLString,
-            ) -> (DLLString, u64, DLLString)>(b"download")?(
-                access_token.as_ptr(),
-                tagname.as_ptr(),
-                filename.as_ptr(),
-            )
+        let result = self.authorized(|access_token| {
+            with_backoff(|| unsafe {
+                let f = self.require::<symbols::Remove>(b"remove")?;
+
+                self.take_error(f(
+                    access_token.as_ptr(),
+                    tagname.as_ptr(),
+                    filename.as_ptr(),
+                ))
+            })
+        });
+
+        self.invalidate_listing();
+        match result {
+            // the file being gone already is what remove wanted
+            Err(e) if e.kind == ErrorKind::NotFound => true,
+            result => self.reported(result).is_some(),
+        }
+    }
+
+    /// Reuses the listing for LISTING_TTL, plugins with a change feed only list what changed
+    fn read_cloud(&self) -> Option<Vec<FileDetails>> {
+        // not held across the plugin call, so a hung plugin cannot stall later reads
+        let cursor = {
+            let mut listing = self.listing.lock().unwrap();
+            if listing.fetched.is_some_and(|at| at.elapsed() < LISTING_TTL) {
+                return Some(listing.details.clone());
+            }
+            listing.cursor.take()
         };
 
-        if unsafe { self.test_error_empty(possible_err) } {
-            let mut v = Vec::new();
-            let u8_ptr = ptr as *const u8;
+        let refreshed = match (self.supports(Capabilities::CHANGES), cursor) {
+            (true, Some(cursor)) => self
+                .read_changes(&cursor)
+                .map(|(changes, next)| (changes, Some(next), true)),
+            (true, None) => self
+                .read_changes("")
+                .map(|(details, next)| (details, Some(next), false)),
+            (false, _) => self.read_listing().map(|details| (details, None, false)),
+        };
+
+        // a failed feed read leaves no cursor, so the next read lists everything again
+        self.reported(refreshed)
+            .map(|(details, cursor, incremental)| {
+                let mut listing = self.listing.lock().unwrap();
+                match incremental {
+                    true => apply_changes(&mut listing.details, details),
+                    false => listing.details = details,
+                }
+                listing.cursor = cursor;
+                listing.fetched = Some(Instant::now());
+                listing.details.clone()
+            })
+    }
+
+    fn invalidate_listing(&self) {
+        self.listing.lock().unwrap().fetched = None;
+    }
+}
+
+/// Proxy mirroring Plugin's methods for a plugin loaded into the app
+/// Every call runs on a worker thread, so its caller stops waiting once the watchdog times it out
+/// while the worker stays stuck in the library, which refuses further calls until it returns
+pub struct InProcess(Arc<Plugin>);
 
-            for i in 0..count as isize {
-                v.push(unsafe { *u8_ptr.offset(i) });
+/// Sent by a worker streaming a download to the caller writing it out
+enum Streamed {
+    Chunk(Vec<u8>),
+    Done(bool),
+}
+
+/// Hands the chunks written by the plugin to the caller, fails once it stopped waiting
+struct Forward(mpsc::Sender<Streamed>);
+
+impl Write for Forward {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Streamed::Chunk(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "download was abandoned"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl InProcess {
+    pub fn new(plugin: Plugin) -> InProcess {
+        InProcess(Arc::new(plugin))
+    }
+
+    /// Starts `work` on a worker thread, `abandon` is called once one of its plugin calls timed out
+    fn spawn(
+        &self,
+        abandon: impl Fn() + Send + Sync + 'static,
+        work: impl FnOnce(&Plugin) + Send + 'static,
+    ) -> bool {
+        let plugin = self.0.clone();
+        thread::Builder::new()
+            .name(format!("plugin: {}", self.0.filename.to_string_lossy()))
+            .spawn(move || {
+                ABANDON.set(Some(Arc::new(abandon)));
+                work(&plugin);
+            })
+            .inspect_err(|e| self.0.emit_error(e.to_string()))
+            .is_ok()
+    }
+
+    /// Runs `call` on a worker thread, None if it timed out
+    fn run<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Plugin) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (sender, receiver) = mpsc::channel();
+        let abandon = {
+            let sender = sender.clone();
+            move || {
+                let _ = sender.send(None);
             }
+        };
+        if !self.spawn(abandon, move |plugin| {
+            let _ = sender.send(Some(call(plugin)));
+        }) {
+            return None;
+        }
+        receiver.recv().ok().flatten()
+    }
+}
+
+impl CloudBackend for InProcess {
+    fn supports(&self, capability: Capabilities) -> bool {
+        self.0.supports(capability)
+    }
 
-            unsafe { self.free_string(ptr) };
+    fn info(&self) -> Option<PluginInfo> {
+        self.run(|plugin| plugin.info()).flatten()
+    }
 
-            Some(v)
-        } else {
-            None
+    fn authenticate(&mut self, settings: &Value) -> bool {
+        let settings = settings.clone();
+        self.run(move |plugin| plugin.authenticate(&settings))
+            .unwrap_or_default()
+    }
+
+    fn settings_schema(&self) -> Option<Value> {
+        self.run(|plugin| plugin.settings_schema()).flatten()
+    }
+
+    fn sets_state(&self) -> bool {
+        self.0.sets_state()
+    }
+
+    fn auth_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> Option<String> {
+        let (redirect_uri, state, code_challenge) = (
+            redirect_uri.to_owned(),
+            state.to_owned(),
+            code_challenge.to_owned(),
+        );
+        self.run(move |plugin| plugin.auth_url(&redirect_uri, &state, &code_challenge))
+            .flatten()
+    }
+
+    fn process_save_credentials(&mut self, url: &str, code_verifier: &str) -> bool {
+        let (url, code_verifier) = (url.to_owned(), code_verifier.to_owned());
+        self.run(move |plugin| plugin.process_save_credentials(&url, &code_verifier))
+            .unwrap_or_default()
+    }
+
+    fn device_login(&self) -> Option<DeviceCode> {
+        self.run(|plugin| plugin.device_login()).flatten()
+    }
+
+    fn poll_device_login(&mut self, device_code: &str) -> DevicePoll {
+        let device_code = device_code.to_owned();
+        self.run(move |plugin| plugin.poll_device_login(&device_code))
+            .unwrap_or(DevicePoll::Failed)
+    }
+
+    fn abort(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        let (tag, folder_name) = (tag.to_vec(), folder_name.to_vec());
+        self.run(move |plugin| plugin.abort(&tag, &folder_name))
+            .unwrap_or_default()
+    }
+
+    /// The buffer is copied for the worker, which may outlive the caller
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
+        let (tag, folder_name, buffer) = (tag.to_vec(), folder_name.to_vec(), buffer.to_vec());
+        self.run(move |plugin| plugin.upload(&tag, &folder_name, date, &buffer))
+            .unwrap_or_default()
+    }
+
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
+        let (tag, folder_name) = (tag.to_vec(), folder_name.to_vec());
+        self.run(move |plugin| plugin.download(&tag, &folder_name))
+            .flatten()
+    }
+
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        reader: Box<dyn Read + Send>,
+        len: u64,
+    ) -> bool {
+        let (tag, folder_name) = (tag.to_vec(), folder_name.to_vec());
+        self.run(move |plugin| plugin.upload_stream(&tag, &folder_name, date, reader, len))
+            .unwrap_or_default()
+    }
+
+    /// The worker forwards the chunks, which are written out on the calling thread
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        let (tag, folder_name) = (tag.to_vec(), folder_name.to_vec());
+        let (sender, receiver) = mpsc::channel();
+        let abandon = {
+            let sender = sender.clone();
+            move || {
+                let _ = sender.send(Streamed::Done(false));
+            }
+        };
+        if !self.spawn(abandon, move |plugin| {
+            let done = plugin.download_stream(&tag, &folder_name, &mut Forward(sender.clone()));
+            let _ = sender.send(Streamed::Done(done));
+        }) {
+            return false;
         }
+
+        // returning drops the receiver, which fails the worker's next write
+        while let Ok(streamed) = receiver.recv() {
+            match streamed {
+                Streamed::Chunk(chunk) => {
+                    if let Err(e) = writer.write_all(&chunk) {
+                        self.0.emit_error(e.to_string());
+                        return false;
+                    }
+                }
+                Streamed::Done(done) => return done,
+            }
+        }
+        false
     }
 
-    pub fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
-        let access_token = CString::new(self.credentials()).unwrap_or_default();
-        let tagname = CString::new(tag).unwrap_or_default();
-        let filename = CString::new(folder_name).unwrap_or_default();
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        let (tag, folder_name) = (tag.to_vec(), folder_name.to_vec());
+        self.run(move |plugin| plugin.remove(&tag, &folder_name))
+            .unwrap_or_default()
+    }
 
-        unsafe {
-            self.get_function::<unsafe extern "C" fn(DLLString, DLLString, DLLString) -> DLLString>(
-                b"remove",
-            )
-            .map(|f| f(access_token.as_ptr(), tagname.as_ptr(), filename.as_ptr()))
-            .is_some_and(|ptr| self.test_error_empty(ptr))
+    fn read_cloud(&self) -> Option<Vec<FileDetails>> {
+        self.run(|plugin| plugin.read_cloud()).flatten()
+    }
+
+    fn invalidate_listing(&self) {
+        self.0.invalidate_listing();
+    }
+}
+
+/// Error of calls refused after the plugin stopped answering
+fn unresponsive() -> BackendError {
+    BackendError::new(ErrorKind::Other, "Plugin stopped answering")
+}
+
+/// Reads details in the layout of ABI version 2
+unsafe fn parse_details(detail: &DLLFileDetails) -> (DLLString, DLLString, u64, Option<Vec<u8>>) {
+    let data = (!detail.3.is_null()).then(|| {
+        unsafe { slice::from_raw_parts(detail.3 as *const u8, detail.4 as usize) }.to_vec()
+    });
+    (detail.0, detail.1, detail.2, data)
+}
+
+/// Replaces the changed files in `details`, dropping the removed ones
+fn apply_changes(details: &mut Vec<FileDetails>, changes: Vec<FileDetails>) {
+    for change in changes {
+        details.retain(|d| d.tag != change.tag || d.folder_name != change.folder_name);
+        if change.last_modified != SystemTime::UNIX_EPOCH {
+            details.push(change);
         }
     }
+}
 
-    pub fn read_cloud(&mut self) -> Option<Vec<FileDetails>> {
-        self.details.clone().or_else(|| {
-            let access_token = CString::new(self.credentials()).unwrap_or_default();
+/// Handed to plugins through their `set_progress_callback` export
+unsafe extern "C" fn host_progress(tag: DLLString, folder_name: DLLString, done: u64, total: u64) {
+    if tag.is_null() || folder_name.is_null() {
+        return;
+    }
 
-            unsafe {
-                let (ptr, count, possible_err) = self.get_function::<unsafe extern "C" fn(
-                    DLLString,
-                ) -> (
-                    DLLFileDetails,
-                    u64,
-                    DLLString,
-                )>(b"read_cloud")?(
-                    access_token.as_ptr()
-                );
+    let (tag, folder_name) = unsafe { (CStr::from_ptr(tag), CStr::from_ptr(folder_name)) };
+    progress::report_plugin(tag.to_bytes(), folder_name.to_bytes(), done, total);
+}
 
-                if self.test_error_empty(possible_err) {
-                    let mut v: Vec<FileDetails> = Vec::new();
-
-                    for i in 0..count as isize {
-                        let detail = *ptr.offset(i);
-                        v.push(FileDetails {
-                            tag: self.create_string(detail.0).unwrap().into(),
-                            folder_name: self.create_string(detail.1).unwrap().into(),
-                            last_modified: SystemTime::UNIX_EPOCH + Duration::from_secs(detail.2),
-                            data: if detail.3.is_null() {
-                                None
-                            } else {
-                                Some(CStr::from_ptr(detail.3).to_bytes().to_vec())
-                            },
-                        });
-                    }
+/// Handed to plugins through their `set_log_callback` export
+unsafe extern "C" fn host_log(
+    context: DLLHandle,
+    level: u32,
+    target: DLLString,
+    message: DLLString,
+) {
+    if target.is_null() || message.is_null() {
+        return;
+    }
 
-                    self.get_function::<unsafe extern "C" fn(u64, DLLFileDetails)>(
-                        b"free_file_details",
-                    )?(count, ptr);
+    let (target, message) = unsafe { (CStr::from_ptr(target), CStr::from_ptr(message)) };
+    plugin_log::record(
+        context as usize,
+        level,
+        &target.to_string_lossy(),
+        &message.to_string_lossy(),
+    );
+}
 
-                    self.details = Some(v);
-                    self.details.clone()
-                } else {
-                    None
+/// Feeds `reader` to `write` in chunks of CHUNK_SIZE
+/// Returns false as soon as `write` rejects a chunk
+pub fn pump<F>(reader: &mut dyn Read, mut write: F) -> std::io::Result<bool>
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => return Ok(true),
+            Ok(read) => {
+                if !write(&chunk[..read]) {
+                    return Ok(false);
                 }
             }
-        })
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct PluginInfo {
-    name: String,
-    description: String,
-    author: String,
-    icon_url: String,
-    filename: OsString,
+/// Loads and pins the library so it is never unloaded while symbols are in use
+#[cfg(windows)]
+unsafe fn load_library(path: &Path) -> Result<Library, libloading::Error> {
+    unsafe {
+        let library = libloading::os::windows::Library::new(path)?;
+        library.pin()?;
+        Ok(library.into())
+    }
+}
+
+/// Loads the library with RTLD_NODELETE, the dlopen equivalent of pinning
+#[cfg(unix)]
+unsafe fn load_library(path: &Path) -> Result<Library, libloading::Error> {
+    use libloading::os::unix::{Library as UnixLibrary, RTLD_LOCAL, RTLD_NOW};
+
+    unsafe { UnixLibrary::open(Some(path), RTLD_NOW | RTLD_LOCAL | libc::RTLD_NODELETE) }
+        .map(Into::into)
 }
@@ -0,0 +1,803 @@
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{self, Read, Write},
+    mem,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
+};
+
+use super::{
+    backend::{
+        BackendError, Capabilities, CloudBackend, DeviceCode, DevicePoll, FileDetails, PluginInfo,
+        download_buffered, report_error, upload_buffered,
+    },
+    emitter, manifest,
+    plugin::{CHUNK_SIZE, Plugin, pump},
+    progress, vault, watchdog,
+};
+
+/// Command line flag that starts SaveSync as a plugin host instead of the app
+const HOST_FLAG: &str = "--plugin-host";
+
+static IS_CHILD: AtomicBool = AtomicBool::new(false);
+
+/// The child's original stdout, which only carries frames once plugins' prints go to stderr
+static PROTOCOL: OnceLock<Mutex<File>> = OnceLock::new();
+
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Info,
+    Authenticate(Value),
+    SettingsSchema,
+    AuthUrl {
+        redirect_uri: String,
+        state: String,
+        code_challenge: String,
+    },
+    ExtractCredentials {
+        url: String,
+        code_verifier: String,
+    },
+    DeviceLogin,
+    PollDeviceLogin(String),
+    Upload {
+        tag: Vec<u8>,
+        folder_name: Vec<u8>,
+        date: SystemTime,
+        #[serde(skip)]
+        buffer: Vec<u8>,
+    },
+    Download {
+        tag: Vec<u8>,
+        folder_name: Vec<u8>,
+    },
+    Remove {
+        tag: Vec<u8>,
+        folder_name: Vec<u8>,
+    },
+    ReadCloud,
+    InvalidateListing,
+    UploadOpen {
+        tag: Vec<u8>,
+        folder_name: Vec<u8>,
+        date: SystemTime,
+        len: u64,
+    },
+    UploadWrite(#[serde(skip)] Vec<u8>),
+    UploadFinish(bool),
+    DownloadOpen {
+        tag: Vec<u8>,
+        folder_name: Vec<u8>,
+    },
+    /// Answered with the next chunk, an empty chunk marks the end
+    DownloadRead,
+    DownloadClose,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    /// Sent once the plugin has been loaded, with its capabilities and ABI version
+    Ready(Capabilities, u32),
+    /// Forwarded to backend::report_error, may precede any other response
+    Error(BackendError),
+    /// Forwarded to progress::emit_plugin, may precede any other response
+    Progress {
+        tag: Vec<u8>,
+        folder_name: Vec<u8>,
+        done: u64,
+        total: u64,
+    },
+    Info(Option<PluginInfo>),
+    Bool(bool),
+    Url(Option<String>),
+    /// Whether the plugin returned a buffer, which is the payload of the frame
+    Buffer(bool, #[serde(skip)] Vec<u8>),
+    Details(Option<Vec<FileDetails>>),
+    Schema(Option<Value>),
+    DeviceCode(Option<DeviceCode>),
+    DevicePoll(DevicePoll),
+}
+
+impl Response {
+    fn buffer(buffer: Option<Vec<u8>>) -> Response {
+        Response::Buffer(buffer.is_some(), buffer.unwrap_or_default())
+    }
+}
+
+/// Message sent over the pipe, whose file contents are kept out of the JSON
+trait Message: Serialize + DeserializeOwned {
+    /// Field skipped by serde that travels as the raw payload of the frame
+    fn payload(&mut self) -> Option<&mut Vec<u8>> {
+        None
+    }
+}
+
+impl Message for Request {
+    fn payload(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            Request::Upload { buffer, .. } | Request::UploadWrite(buffer) => Some(buffer),
+            _ => None,
+        }
+    }
+}
+
+impl Message for Response {
+    fn payload(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            Response::Buffer(_, buffer) => Some(buffer),
+            _ => None,
+        }
+    }
+}
+
+/// The vault key sent before the first request
+impl Message for Option<Vec<u8>> {}
+
+/// Frames are a little-endian u32 length and that many bytes of JSON, followed by
+/// a little-endian u64 length and that many raw bytes of payload
+fn write_frame<T: Message>(writer: &mut impl Write, mut message: T) -> io::Result<()> {
+    let payload = message.payload().map(mem::take).unwrap_or_default();
+    let bytes = serde_json::to_vec(&message)?;
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message is too long"))?;
+
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+fn read_frame<T: Message>(reader: &mut impl Read) -> io::Result<T> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    let mut message: T = serde_json::from_slice(&bytes)?;
+
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    match message.payload() {
+        Some(payload) => {
+            reader.take(len).read_to_end(payload)?;
+            if payload.len() as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        None if len != 0 => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Payload sent with a message that has none",
+            ));
+        }
+        None => {}
+    }
+    Ok(message)
+}
+
+/// Returns the plugin filename if this process was started as a plugin host
+pub fn requested_plugin() -> Option<OsString> {
+    let mut args = env::args_os().skip(1);
+    (args.next()? == HOST_FLAG).then(|| args.next()).flatten()
+}
+
+pub fn is_child() -> bool {
+    IS_CHILD.load(Ordering::Relaxed)
+}
+
+/// Sends a plugin error to the parent process instead of the (absent) webview
+pub fn forward_error(error: &BackendError) {
+    respond(Response::Error(error.clone()));
+}
+
+/// Sends plugin-reported progress to the parent process
+pub fn forward_progress(tag: &[u8], folder_name: &[u8], done: u64, total: u64) {
+    respond(Response::Progress {
+        tag: tag.to_owned(),
+        folder_name: folder_name.to_owned(),
+        done,
+        total,
+    });
+}
+
+fn respond(response: Response) {
+    if let Some(protocol) = PROTOCOL.get() {
+        let _ = write_frame(&mut *protocol.lock().unwrap(), response);
+    }
+}
+
+/// Takes the original stdout for the protocol and points stdout at stderr,
+/// so anything a plugin prints cannot end up between frames
+#[cfg(unix)]
+fn claim_stdout() -> io::Result<File> {
+    use std::os::fd::FromRawFd;
+
+    io::stdout().flush()?;
+    unsafe {
+        let protocol = libc::dup(libc::STDOUT_FILENO);
+        if protocol < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(protocol))
+    }
+}
+
+/// Takes the original stdout for the protocol and points stdout at stderr,
+/// so anything a plugin prints cannot end up between frames
+#[cfg(windows)]
+fn claim_stdout() -> io::Result<File> {
+    use std::os::windows::io::FromRawHandle;
+    use windows_sys::Win32::{
+        Foundation::INVALID_HANDLE_VALUE,
+        System::Console::{GetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE, SetStdHandle},
+    };
+
+    io::stdout().flush()?;
+    unsafe {
+        let protocol = GetStdHandle(STD_OUTPUT_HANDLE);
+        if protocol == INVALID_HANDLE_VALUE
+            || SetStdHandle(STD_OUTPUT_HANDLE, GetStdHandle(STD_ERROR_HANDLE)) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(File::from_raw_handle(protocol))
+    }
+}
+
+/// Entry point of the child process, serves requests on stdin until it is closed
+pub fn serve(filename: &OsStr) {
+    IS_CHILD.store(true, Ordering::Relaxed);
+    let protocol = match claim_stdout() {
+        Ok(protocol) => protocol,
+        Err(e) => {
+            eprintln!("Unable to set up the plugin host: {e}");
+            return;
+        }
+    };
+    PROTOCOL.get_or_init(|| Mutex::new(protocol));
+
+    let mut stdin = io::stdin().lock();
+    // the parent sends the vault key first, the saved credentials cannot be read without it
+    let Ok(key) = read_frame::<Option<Vec<u8>>>(&mut stdin) else {
+        return;
+    };
+    if let Some(key) = key {
+        vault::import_key(&key);
+    }
+
+    let Some(plugin) = (unsafe { Plugin::new(filename) }) else {
+        return;
+    };
+    respond(Response::Ready(plugin.capabilities(), plugin.abi_version()));
+
+    // at most one stream of each direction is open, PluginProcess holds its lock throughout
+    let mut upload = None;
+    let mut download = None;
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    while let Ok(request) = read_frame(&mut stdin) {
+        respond(match request {
+            Request::Info => Response::Info(plugin.info()),
+            Request::Authenticate(settings) => Response::Bool(plugin.authenticate(&settings)),
+            Request::SettingsSchema => Response::Schema(plugin.settings_schema()),
+            Request::AuthUrl {
+                redirect_uri,
+                state,
+                code_challenge,
+            } => Response::Url(plugin.auth_url(&redirect_uri, &state, &code_challenge)),
+            Request::ExtractCredentials { url, code_verifier } => {
+                Response::Bool(plugin.process_save_credentials(&url, &code_verifier))
+            }
+            Request::DeviceLogin => Response::DeviceCode(plugin.device_login()),
+            Request::PollDeviceLogin(device_code) => {
+                Response::DevicePoll(plugin.poll_device_login(&device_code))
+            }
+            Request::Upload {
+                tag,
+                folder_name,
+                date,
+                buffer,
+            } => Response::Bool(plugin.upload(&tag, &folder_name, date, &buffer)),
+            Request::Download { tag, folder_name } => {
+                Response::buffer(plugin.download(&tag, &folder_name))
+            }
+            Request::Remove { tag, folder_name } => {
+                Response::Bool(plugin.remove(&tag, &folder_name))
+            }
+            Request::ReadCloud => Response::Details(plugin.read_cloud()),
+            Request::InvalidateListing => {
+                plugin.invalidate_listing();
+                Response::Bool(true)
+            }
+            Request::UploadOpen {
+                tag,
+                folder_name,
+                date,
+                len,
+            } => {
+                upload = plugin.upload_open(&tag, &folder_name, date, len);
+                Response::Bool(upload.is_some())
+            }
+            Request::UploadWrite(buffer) => Response::Bool(
+                upload
+                    .as_ref()
+                    .is_some_and(|handle| plugin.upload_write(handle, &buffer)),
+            ),
+            Request::UploadFinish(commit) => Response::Bool(
+                upload
+                    .take()
+                    .is_some_and(|handle| plugin.upload_finish(handle, commit)),
+            ),
+            Request::DownloadOpen { tag, folder_name } => {
+                download = plugin.download_open(&tag, &folder_name);
+                Response::Bool(download.is_some())
+            }
+            Request::DownloadRead => Response::buffer(download.as_ref().and_then(|handle| {
+                plugin
+                    .download_read(handle, &mut chunk)
+                    .map(|read| chunk[..read].to_vec())
+            })),
+            Request::DownloadClose => {
+                if let Some(handle) = download.take() {
+                    plugin.download_close(handle);
+                }
+                Response::Bool(true)
+            }
+        });
+    }
+}
+
+struct Connection {
+    /// Shared with PluginProcess::abort and the watchdog, which kill the child to stop a call
+    child: Arc<Mutex<Child>>,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl Connection {
+    /// Starts a child for the plugin, which is verified again first since it may
+    /// have been replaced since the last start
+    fn spawn(filename: &OsStr) -> Option<(Connection, Capabilities, u32)> {
+        if let Err(e) = manifest::verify_plugin(filename) {
+            emitter::plugin_error(filename, &e);
+            return None;
+        }
+
+        let mut child = Command::new(env::current_exe().ok()?)
+            .arg(HOST_FLAG)
+            .arg(filename)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .inspect_err(|e| emitter::plugin_error(filename, &e.to_string()))
+            .ok()?;
+
+        let mut connection = Connection {
+            stdin: child.stdin.take()?,
+            stdout: child.stdout.take()?,
+            child: Arc::new(Mutex::new(child)),
+        };
+        write_frame(&mut connection.stdin, vault::export_key()).ok()?;
+
+        match connection.receive(filename).ok()? {
+            Response::Ready(capabilities, abi_version) => {
+                Some((connection, capabilities, abi_version))
+            }
+            _ => None,
+        }
+    }
+
+    fn receive(&mut self, filename: &OsStr) -> io::Result<Response> {
+        loop {
+            match read_frame(&mut self.stdout)? {
+                Response::Error(error) => report_error(filename, &error),
+                Response::Progress {
+                    tag,
+                    folder_name,
+                    done,
+                    total,
+                } => progress::emit_plugin(&tag, &folder_name, done, total),
+                response => return Ok(response),
+            }
+        }
+    }
+
+    fn call(&mut self, filename: &OsStr, request: Request) -> io::Result<Response> {
+        write_frame(&mut self.stdin, request)?;
+        self.receive(filename)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// (tag, folder_name, child) of the transfer holding the connection
+type RunningTransfer = (Vec<u8>, Vec<u8>, Arc<Mutex<Child>>);
+
+/// Proxy mirroring Plugin's methods for a plugin running in a child process
+/// A crash or hang in the plugin only kills the child, which is restarted on the next call
+pub struct PluginProcess {
+    filename: OsString,
+    capabilities: Capabilities,
+    abi_version: u32,
+    connection: Mutex<Option<Connection>>,
+    running: Mutex<Option<RunningTransfer>>,
+    /// Set when abort or the watchdog killed the child, so its death is not reported as a crash
+    killed: Arc<AtomicBool>,
+}
+
+impl PluginProcess {
+    pub fn new(filename: &OsStr) -> Option<PluginProcess> {
+        let (connection, capabilities, abi_version) = Connection::spawn(filename)?;
+
+        Some(PluginProcess {
+            filename: filename.to_owned(),
+            capabilities,
+            abi_version,
+            connection: Mutex::new(Some(connection)),
+            running: Mutex::new(None),
+            killed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    fn call(&self, request: Request) -> Option<Response> {
+        self.session(|call| call(request))
+    }
+
+    /// Runs several calls on one connection, so a stream's chunks are not
+    /// interleaved with calls from other threads
+    fn session<T>(&self, f: impl FnOnce(&mut dyn FnMut(Request) -> Option<Response>) -> T) -> T {
+        let mut connection = self.connection.lock().unwrap();
+        f(&mut |request| self.call_on(&mut connection, request))
+    }
+
+    /// Session transferring a file, which abort stops by killing the child
+    fn transfer<T>(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        f: impl FnOnce(&mut dyn FnMut(Request) -> Option<Response>) -> T,
+    ) -> T {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Connection::spawn(&self.filename).map(|(c, ..)| c);
+        }
+
+        *self.running.lock().unwrap() = connection
+            .as_ref()
+            .map(|c| (tag.to_vec(), folder_name.to_vec(), c.child.clone()));
+        let result = f(&mut |request| self.call_on(&mut connection, request));
+        *self.running.lock().unwrap() = None;
+        self.killed.store(false, Ordering::Relaxed);
+
+        result
+    }
+
+    fn call_on(&self, connection: &mut Option<Connection>, request: Request) -> Option<Response> {
+        if connection.is_none() {
+            *connection = Connection::spawn(&self.filename).map(|(c, ..)| c);
+        }
+
+        // a hung call is stopped by killing the child, the next call spawns a fresh one
+        let child = connection.as_ref()?.child.clone();
+        let killed = self.killed.clone();
+        let watch = watchdog::watch(&self.filename, "Plugin host", move || {
+            killed.store(true, Ordering::Relaxed);
+            let _ = child.lock().unwrap().kill();
+        });
+        if let Some((tag, folder_name, _)) = self.running.lock().unwrap().as_ref() {
+            watch.transferring(tag, folder_name);
+        }
+
+        let response = connection
+            .as_mut()?
+            .call(&self.filename, request)
+            .inspect_err(|e| {
+                if !self.killed.swap(false, Ordering::Relaxed) {
+                    emitter::plugin_error(
+                        &self.filename,
+                        &format!("Plugin host crashed ({e}), restarting it"),
+                    )
+                }
+            })
+            .ok();
+        drop(watch);
+
+        if response.is_none() {
+            *connection = Connection::spawn(&self.filename).map(|(c, ..)| c);
+        }
+
+        response
+    }
+
+    fn call_bool(&self, request: Request) -> bool {
+        is_true(self.call(request))
+    }
+}
+
+fn is_true(response: Option<Response>) -> bool {
+    matches!(response, Some(Response::Bool(true)))
+}
+
+impl CloudBackend for PluginProcess {
+    fn supports(&self, capability: Capabilities) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    fn info(&self) -> Option<PluginInfo> {
+        match self.call(Request::Info)? {
+            Response::Info(info) => info,
+            _ => None,
+        }
+    }
+
+    fn authenticate(&mut self, settings: &Value) -> bool {
+        self.call_bool(Request::Authenticate(settings.clone()))
+    }
+
+    fn settings_schema(&self) -> Option<Value> {
+        if !self.supports(Capabilities::SETTINGS) {
+            return None;
+        }
+
+        match self.call(Request::SettingsSchema)? {
+            Response::Schema(schema) => schema,
+            _ => None,
+        }
+    }
+
+    fn sets_state(&self) -> bool {
+        self.abi_version >= 4
+    }
+
+    fn auth_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> Option<String> {
+        match self.call(Request::AuthUrl {
+            redirect_uri: redirect_uri.into(),
+            state: state.into(),
+            code_challenge: code_challenge.into(),
+        })? {
+            Response::Url(url) => url,
+            _ => None,
+        }
+    }
+
+    fn process_save_credentials(&mut self, url: &str, code_verifier: &str) -> bool {
+        self.call_bool(Request::ExtractCredentials {
+            url: url.into(),
+            code_verifier: code_verifier.into(),
+        })
+    }
+
+    fn device_login(&self) -> Option<DeviceCode> {
+        if !self.supports(Capabilities::DEVICE_LOGIN) {
+            return None;
+        }
+        match self.call(Request::DeviceLogin)? {
+            Response::DeviceCode(code) => code,
+            _ => None,
+        }
+    }
+
+    fn poll_device_login(&mut self, device_code: &str) -> DevicePoll {
+        match self.call(Request::PollDeviceLogin(device_code.into())) {
+            Some(Response::DevicePoll(poll)) => poll,
+            _ => DevicePoll::Failed,
+        }
+    }
+
+    /// Kills the child if it is transferring the file, the next call restarts it
+    fn abort(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        match self.running.lock().unwrap().as_ref() {
+            Some((running_tag, running_folder, child))
+                if running_tag == tag && running_folder == folder_name =>
+            {
+                self.killed.store(true, Ordering::Relaxed);
+                child.lock().unwrap().kill().is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    fn upload(&self, tag: &[u8], folder_name: &[u8], date: SystemTime, buffer: &[u8]) -> bool {
+        self.transfer(tag, folder_name, |call| {
+            is_true(call(Request::Upload {
+                tag: tag.into(),
+                folder_name: folder_name.into(),
+                date,
+                buffer: buffer.into(),
+            }))
+        })
+    }
+
+    fn download(&self, tag: &[u8], folder_name: &[u8]) -> Option<Vec<u8>> {
+        self.transfer(tag, folder_name, |call| {
+            match call(Request::Download {
+                tag: tag.into(),
+                folder_name: folder_name.into(),
+            })? {
+                Response::Buffer(true, buffer) => Some(buffer),
+                _ => None,
+            }
+        })
+    }
+
+    fn upload_stream(
+        &self,
+        tag: &[u8],
+        folder_name: &[u8],
+        date: SystemTime,
+        mut reader: Box<dyn Read + Send>,
+        len: u64,
+    ) -> bool {
+        if !self.supports(Capabilities::STREAMING) {
+            return upload_buffered(self, tag, folder_name, date, reader, len);
+        }
+
+        self.transfer(tag, folder_name, |call| {
+            if !is_true(call(Request::UploadOpen {
+                tag: tag.into(),
+                folder_name: folder_name.into(),
+                date,
+                len,
+            })) {
+                return false;
+            }
+
+            let completed = pump(&mut reader, |chunk| {
+                is_true(call(Request::UploadWrite(chunk.into())))
+            })
+            .inspect_err(|e| emitter::plugin_error(&self.filename, &e.to_string()))
+            .unwrap_or_default();
+
+            is_true(call(Request::UploadFinish(completed))) && completed
+        })
+    }
+
+    fn download_stream(&self, tag: &[u8], folder_name: &[u8], writer: &mut dyn Write) -> bool {
+        if !self.supports(Capabilities::STREAMING) {
+            return download_buffered(self, tag, folder_name, writer);
+        }
+
+        self.transfer(tag, folder_name, |call| {
+            if !is_true(call(Request::DownloadOpen {
+                tag: tag.into(),
+                folder_name: folder_name.into(),
+            })) {
+                return false;
+            }
+
+            let completed = loop {
+                match call(Request::DownloadRead) {
+                    Some(Response::Buffer(true, chunk)) if chunk.is_empty() => break true,
+                    Some(Response::Buffer(true, chunk)) => {
+                        if let Err(e) = writer.write_all(&chunk) {
+                            emitter::plugin_error(&self.filename, &e.to_string());
+                            break false;
+                        }
+                    }
+                    _ => break false,
+                }
+            };
+
+            call(Request::DownloadClose);
+            completed
+        })
+    }
+
+    fn remove(&self, tag: &[u8], folder_name: &[u8]) -> bool {
+        self.call_bool(Request::Remove {
+            tag: tag.into(),
+            folder_name: folder_name.into(),
+        })
+    }
+
+    fn read_cloud(&self) -> Option<Vec<FileDetails>> {
+        match self.call(Request::ReadCloud)? {
+            Response::Details(details) => details,
+            _ => None,
+        }
+    }
+
+    fn invalidate_listing(&self) {
+        self.call(Request::InvalidateListing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Message>(message: T) -> T {
+        let mut pipe = Vec::new();
+        write_frame(&mut pipe, message).unwrap();
+        read_frame(&mut &pipe[..]).unwrap()
+    }
+
+    #[test]
+    fn buffers_travel_as_payload() {
+        let mut pipe = Vec::new();
+        write_frame(&mut pipe, Request::UploadWrite(vec![7; 1000])).unwrap();
+        // 4 + JSON + 8 + the raw bytes, instead of a JSON array of numbers
+        assert!(pipe.len() < 1000 + 32);
+
+        match round_trip(Request::Upload {
+            tag: b"tag".to_vec(),
+            folder_name: b"save".to_vec(),
+            date: SystemTime::UNIX_EPOCH,
+            buffer: vec![1, 2, 3],
+        }) {
+            Request::Upload {
+                tag,
+                folder_name,
+                buffer,
+                ..
+            } => {
+                assert_eq!(tag, b"tag");
+                assert_eq!(folder_name, b"save");
+                assert_eq!(buffer, [1, 2, 3]);
+            }
+            _ => panic!("decoded another request"),
+        }
+    }
+
+    #[test]
+    fn missing_and_empty_buffers_differ() {
+        assert!(matches!(
+            round_trip(Response::buffer(None)),
+            Response::Buffer(false, _)
+        ));
+        assert!(matches!(
+            round_trip(Response::buffer(Some(Vec::new()))),
+            Response::Buffer(true, buffer) if buffer.is_empty()
+        ));
+    }
+
+    #[test]
+    fn frames_are_read_in_sequence() {
+        let mut pipe = Vec::new();
+        write_frame(&mut pipe, Response::buffer(Some(vec![0; 10]))).unwrap();
+        write_frame(&mut pipe, Response::Bool(true)).unwrap();
+
+        let mut reader = &pipe[..];
+        assert!(matches!(
+            read_frame(&mut reader).unwrap(),
+            Response::Buffer(true, buffer) if buffer.len() == 10
+        ));
+        assert!(matches!(
+            read_frame(&mut reader).unwrap(),
+            Response::Bool(true)
+        ));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn truncated_and_unexpected_payloads_are_rejected() {
+        let mut pipe = Vec::new();
+        write_frame(&mut pipe, Request::UploadWrite(vec![0; 10])).unwrap();
+        pipe.truncate(pipe.len() - 1);
+        assert!(read_frame::<Request>(&mut &pipe[..]).is_err());
+
+        let mut pipe = Vec::new();
+        write_frame(&mut pipe, Response::buffer(Some(vec![0; 10]))).unwrap();
+        // a Bool frame carrying the payload of a Buffer
+        let json = serde_json::to_vec(&Response::Bool(true)).unwrap();
+        let mut forged = (json.len() as u32).to_le_bytes().to_vec();
+        forged.extend(json);
+        forged.extend(&pipe[pipe.len() - 18..]);
+        assert!(read_frame::<Response>(&mut &forged[..]).is_err());
+    }
+}
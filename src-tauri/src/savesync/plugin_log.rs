@@ -0,0 +1,119 @@
+use chrono::Local;
+use savesync_plugin_abi::log_level;
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    ffi::{OsStr, OsString},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use super::config_paths;
+
+/// Size at which a plugin's log file is rotated
+const MAX_LOG_SIZE: u64 = 1 << 20;
+
+/// Rotated files kept next to the current one, `<plugin>.1.log` being the newest
+const KEPT_LOGS: u32 = 3;
+
+/// Plugins handed the log callback, the index of each is the context its records carry
+static PLUGINS: Mutex<Vec<OsString>> = Mutex::new(Vec::new());
+
+/// Open log files by plugin filename
+static FILES: LazyLock<Mutex<HashMap<OsString, File>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Context of the log callback for the plugin `filename`
+/// Indices are never reused, so records of a library loaded earlier still find their file
+pub fn register(filename: &OsStr) -> usize {
+    let mut plugins = PLUGINS.lock().unwrap();
+    match plugins.iter().position(|plugin| plugin == filename) {
+        Some(context) => context,
+        None => {
+            plugins.push(filename.to_owned());
+            plugins.len() - 1
+        }
+    }
+}
+
+/// Appends a record to the log file of the plugin registered as `context`
+pub fn record(context: usize, level: u32, target: &str, message: &str) {
+    let filename = PLUGINS.lock().unwrap().get(context).cloned();
+    if let Some(filename) = filename {
+        // a failing log must not fail the plugin call writing it
+        let _ = write(&filename, level, target, message);
+    }
+}
+
+fn write(filename: &OsStr, level: u32, target: &str, message: &str) -> io::Result<()> {
+    let mut files = FILES.lock().unwrap();
+    let path = log_path(filename, 0);
+
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= MAX_LOG_SIZE) {
+        files.remove(filename);
+        rotate(filename)?;
+    }
+
+    let file = match files.entry(filename.to_owned()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            entry.insert(OpenOptions::new().create(true).append(true).open(&path)?)
+        }
+    };
+
+    writeln!(
+        file,
+        "{} {:<5} [{}] {target}: {message}",
+        Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+        level_name(level),
+        filename.to_string_lossy()
+    )
+}
+
+/// Shifts every log file of the plugin one place back, dropping the oldest
+fn rotate(filename: &OsStr) -> io::Result<()> {
+    for n in (1..KEPT_LOGS).rev() {
+        let from = log_path(filename, n);
+        if from.exists() {
+            fs::rename(from, log_path(filename, n + 1))?;
+        }
+    }
+    fs::rename(log_path(filename, 0), log_path(filename, 1))
+}
+
+/// `<plugin>.log`, or `<plugin>.<n>.log` for the rotated ones
+fn log_path(filename: &OsStr, n: u32) -> PathBuf {
+    let mut name = filename.to_owned();
+    if n > 0 {
+        name.push(format!(".{n}"));
+    }
+    name.push(".log");
+    config_paths::logs().join(name)
+}
+
+fn level_name(level: u32) -> &'static str {
+    match level {
+        log_level::ERROR => "ERROR",
+        log_level::WARN => "WARN",
+        log_level::INFO => "INFO",
+        log_level::DEBUG => "DEBUG",
+        log_level::TRACE => "TRACE",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_plugin_gets_its_own_context() {
+        let first = register(OsStr::new("first-plugin.so"));
+        let second = register(OsStr::new("second-plugin.so"));
+
+        assert_ne!(first, second);
+        assert_eq!(register(OsStr::new("first-plugin.so")), first);
+        assert_eq!(PLUGINS.lock().unwrap()[second], "second-plugin.so");
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io::{self, Read, Write},
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{cancellation, emitter, plugin_host, watch::strip_zip_extension, watchdog};
+
+/// Least time between two progress events of one file
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Tag and name of a file in the cloud, as encoded bytes
+type FileKey = (Vec<u8>, Vec<u8>);
+
+/// Time of the last progress event by file
+static LAST_REPORT: LazyLock<Mutex<HashMap<FileKey, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Reports how far the transfer of a file got, `total` is 0 when unknown
+/// Throttled to one report per INTERVAL, the report completing a transfer always goes through
+pub fn report(tag: &str, folder_name: &OsStr, done: u64, total: u64) {
+    let (tag_bytes, name_bytes) = (tag.as_bytes(), folder_name.as_encoded_bytes());
+    if throttled(tag_bytes, name_bytes, done, total) {
+        return;
+    }
+
+    if plugin_host::is_child() {
+        plugin_host::forward_progress(tag_bytes, name_bytes, done, total);
+    } else {
+        emit(tag, folder_name, done, total);
+    }
+}
+
+/// Reports progress a plugin gave for the file it was handed as `tag` and `folder_name`
+pub fn report_plugin(tag: &[u8], folder_name: &[u8], done: u64, total: u64) {
+    if throttled(tag, folder_name, done, total) {
+        return;
+    }
+
+    if plugin_host::is_child() {
+        plugin_host::forward_progress(tag, folder_name, done, total);
+    } else {
+        emit_plugin(tag, folder_name, done, total);
+    }
+}
+
+/// Emits plugin progress under the transfer the bytes name
+/// Dropped when no transfer matches, the plugin may not have handed back the bytes it was given
+pub fn emit_plugin(tag: &[u8], folder_name: &[u8], done: u64, total: u64) {
+    if let Some((tag, folder_name)) = cancellation::running(tag, folder_name) {
+        emit(&tag, &folder_name, done, total);
+    }
+}
+
+/// Whether a report comes too soon after the last one of the file
+fn throttled(tag: &[u8], folder_name: &[u8], done: u64, total: u64) -> bool {
+    let key = (tag.to_owned(), folder_name.to_owned());
+    let now = Instant::now();
+    let mut last_report = LAST_REPORT.lock().unwrap();
+    if total != 0 && done >= total {
+        last_report.remove(&key);
+    } else if last_report
+        .get(&key)
+        .is_some_and(|at| now.duration_since(*at) < INTERVAL)
+    {
+        return true;
+    } else {
+        last_report.insert(key, now);
+    }
+    false
+}
+
+/// Sends already throttled progress to the UI under the local name of the file
+/// and lets the watchdog know the transfer is still moving
+pub fn emit(tag: &str, folder_name: &OsStr, done: u64, total: u64) {
+    watchdog::progressed(tag, folder_name);
+    emitter::transfer_progress(
+        tag,
+        strip_zip_extension(folder_name).value().as_os_str(),
+        done,
+        total,
+    );
+}
+
+/// Reader or writer reporting the bytes that passed through it
+/// Used for streamed transfers, where the host sees every chunk
+pub struct Tracked<T> {
+    inner: T,
+    tag: String,
+    folder_name: OsString,
+    done: u64,
+    total: u64,
+}
+
+impl<T> Tracked<T> {
+    pub fn new(tag: &str, folder_name: &OsStr, total: u64, inner: T) -> Tracked<T> {
+        Tracked {
+            inner,
+            tag: tag.to_owned(),
+            folder_name: folder_name.to_owned(),
+            done: 0,
+            total,
+        }
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.done += bytes as u64;
+        report(&self.tag, &self.folder_name, self.done, self.total);
+    }
+}
+
+impl<R: Read> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.advance(read);
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Tracked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.advance(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    /// Lets the next transfer of the file report right away
+    fn drop(&mut self) {
+        LAST_REPORT.lock().unwrap().remove(&(
+            self.tag.as_bytes().to_owned(),
+            self.folder_name.as_encoded_bytes().to_owned(),
+        ));
+    }
+}
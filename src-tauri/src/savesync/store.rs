@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     ffi::{OsStr, OsString},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, SystemTime},
@@ -10,6 +11,9 @@ use serde_json::{from_value, json, to_value, Map, Value};
 use tauri::{Manager, Wry};
 use tauri_plugin_store::{Result, Store, StoreBuilder};
 
+/// Ports the OAuth redirect server tries when none are configured
+const DEFAULT_OAUTH_PORTS: RangeInclusive<u16> = 5000..=5009;
+
 pub struct AppStore {
     store: Arc<Store<Wry>>,
 }
@@ -27,6 +31,12 @@ impl AppStore {
                 .default("silenceMissingMappings", false)
                 .default("hide_to_tray", true)
                 .default("sync_notifications", true)
+                .default("isolate_plugins", false)
+                .default("trusted_keys", json!([]))
+                .default("plugin_timeout", 300)
+                .default("plugin_settings", json!({}))
+                .default("oauth_ports", json!(DEFAULT_OAUTH_PORTS.collect::<Vec<_>>()))
+                .default("oauth_deep_link", false)
                 .auto_save(Duration::from_secs(60))
                 .build()
                 .unwrap(),
@@ -73,6 +83,7 @@ impl AppStore {
         );
     }
 
+    /// When the app last closed, the baseline of items sync_state has no record of yet
     pub fn last_sync(&self) -> SystemTime {
         SystemTime::UNIX_EPOCH
             + Duration::from_secs(
@@ -108,6 +119,80 @@ impl AppStore {
             .unwrap_or_default()
     }
 
+    /// Whether plugins are run in a separate process so their crashes cannot take down the app
+    pub fn isolate_plugins(&self) -> bool {
+        self.store
+            .get("isolate_plugins")
+            .as_ref()
+            .and_then(Value::as_bool)
+            .unwrap_or_default()
+    }
+
+    /// Longest a plugin call may go without answering or making progress before the watchdog gives up on it
+    pub fn plugin_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.store
+                .get("plugin_timeout")
+                .as_ref()
+                .and_then(Value::as_u64)
+                .filter(|secs| *secs > 0)
+                .unwrap_or(300),
+        )
+    }
+
+    /// Ports the OAuth redirect server tries in order
+    /// Services only redirect to the ones registered with them
+    pub fn oauth_ports(&self) -> Vec<u16> {
+        self.store
+            .get("oauth_ports")
+            .and_then(|ports| from_value::<Vec<u16>>(ports).ok())
+            .filter(|ports| !ports.is_empty())
+            .unwrap_or_else(|| DEFAULT_OAUTH_PORTS.collect())
+    }
+
+    /// Whether browser logins redirect to a savesync://auth link instead of the redirect server
+    pub fn oauth_deep_link(&self) -> bool {
+        self.store
+            .get("oauth_deep_link")
+            .as_ref()
+            .and_then(Value::as_bool)
+            .unwrap_or_default()
+    }
+
+    /// Values saved for the settings schema of the plugin `filename`
+    pub fn plugin_settings(&self, filename: &OsStr) -> Value {
+        self.store
+            .get("plugin_settings")
+            .as_ref()
+            .and_then(|settings| settings.get(filename.to_string_lossy().as_ref()))
+            .cloned()
+            .unwrap_or_else(|| json!({}))
+    }
+
+    pub fn set_plugin_settings(&self, filename: &OsStr, values: Value) {
+        let mut settings = self
+            .store
+            .get("plugin_settings")
+            .and_then(|settings| settings.as_object().cloned())
+            .unwrap_or_default();
+        settings.insert(filename.to_string_lossy().into_owned(), values);
+        self.store.set("plugin_settings", Value::Object(settings));
+    }
+
+    /// Hex encoded ed25519 keys of the publishers whose plugins may be loaded
+    pub fn trusted_keys(&self) -> Vec<String> {
+        from_value(self.store.get("trusted_keys").unwrap_or_default()).unwrap_or_default()
+    }
+
+    pub fn add_trusted_key(&self, key: &str) {
+        let mut keys = self.trusted_keys();
+        if !keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            keys.push(key.to_lowercase());
+            self.store
+                .set("trusted_keys", to_value(keys).unwrap_or_default());
+        }
+    }
+
     pub fn save(&self) -> Result<()> {
         self.set_last_sync(SystemTime::now());
         self.set_mapping(self.path_mapping());
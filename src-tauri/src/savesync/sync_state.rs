@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::SystemTime,
+};
+
+use super::config_paths;
+
+/// Last synced state of every (tag, cloud item), written through to sync_state.json
+static RECORDS: LazyLock<Mutex<HashMap<(String, OsString), SyncRecord>>> =
+    LazyLock::new(|| Mutex::new(load()));
+
+/// Name of this machine, kept with every record
+static DEVICE: LazyLock<String> = LazyLock::new(|| hostname().unwrap_or_else(|| "unknown".into()));
+
+/// Both sides of an item as they were when it was last synced, the baseline later
+/// changes are told apart by
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SyncRecord {
+    /// Newest modification time of the local files
+    pub local_modified: SystemTime,
+    /// last_modified of the cloud copy
+    pub cloud_modified: SystemTime,
+    /// Hex encoded SHA-256 of the synced archive
+    pub hash: String,
+    /// Machine the item was synced on, empty for records saved before it was kept
+    #[serde(default)]
+    pub device: String,
+}
+
+/// Sides of an item that changed since it was last synced
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Changed {
+    Neither,
+    Local,
+    Cloud,
+    Both,
+}
+
+/// Layout on disk, JSON objects cannot be keyed by tuples
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    tag: String,
+    item: OsString,
+    #[serde(flatten)]
+    record: SyncRecord,
+}
+
+pub fn get(tag: &str, item: &OsStr) -> Option<SyncRecord> {
+    RECORDS
+        .lock()
+        .unwrap()
+        .get(&(tag.to_owned(), item.to_owned()))
+        .cloned()
+}
+
+/// Records that `item` of `tag` was synced, saving at once so a crash does not lose it
+pub fn record(
+    tag: &str,
+    item: &OsStr,
+    local_modified: SystemTime,
+    cloud_modified: SystemTime,
+    hash: String,
+) {
+    let mut records = RECORDS.lock().unwrap();
+    records.insert(
+        (tag.to_owned(), item.to_owned()),
+        SyncRecord {
+            local_modified,
+            cloud_modified,
+            hash,
+            device: DEVICE.clone(),
+        },
+    );
+    save(&records)
+        .inspect_err(|e| println!("Unable to save the sync state: {e}"))
+        .ok();
+}
+
+/// Compares each side against its own state at the last sync of the item
+/// Items synced before the per-item records fall back to `last_sync`, when the app last closed,
+/// as do records another device wrote, whose local times describe its files instead of these
+///
+/// local < synced < cloud (Cloud)
+/// cloud < synced < local (Local)
+/// synced < local, synced < cloud (Both)
+/// local, cloud < synced (Neither)
+pub fn changed(
+    record: Option<&SyncRecord>,
+    last_sync: SystemTime,
+    local_modified: SystemTime,
+    cloud_modified: SystemTime,
+) -> Changed {
+    let (synced_local, synced_cloud) = record
+        .filter(|r| r.device == *DEVICE)
+        .map_or((last_sync, last_sync), |r| {
+            (r.local_modified, r.cloud_modified)
+        });
+
+    match (synced_local < local_modified, synced_cloud < cloud_modified) {
+        (false, false) => Changed::Neither,
+        (true, false) => Changed::Local,
+        (false, true) => Changed::Cloud,
+        (true, true) => Changed::Both,
+    }
+}
+
+/// Whether the cloud copy hashing to `hash` holds what was last synced, so it was only touched again
+pub fn same_content(record: Option<&SyncRecord>, hash: &str) -> bool {
+    record.is_some_and(|r| !hash.is_empty() && r.hash == hash)
+}
+
+/// Hex encoded SHA-256 of the archive at `path`, empty if it cannot be read
+pub fn hash_file(path: &Path) -> String {
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0; 1 << 16];
+    let hashed = File::open(path).and_then(|mut file| {
+        loop {
+            match file.read(&mut chunk)? {
+                0 => return Ok(()),
+                read => hasher.update(&chunk[..read]),
+            }
+        }
+    });
+    hashed.map_or_else(|_| String::new(), |_| hex::encode(hasher.finalize()))
+}
+
+fn state_path() -> PathBuf {
+    config_paths::config().join("sync_state.json")
+}
+
+fn load() -> HashMap<(String, OsString), SyncRecord> {
+    fs::read(state_path())
+        .ok()
+        .and_then(|json| serde_json::from_slice::<Vec<Entry>>(&json).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|Entry { tag, item, record }| ((tag, item), record))
+        .collect()
+}
+
+/// Replaces the file in one rename, so it is never left half written
+fn save(records: &HashMap<(String, OsString), SyncRecord>) -> io::Result<()> {
+    let entries: Vec<_> = records
+        .iter()
+        .map(|((tag, item), record)| Entry {
+            tag: tag.clone(),
+            item: item.clone(),
+            record: record.clone(),
+        })
+        .collect();
+
+    let partial = state_path().with_extension("json.partial");
+    fs::write(&partial, serde_json::to_vec(&entries)?)?;
+    fs::rename(partial, state_path())
+}
+
+#[cfg(windows)]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return None;
+    }
+    std::ffi::CStr::from_bytes_until_nul(&buffer)
+        .ok()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn synced(local: u64, cloud: u64) -> SyncRecord {
+        SyncRecord {
+            local_modified: at(local),
+            cloud_modified: at(cloud),
+            hash: "abc".into(),
+            device: DEVICE.clone(),
+        }
+    }
+
+    #[test]
+    fn compares_each_side_with_its_own_record() {
+        // the cloud clock is far ahead of the local one, only each side's own change counts
+        let record = synced(100, 5000);
+
+        assert_eq!(
+            changed(Some(&record), at(0), at(100), at(5000)),
+            Changed::Neither
+        );
+        assert_eq!(
+            changed(Some(&record), at(0), at(200), at(5000)),
+            Changed::Local
+        );
+        assert_eq!(
+            changed(Some(&record), at(0), at(100), at(6000)),
+            Changed::Cloud
+        );
+        assert_eq!(
+            changed(Some(&record), at(0), at(200), at(6000)),
+            Changed::Both
+        );
+        assert_eq!(
+            changed(Some(&record), at(0), at(50), at(4000)),
+            Changed::Neither
+        );
+    }
+
+    #[test]
+    fn falls_back_to_last_sync_without_record() {
+        assert_eq!(changed(None, at(100), at(50), at(200)), Changed::Cloud);
+        assert_eq!(changed(None, at(100), at(200), at(50)), Changed::Local);
+        assert_eq!(changed(None, at(100), at(200), at(300)), Changed::Both);
+        assert_eq!(changed(None, at(100), at(100), at(100)), Changed::Neither);
+    }
+
+    #[test]
+    fn ignores_records_of_other_devices() {
+        // copied over with the config of another machine, whose files were synced at 5000
+        let record = SyncRecord {
+            device: format!("not-{}", *DEVICE),
+            ..synced(5000, 5000)
+        };
+
+        assert_eq!(
+            changed(Some(&record), at(100), at(200), at(50)),
+            Changed::Local
+        );
+        assert_eq!(
+            changed(Some(&record), at(100), at(50), at(50)),
+            Changed::Neither
+        );
+    }
+
+    #[test]
+    fn same_content_needs_a_matching_hash() {
+        let record = synced(100, 100);
+
+        assert!(same_content(Some(&record), "abc"));
+        assert!(!same_content(Some(&record), "def"));
+        // an unreadable archive hashes to nothing and never matches
+        assert!(!same_content(
+            Some(&SyncRecord {
+                hash: String::new(),
+                ..record
+            }),
+            ""
+        ));
+        assert!(!same_content(None, "abc"));
+    }
+
+    #[test]
+    fn hashes_archives() {
+        let path = std::env::temp_dir().join(format!("savesync-hash-{}", std::process::id()));
+        fs::write(&path, b"abc").unwrap();
+
+        assert_eq!(
+            hash_file(&path),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        fs::remove_file(&path).unwrap();
+        assert_eq!(hash_file(&path), "");
+    }
+}
@@ -0,0 +1,289 @@
+use argon2::Argon2;
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use super::config_paths;
+
+/// Starts every encrypted credential file, files without it are plaintext
+const MAGIC: &[u8] = b"SSVAULT1";
+const NONCE_LEN: usize = 12;
+/// Encrypted into the header, so a wrong passphrase is told apart from a corrupt file
+const CHECK: &[u8] = b"savesync";
+
+/// Key derived from the passphrase, None while locked or unencrypted
+static KEY: RwLock<Option<Key>> = RwLock::new(None);
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultState {
+    /// Credentials are stored in plaintext
+    Unencrypted,
+    /// Credentials are encrypted and the passphrase has not been entered yet
+    Locked,
+    Unlocked,
+}
+
+/// Exists only while credentials are encrypted
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// Hex encoded argon2 salt
+    salt: String,
+    /// Hex encoded CHECK sealed with the key
+    check: String,
+}
+
+pub fn state() -> VaultState {
+    match (header_path().exists(), KEY.read().unwrap().is_some()) {
+        (false, _) => VaultState::Unencrypted,
+        (true, false) => VaultState::Locked,
+        (true, true) => VaultState::Unlocked,
+    }
+}
+
+/// Derives the key from `passphrase`, failing if it does not open the vault
+pub fn unlock(passphrase: &str) -> Result<(), String> {
+    let header = read_header()?;
+    let key = derive_key(passphrase, &decode(&header.salt)?)?;
+    open(&key, &decode(&header.check)?, b"")
+        .filter(|check| check == CHECK)
+        .ok_or("Wrong passphrase")?;
+
+    *KEY.write().unwrap() = Some(key);
+    Ok(())
+}
+
+/// Encrypts every saved credential with a key derived from `passphrase`
+pub fn enable(passphrase: &str) -> Result<(), String> {
+    if state() != VaultState::Unencrypted {
+        return Err("Credentials are already encrypted".into());
+    }
+    if passphrase.is_empty() {
+        return Err("The passphrase cannot be empty".into());
+    }
+
+    let mut salt = [0; 16];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt)?;
+    let credentials = credential_files()
+        .into_iter()
+        .map(|path| Ok((fs::read(&path)?, path)))
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    let header = Header {
+        salt: hex::encode(salt),
+        check: hex::encode(seal(&key, CHECK, b"")?),
+    };
+    fs::write(
+        header_path(),
+        serde_json::to_string(&header).map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| e.to_string())?;
+    *KEY.write().unwrap() = Some(key);
+
+    credentials
+        .into_iter()
+        .try_for_each(|(plaintext, path)| write_file(&path, &plaintext))
+        .map_err(|e| e.to_string())
+}
+
+/// Stores every credential in plaintext again, the vault has to be unlocked
+pub fn disable() -> Result<(), String> {
+    if state() != VaultState::Unlocked {
+        return Err("Unlock the credentials before storing them unencrypted".into());
+    }
+
+    let credentials = credential_files()
+        .into_iter()
+        .map(|path| read_file(&path).map(|plaintext| (plaintext, path)))
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    fs::remove_file(header_path()).map_err(|e| e.to_string())?;
+    *KEY.write().unwrap() = None;
+
+    credentials
+        .into_iter()
+        .try_for_each(|(plaintext, path)| fs::write(path, plaintext))
+        .map_err(|e| e.to_string())
+}
+
+/// Credentials saved for the backend `filename`, None if there are none or the vault is locked
+pub fn read(filename: &OsStr) -> Option<String> {
+    read_file(&credential_path(filename))
+        .ok()
+        .and_then(|plaintext| String::from_utf8(plaintext).ok())
+}
+
+/// Saves the credentials of the backend `filename`, encrypted unless the vault is unencrypted
+pub fn write(filename: &OsStr, credentials: &str) -> io::Result<()> {
+    write_file(&credential_path(filename), credentials.as_bytes())
+}
+
+pub fn exists(filename: &OsStr) -> bool {
+    credential_path(filename).exists()
+}
+
+pub fn remove(filename: &OsStr) -> io::Result<()> {
+    fs::remove_file(credential_path(filename))
+}
+
+/// Key handed to plugin host processes, which cannot ask for the passphrase themselves
+pub fn export_key() -> Option<Vec<u8>> {
+    KEY.read().unwrap().map(|key| key.to_vec())
+}
+
+pub fn import_key(key: &[u8]) {
+    if key.len() == 32 {
+        *KEY.write().unwrap() = Some(*Key::from_slice(key));
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    let Some(sealed) = bytes.strip_prefix(MAGIC) else {
+        return Ok(bytes);
+    };
+
+    let key = KEY
+        .read()
+        .unwrap()
+        .ok_or_else(|| io::Error::new(ErrorKind::PermissionDenied, "Credentials are locked"))?;
+    open(&key, sealed, aad(path)).ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} could not be decrypted", path.to_string_lossy()),
+        )
+    })
+}
+
+fn write_file(path: &Path, plaintext: &[u8]) -> io::Result<()> {
+    if !header_path().exists() {
+        return fs::write(path, plaintext);
+    }
+
+    let key = KEY
+        .read()
+        .unwrap()
+        .ok_or_else(|| io::Error::new(ErrorKind::PermissionDenied, "Credentials are locked"))?;
+    let sealed = seal(&key, plaintext, aad(path)).map_err(io::Error::other)?;
+    fs::write(path, [MAGIC, sealed.as_slice()].concat())
+}
+
+/// [nonce][ciphertext], `aad` ties the ciphertext to the file it was written to
+fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+}
+
+fn open(key: &Key, sealed: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+    let (nonce, ciphertext) = sealed.split_at_checked(NONCE_LEN)?;
+    ChaCha20Poly1305::new(key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .ok()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+fn aad(path: &Path) -> &[u8] {
+    path.file_name().unwrap_or_default().as_encoded_bytes()
+}
+
+fn decode(hex: &str) -> Result<Vec<u8>, String> {
+    hex::decode(hex).map_err(|_| "Corrupt credential vault header".to_owned())
+}
+
+fn read_header() -> Result<Header, String> {
+    let header = fs::read_to_string(header_path()).map_err(|e| e.to_string())?;
+    serde_json::from_str(&header).map_err(|_| "Corrupt credential vault header".to_owned())
+}
+
+fn header_path() -> PathBuf {
+    config_paths::creds().join("vault.json")
+}
+
+fn credential_path(filename: &OsStr) -> PathBuf {
+    config_paths::create_credential_path(filename.to_owned())
+}
+
+/// Every saved credential file, found by their .auth extension
+fn credential_files() -> Vec<PathBuf> {
+    fs::read_dir(config_paths::creds())
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|path| path.extension() == Some(OsStr::new("auth")))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_credentials_round_trip() {
+        let key = derive_key("passphrase", b"0123456789abcdef").unwrap();
+        let sealed = seal(&key, b"token", b"plugin.auth").unwrap();
+
+        assert_ne!(&sealed[NONCE_LEN..], b"token");
+        assert_eq!(open(&key, &sealed, b"plugin.auth").unwrap(), b"token");
+    }
+
+    #[test]
+    fn rejects_wrong_key_and_moved_files() {
+        let key = derive_key("passphrase", b"0123456789abcdef").unwrap();
+        let other = derive_key("other", b"0123456789abcdef").unwrap();
+        let sealed = seal(&key, b"token", b"plugin.auth").unwrap();
+
+        assert!(open(&other, &sealed, b"plugin.auth").is_none());
+        // credentials copied over another plugin's file do not open
+        assert!(open(&key, &sealed, b"other.auth").is_none());
+        assert!(open(&key, &sealed[..NONCE_LEN - 1], b"plugin.auth").is_none());
+    }
+
+    #[test]
+    fn derives_the_same_key_from_the_same_salt() {
+        let salt = b"0123456789abcdef";
+        assert_eq!(
+            derive_key("passphrase", salt).unwrap(),
+            derive_key("passphrase", salt).unwrap()
+        );
+        assert_ne!(
+            derive_key("passphrase", salt).unwrap(),
+            derive_key("passphrase", b"fedcba9876543210").unwrap()
+        );
+    }
+}
@@ -1,18 +1,22 @@
 use notify_debouncer_full::{Debouncer, RecommendedCache, new_debouncer, notify::*};
 use std::{
     collections::HashMap,
-    ffi::OsString,
-    fs,
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::Read,
     path::{Path, PathBuf},
     sync::{LazyLock, Mutex},
     time::Duration,
 };
 
 use crate::{
-    app_store, read_app_state,
+    app_store, loaded_plugin,
     savesync::{
+        backend::Capabilities,
+        cancellation::Transfer,
         notifications::{DesktopNotification, sync_notify},
-        zip_utils,
+        progress::Tracked,
+        sync_state, zip_utils,
     },
 };
 
@@ -34,14 +38,12 @@ pub fn upload_file(tag: &str, path: &PathType) {
         silent: true,
     });
 
-    let (zipbuffer, date) = match path {
+    let (archive, date) = match path {
         PathType::Directory(path) => zip_dir(&app_store().resolve_path(tag, path)),
         PathType::File(path) => {
-            let abspath = &app_store().resolve_path(tag, path);
-            (
-                fs::read(abspath).unwrap(),
-                fs::metadata(abspath).unwrap().modified().unwrap(),
-            )
+            let abspath = app_store().resolve_path(tag, path);
+            let date = fs::metadata(&abspath).unwrap().modified().unwrap();
+            (abspath, date)
         }
     };
 
@@ -50,15 +52,50 @@ pub fn upload_file(tag: &str, path: &PathType) {
         PathType::File(path_buf) => path_buf.to_owned(),
     };
 
-    read_app_state(|s| {
-        s.plugin_ref().upload(
+    let file = File::open(&archive).unwrap();
+    let len = file.metadata().unwrap().len();
+    let transfer = Transfer::begin(tag, refpath.as_os_str());
+
+    let uploaded = loaded_plugin().is_some_and(|plugin| {
+        let reader = transfer.token().guard(file);
+        // backends buffering the archive read it whole before uploading anything
+        let reader: Box<dyn Read + Send> = match plugin.supports(Capabilities::STREAMING) {
+            true => Box::new(Tracked::new(tag, refpath.as_os_str(), len, reader)),
+            false => Box::new(reader),
+        };
+        plugin.upload_stream(
             tag.as_bytes(),
             refpath.as_os_str().as_encoded_bytes(),
             date,
-            zipbuffer.as_slice(),
+            reader,
+            len,
         )
     });
 
+    // the cloud lists the upload with `date`, which is what later changes are compared to
+    if uploaded && !transfer.is_cancelled() {
+        sync_state::record(
+            tag,
+            refpath.as_os_str(),
+            date,
+            date,
+            sync_state::hash_file(&archive),
+        );
+    }
+
+    if let PathType::Directory(_) = path {
+        let _ = fs::remove_file(archive);
+    }
+
+    if transfer.is_cancelled() {
+        sync_notify(DesktopNotification {
+            title: Some(format!("Syncing {tag} cancelled")),
+            body: Some("The cloud keeps its previous copy".into()),
+            silent: true,
+        });
+        return;
+    }
+
     sync_notify(DesktopNotification {
         title: Some(format!("Syncing {tag} done")),
         body: Some("Done syncing".into()),
@@ -66,7 +103,8 @@ pub fn upload_file(tag: &str, path: &PathType) {
     });
 }
 
-pub fn handle_buffer(path: impl AsRef<Path>, foldername: &PathType, buffer: Vec<u8>) {
+/// Moves a staged download into place, extracting it if it is a directory archive
+pub fn handle_buffer(path: impl AsRef<Path>, foldername: &PathType, archive: PathBuf) {
     sync_notify(DesktopNotification {
         title: Some("Outdated saves".into()),
         body: Some(format!(
@@ -77,10 +115,11 @@ pub fn handle_buffer(path: impl AsRef<Path>, foldername: &PathType, buffer: Vec<
     });
 
     if let PathType::File(foldername) = foldername {
-        fs::write(path.as_ref().join(foldername), buffer).unwrap();
+        fs::copy(&archive, path.as_ref().join(foldername)).unwrap();
     } else {
-        zip_utils::extract(path.as_ref().with_extension(""), buffer).unwrap()
+        zip_utils::extract(path.as_ref().with_extension(""), &archive).unwrap()
     }
+    let _ = fs::remove_file(archive);
 
     sync_notify(DesktopNotification {
         title: Some("Saves synced".into()),
@@ -155,20 +194,15 @@ pub fn toggle_watch(tag: &str, path: &OsString) -> bool {
         // exist, !initial => remove
         // exist, initial => nothing
 
-        let path = Path::new(path);
-        let abspath = app_store().resolve_path(&tag, &path);
-        let pathbuf = if abspath.is_dir() {
-            path.with_extension(ZIPEXTENSION)
-        } else {
-            path.into()
-        };
+        let pathbuf = cloud_name(tag, path);
         match map.contains_key(&key) {
             true => {
                 map.remove(&key);
-                read_app_state(|s| {
-                    s.plugin_ref()
-                        .remove(tag.as_bytes(), pathbuf.as_os_str().as_encoded_bytes())
-                });
+                if let Some(plugin) = loaded_plugin()
+                    && plugin.supports(Capabilities::REMOVE)
+                {
+                    plugin.remove(tag.as_bytes(), pathbuf.as_os_str().as_encoded_bytes());
+                }
                 false
             }
             false => {
@@ -179,6 +213,16 @@ pub fn toggle_watch(tag: &str, path: &OsString) -> bool {
     })
 }
 
+/// Name of a watched file or folder in the cloud, folders are uploaded zipped
+pub fn cloud_name(tag: &str, path: &OsStr) -> PathBuf {
+    let path = Path::new(path);
+    if app_store().resolve_path(tag, path).is_dir() {
+        path.with_extension(ZIPEXTENSION)
+    } else {
+        path.into()
+    }
+}
+
 pub fn watched_folders() -> Vec<(String, OsString)> {
     mutate_watchers(|map| {
         map.iter()
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    sync::{
+        LazyLock, Mutex, Once,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{emitter, plugin_host};
+use crate::app_store;
+
+/// How often the watchdog looks for calls past their deadline
+const PATROL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Plugin calls being watched, by the id of their Watch
+static DEADLINES: LazyLock<Mutex<HashMap<u64, Deadline>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static PATROL: Once = Once::new();
+
+struct Deadline {
+    at: Instant,
+    timeout: Duration,
+    filename: OsString,
+    call: &'static str,
+    /// (tag, folder_name) of the file the call transfers, whose progress pushes `at` back
+    file: Option<(Vec<u8>, Vec<u8>)>,
+    on_timeout: Box<dyn FnOnce() + Send>,
+}
+
+/// Running plugin call, no longer watched once dropped
+pub struct Watch(Option<u64>);
+
+impl Watch {
+    /// Marks the call as transferring the file, so it only times out once the
+    /// transfer stops making progress instead of after the timeout in total
+    pub fn transferring(&self, tag: &[u8], folder_name: &[u8]) {
+        if let Some(id) = self.0
+            && let Some(deadline) = DEADLINES.lock().unwrap().get_mut(&id)
+        {
+            deadline.file = Some((tag.to_vec(), folder_name.to_vec()));
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            DEADLINES.lock().unwrap().remove(&id);
+        }
+    }
+}
+
+/// Watches a call to the plugin `filename`, reporting it and running `on_timeout`
+/// on the watchdog thread if it outlives the configured plugin timeout
+/// A call whose transfer reports progress gets the whole timeout again from then on
+/// Plugin hosts are watched by their parent, so calls in the child are not
+pub fn watch(
+    filename: &OsStr,
+    call: &'static str,
+    on_timeout: impl FnOnce() + Send + 'static,
+) -> Watch {
+    if plugin_host::is_child() {
+        return Watch(None);
+    }
+
+    PATROL.call_once(|| {
+        thread::Builder::new()
+            .name("plugin watchdog".into())
+            .spawn(patrol)
+            .unwrap();
+    });
+
+    let timeout = app_store().plugin_timeout();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    DEADLINES.lock().unwrap().insert(
+        id,
+        Deadline {
+            at: Instant::now() + timeout,
+            timeout,
+            filename: filename.to_owned(),
+            call,
+            file: None,
+            on_timeout: Box::new(on_timeout),
+        },
+    );
+    Watch(Some(id))
+}
+
+/// Pushes back the deadlines of calls transferring the file, which is still moving
+pub fn progressed(tag: &str, folder_name: &OsStr) {
+    let now = Instant::now();
+    for deadline in DEADLINES.lock().unwrap().values_mut() {
+        if deadline
+            .file
+            .as_ref()
+            .is_some_and(|(t, f)| t == tag.as_bytes() && f == folder_name.as_encoded_bytes())
+        {
+            deadline.at = now + deadline.timeout;
+        }
+    }
+}
+
+fn patrol() {
+    loop {
+        thread::sleep(PATROL_INTERVAL);
+
+        let now = Instant::now();
+        let expired: Vec<Deadline> = {
+            let mut deadlines = DEADLINES.lock().unwrap();
+            let ids: Vec<u64> = deadlines
+                .iter()
+                .filter(|(_, deadline)| deadline.at <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.iter().filter_map(|id| deadlines.remove(id)).collect()
+        };
+
+        // handled outside the lock, on_timeout may wait on the call it stops
+        for deadline in expired {
+            emitter::plugin_error(
+                &deadline.filename,
+                &format!(
+                    "{} did not answer or make progress within {} seconds",
+                    deadline.call,
+                    deadline.timeout.as_secs()
+                ),
+            );
+            (deadline.on_timeout)();
+        }
+    }
+}
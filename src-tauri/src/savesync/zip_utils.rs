@@ -1,25 +1,27 @@
 use std::{
     cmp::max,
-    fs,
-    io::{Cursor, Write},
-    path::Path,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
     time::SystemTime,
 };
 
 use zip::{result::ZipResult, write::SimpleFileOptions, ZipArchive, ZipWriter};
 
-use crate::savesync::fs_utils::recurse_directories;
+use crate::savesync::{config_paths, fs_utils::recurse_directories};
 
-pub fn extract(directory: impl AsRef<Path>, buffer: Vec<u8>) -> ZipResult<()> {
-    ZipArchive::new(Cursor::new(buffer))?.extract(directory)
+pub fn extract(directory: impl AsRef<Path>, archive: &Path) -> ZipResult<()> {
+    ZipArchive::new(File::open(archive)?)?.extract(directory)
 }
 
-pub fn zip_dir<P>(path: P) -> (Vec<u8>, SystemTime)
+/// Zips the directory into a staging file, returning its path and the
+/// newest modified date of the files inside
+pub fn zip_dir<P>(path: P) -> (PathBuf, SystemTime)
 where
     P: AsRef<Path>,
 {
-    let buffer = Cursor::new(vec![]);
-    let mut zip = ZipWriter::new(buffer);
+    let archive = config_paths::staging_file();
+    let mut zip = ZipWriter::new(File::create(&archive).unwrap());
 
     let date = recurse_directories(
         path.as_ref(),
@@ -28,18 +30,20 @@ where
             let filename = e.file_name();
             zip.start_file_from_path(relative_path.join(&filename), SimpleFileOptions::default())
                 .unwrap();
-            zip.write_all(&fs::read(p.join(&filename)).unwrap())
-                .unwrap();
+            io::copy(&mut File::open(p.join(&filename))?, &mut zip)?;
             e.metadata()?.modified()
         },
         &max,
     )
     .unwrap_or(SystemTime::UNIX_EPOCH);
-    (
-        zip.finish_into_readable()
-            .unwrap()
-            .into_inner()
-            .into_inner(),
-        date,
-    )
+
+    zip.finish().unwrap();
+    (archive, date)
+}
+
+/// Writes `data` into a staging file
+pub fn stage_buffer(data: &[u8]) -> io::Result<PathBuf> {
+    let path = config_paths::staging_file();
+    fs::write(&path, data)?;
+    Ok(path)
 }
@@ -20,12 +20,19 @@ export function listen<T extends keyof ListenTypes>(eventName: T | EventName, ha
   return old_listen<ListenTypes[T]>(eventName, ({ payload }) => handler(payload), option)
 }
 
+const isWindows = navigator.userAgent.includes("Windows")
+
 export function osStringToString(osString?: OsString) {
-  return osString ? String.fromCharCode(...osString.Windows) : ""
+  if (!osString) return ""
+  return "Windows" in osString
+    ? String.fromCharCode(...osString.Windows)
+    : new TextDecoder().decode(new Uint8Array(osString.Unix))
 }
 
 export function stringToOsString(str: string): OsString {
-  return { Windows: str.split('').map(s => s.charCodeAt(0)) }
+  return isWindows
+    ? { Windows: str.split('').map(s => s.charCodeAt(0)) }
+    : { Unix: Array.from(new TextEncoder().encode(str)) }
 }
 
 export function systemTimeToNumber(time: SystemTime) {
@@ -7,7 +7,9 @@ import { useNavigate, useParams } from "@solidjs/router";
 import Folder from "@suid/icons-material/Folder"
 import InsertDriveFile from "@suid/icons-material/InsertDriveFile"
 import Loop from "@suid/icons-material/Loop"
+import Close from "@suid/icons-material/Close"
 import { Index } from "solid-js";
+import { createStore } from "solid-js/store";
 import { Portal } from "solid-js/web";
 
 export default function Folders() {
@@ -17,9 +19,17 @@ export default function Folders() {
     Object.entries(params).map(([k, v]) => [k, decodeURIComponent(v)])
   )
 
+  // percentage, or bytes when the total is unknown
+  const [progress, setProgress] = createStore<Record<string, string>>({})
+
   unlisten([
     listen("sync_result", ([tag, folder, bool]) => {
       setFolders(tag, osStringToString(folder), { loading: false, synced: bool })
+      if (tag === TAGNAME) setProgress(osStringToString(folder), "")
+    }),
+    listen("transfer_progress", ([tag, folder, done, total]) => {
+      if (tag !== TAGNAME) return
+      setProgress(osStringToString(folder), total ? `${Math.floor(done * 100 / total)}%` : `${(done / 1e6).toFixed(1)} MB`)
     })
   ])
 
@@ -28,6 +38,11 @@ export default function Folders() {
     emit("sync", { tag: TAGNAME, foldername })
   }
 
+  const abort_folder = (foldername: OsString, e: MouseEvent) => {
+    e.stopPropagation()
+    emit("abort", { tag: TAGNAME, foldername })
+  }
+
   return <PageRoot>
     <div class="w-full h-screen flex-col content-center justify-center items-center">
       <Index each={Object.entries(folders[TAGNAME])}>
@@ -36,6 +51,9 @@ export default function Folders() {
             <input type="checkbox" class="mr-4 rounded-2xl" checked={foldername()[1].synced} onclick={(e) => e.preventDefault()} />
             {foldername()[1].folder ? <Folder /> : <InsertDriveFile />} {foldername()[0]}
             <Loop style={{ "visibility": foldername()[1].loading ? "visible" : "hidden" }} class="ml-2" />
+            <span class="ml-1">{foldername()[1].loading ? progress[foldername()[0]] : ""}</span>
+            <Close style={{ "visibility": foldername()[1].loading ? "visible" : "hidden" }} class="ml-1 cursor-pointer"
+              onclick={[abort_folder, stringToOsString(foldername()[0])]} />
           </DivButton>
         }
       </Index>
@@ -5,9 +5,11 @@ import { useNavigate } from "@solidjs/router";
 import { open } from "@tauri-apps/plugin-dialog"
 
 import { emit, listen, invoke, unlisten, stringToOsString, osStringToString } from "@/logic/backend";
-import { Info } from "@/types/data";
-import { Button, Dialog, DialogActions, DialogContent, DialogContentText, DialogTitle } from "@suid/material";
+import { DeviceCode, Info, SettingsSchema, SettingsValues } from "@/types/data";
+import { Button, Dialog, DialogActions, DialogContent, DialogContentText, DialogTitle, Switch, TextField } from "@suid/material";
 import Logout from "@suid/icons-material/Logout";
+import Settings from "@suid/icons-material/Settings";
+import Devices from "@suid/icons-material/Devices";
 import { OsString } from "@/types/rust";
 
 export default function PluginSelect() {
@@ -17,23 +19,71 @@ export default function PluginSelect() {
   const [loggedInPlugins, setLoggedInPlugins] = createStore<Record<string, boolean>>({})
   const [loading, setLoading] = createSignal<AbortInfo | undefined>()
   const [filepath, setFilepath] = createSignal<string>("")
+  const [formPending, setFormPending] = createSignal<AbortInfo | undefined>()
+  const [formValues, setFormValues] = createStore<Record<string, string>>({})
+  const pendingForm = () => FORM_LOGINS[osStringToString(formPending()?.filename)]
+  const [settingsPlugin, setSettingsPlugin] = createSignal<OsString | undefined>()
+  const [settingsSchema, setSettingsSchema] = createSignal<SettingsSchema | undefined>()
+  const [settingsValues, setSettingsValues] = createStore<SettingsValues>({})
+  const settingsFields = () => Object.entries(settingsSchema()?.properties ?? {})
+  const [locked, setLocked] = createSignal(false)
+  const [passphrase, setPassphrase] = createSignal("")
+  const [unlockError, setUnlockError] = createSignal("")
+  const [deviceCode, setDeviceCode] = createSignal<DeviceCode | undefined>()
 
   function onFileSelected(filepath: string | null) {
     if (!filepath) return
     setFilepath(filepath)
   }
 
-  function init(pair: AbortInfo) {
+  async function init(pair: AbortInfo) {
+    const filename = osStringToString(pair.filename)
+    if (filename == LOCAL_FOLDER && !loggedInPlugins[filename]) {
+      const path = await open({ directory: true, multiple: false, title: "Select folder to sync to" })
+      if (!path) return
+      await invoke("set_local_folder", { path })
+    } else if (filename in FORM_LOGINS && !loggedInPlugins[filename]) {
+      setFormValues(reconcile(Object.fromEntries(FORM_LOGINS[filename].fields.map(([key, , value]) => [key, value ?? ""]))))
+      setFormPending(pair)
+      return
+    }
+
+    setLoading(pair)
+    emit("init", pair.filename)
+  }
+
+  async function loginForm() {
+    const pair = formPending()!
+    const { command } = pendingForm()
+    setFormPending()
+    await invoke(command, { login: { ...formValues } } as any)
     setLoading(pair)
     emit("init", pair.filename)
   }
 
   unlisten([
-    listen("init_result", () => navigate("/tags"))
+    listen("init_result", () => navigate("/tags")),
+    // also sent for logins started with --device-login
+    listen("device_code", ([, code]) => setDeviceCode(code)),
+    listen("plugin_error", () => setDeviceCode())
   ])()
 
-  // run on app boot
-  emit("saved_plugin")
+  // run on app boot, the saved plugin cannot log in until the credentials are unlocked
+  invoke("vault_state").then(state => state == "locked" ? setLocked(true) : emit("saved_plugin"))
+
+  async function unlock() {
+    try {
+      await invoke("unlock_vault", { passphrase: passphrase() })
+    } catch (e) {
+      setUnlockError(String(e))
+      return
+    }
+    setPassphrase("")
+    setUnlockError("")
+    setLocked(false)
+    emit("saved_plugin")
+    refresh()
+  }
 
   const refresh = () => invoke("get_plugins").then(plugins => {
     setServices(reconcile(plugins.sort((p1, p2) => p1.name.localeCompare(p2.name))))
@@ -66,7 +116,61 @@ export default function PluginSelect() {
     }
   }
 
+  function deviceLogin(plugin: OsString) {
+    return (e: MouseEvent) => {
+      e.stopPropagation()
+      invoke("start_device_login", { filepath: plugin })
+    }
+  }
+
+  function openSettings(plugin: OsString) {
+    return async (e: MouseEvent) => {
+      e.stopPropagation()
+      const settings = await invoke("get_plugin_settings", { filepath: plugin })
+      setSettingsSchema(settings?.schema)
+      setSettingsValues(reconcile(Object.fromEntries(settingsFields().map(([key, field]) =>
+        [key, settings?.values[key] ?? field.default ?? (field.type == "boolean" ? false : "")]))))
+      setSettingsPlugin(plugin)
+    }
+  }
+
+  async function saveSettings() {
+    const values = Object.fromEntries(settingsFields().map(([key, field]) => {
+      const value = settingsValues[key]
+      return [key, field.type == "number" || field.type == "integer" ? Number(value) : value]
+    }))
+    await invoke("set_plugin_settings", { filepath: settingsPlugin()!, values })
+    setSettingsPlugin()
+  }
+
   return <>
+    <Dialog open={locked()}>
+      <DialogTitle>Unlock your credentials</DialogTitle>
+      <DialogContent>
+        <DialogContentText>
+          Saved logins are encrypted, enter the passphrase to use them
+        </DialogContentText>
+        <TextField label="Passphrase" type="password" fullWidth margin="dense" value={passphrase()}
+          error={unlockError() != ""} helperText={unlockError()}
+          onChange={(_, value) => setPassphrase(value)} />
+      </DialogContent>
+      <DialogActions>
+        <Button onClick={unlock} disabled={!passphrase()}>Unlock</Button>
+        <Button onClick={[setLocked, false]}>Skip</Button>
+      </DialogActions>
+    </Dialog>
+    <Dialog open={deviceCode() != undefined}>
+      <DialogTitle>Log in on another device</DialogTitle>
+      <DialogContent>
+        <DialogContentText>
+          Open {deviceCode()?.verification_uri} on a phone or computer and enter the code
+        </DialogContentText>
+        <h2 class="text-center font-mono">{deviceCode()?.user_code}</h2>
+      </DialogContent>
+      <DialogActions>
+        <Button onClick={() => setDeviceCode()}>Close</Button>
+      </DialogActions>
+    </Dialog>
     <Dialog open={filepath()?.length != 0}>
       <DialogTitle>Are you sure you want to add this plugin?</DialogTitle>
       <DialogContent>
@@ -79,6 +183,50 @@ export default function PluginSelect() {
         <Button onClick={[setFilepath, ""]}>Cancel</Button>
       </DialogActions>
     </Dialog>
+    <Dialog open={formPending() != undefined}>
+      <DialogTitle>{pendingForm()?.title}</DialogTitle>
+      <DialogContent>
+        <Index each={pendingForm()?.fields ?? []}>
+          {field =>
+            <TextField label={field()[1]} type={field()[0].includes("password") || field()[0].includes("secret") ? "password" : "text"}
+              fullWidth margin="dense" value={formValues[field()[0]] ?? ""}
+              onChange={(_, value) => setFormValues(field()[0], value)} />
+          }
+        </Index>
+      </DialogContent>
+      <DialogActions>
+        <Button onClick={loginForm}>Log in</Button>
+        <Button onClick={() => setFormPending()}>Cancel</Button>
+      </DialogActions>
+    </Dialog>
+    <Dialog open={settingsPlugin() != undefined}>
+      <DialogTitle>Plugin settings</DialogTitle>
+      <DialogContent>
+        <Show when={settingsFields().length} fallback={<DialogContentText>This plugin has no settings</DialogContentText>}>
+          <Index each={settingsFields()}>
+            {field =>
+              <Show when={field()[1].type != "boolean"} fallback={
+                <div>
+                  <span>{field()[1].title ?? field()[0]}</span>
+                  <Switch checked={settingsValues[field()[0]] == true} onChange={(_, checked) => setSettingsValues(field()[0], checked)} />
+                </div>
+              }>
+                <TextField label={field()[1].title ?? field()[0]} helperText={field()[1].description}
+                  type={field()[1].type == "number" || field()[1].type == "integer" ? "number" : "text"}
+                  fullWidth margin="dense" value={settingsValues[field()[0]] ?? ""}
+                  onChange={(_, value) => setSettingsValues(field()[0], value)} />
+              </Show>
+            }
+          </Index>
+        </Show>
+      </DialogContent>
+      <DialogActions>
+        <Show when={settingsFields().length}>
+          <Button onClick={saveSettings}>Save</Button>
+        </Show>
+        <Button onClick={() => setSettingsPlugin()}>Cancel</Button>
+      </DialogActions>
+    </Dialog>
     <main class="container items-center">
       <Portal>
         <div class="fixed right-0 bottom-0 m-4">
@@ -87,7 +235,7 @@ export default function PluginSelect() {
       </Portal>
       <Show when={!loading()} fallback={<>
         <h1>Now loading: {loading()!.name}</h1>
-        <button onClick={() => { emit("abort", loading()!.filename); setLoading() }}>Cancel loading</button>
+        <button onClick={() => setLoading()}>Cancel loading</button>
       </>}>
         <h1>Welcome to Tauri + Solid + Lua</h1>
         <div class="space-y-5">
@@ -104,6 +252,8 @@ export default function PluginSelect() {
                   <span>Written by: {elem().author}</span>
                 </div>
                 <div class="inline-block">
+                  <Settings class="size-full content-center hover:outline-2" onClick={openSettings(elem().filename)} />
+                  <Devices class="size-full content-center hover:outline-2" onClick={deviceLogin(elem().filename)} />
                   <Show when={loggedInPlugins[osStringToString(elem().filename)]}>
                     <Logout class="bg-red-600 size-full content-center hover:outline-2" onClick={logout(elem().filename)} />
                   </Show>
@@ -123,3 +273,22 @@ export default function PluginSelect() {
 }
 
 type AbortInfo = Pick<Info, "name" | "filename">
+
+/** Filename of the built-in local folder backend, which logs in by choosing a folder */
+const LOCAL_FOLDER = "savesync-local-folder"
+/**
+ *  Built-in backends which log in with a form instead of OAuth
+ *  fields: [key, label, default value]
+ */
+const FORM_LOGINS: Record<string, { title: string, command: "set_webdav_login" | "set_s3_login", fields: [string, string, string?][] }> = {
+  "savesync-webdav": {
+    title: "Log in to your WebDAV server",
+    command: "set_webdav_login",
+    fields: [["url", "Server URL"], ["username", "Username"], ["password", "Password or app password"]]
+  },
+  "savesync-s3": {
+    title: "Connect to your S3-compatible bucket",
+    command: "set_s3_login",
+    fields: [["endpoint", "Endpoint URL"], ["bucket", "Bucket"], ["region", "Region", "us-east-1"], ["access_key", "Access key"], ["secret_key", "Secret key"]]
+  }
+}
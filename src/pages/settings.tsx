@@ -1,22 +1,38 @@
 import PageRoot from "@/PageRoot"
 import Switch from "@suid/material/Switch"
+import { Button, TextField } from "@suid/material"
+import { invoke } from "@/logic/backend"
+import { VaultState } from "@/types/data"
 import { enable, disable, isEnabled } from "@tauri-apps/plugin-autostart"
 import { load } from '@tauri-apps/plugin-store'
-import { createSignal } from "solid-js"
+import { createSignal, Show } from "solid-js"
 
 export default function Settings() {
   const [autoStartup, setAutoStartup] = createSignal<boolean>(false)
   const [closeBehaviour, setCloseBehaviour] = createSignal<boolean>(true)
   const [silenceMissingMappings, setSilenceMappingsMissing] = createSignal<boolean>(false)
   const [syncNotifications, setSyncNotifications] = createSignal<boolean>(true)
+  const [isolatePlugins, setIsolatePlugins] = createSignal<boolean>(false)
+  const [trustedKey, setTrustedKey] = createSignal("")
+  const [pluginTimeout, setPluginTimeout] = createSignal<number>(300)
+  const [oauthPorts, setOauthPorts] = createSignal("")
+  const [oauthDeepLink, setOauthDeepLink] = createSignal<boolean>(false)
+  const [vaultState, setVaultState] = createSignal<VaultState>("unencrypted")
+  const [vaultPassphrase, setVaultPassphrase] = createSignal("")
+  const [vaultError, setVaultError] = createSignal("")
 
   isEnabled().then(setAutoStartup)
+  invoke("vault_state").then(setVaultState)
 
   const store = load("store.json")
   store.then(s => {
     s.get<boolean>("silenceMissingMappings").then(setSilenceMappingsMissing)
     s.get<boolean>("hide_to_tray").then(setCloseBehaviour)
     s.get<boolean>("sync_notifications").then(setSyncNotifications)
+    s.get<boolean>("isolate_plugins").then(setIsolatePlugins)
+    s.get<number>("plugin_timeout").then(t => t && setPluginTimeout(t))
+    s.get<number[]>("oauth_ports").then(ports => ports && setOauthPorts(ports.join(", ")))
+    s.get<boolean>("oauth_deep_link").then(setOauthDeepLink)
   })
 
   async function toggleAutoStartup(_: any, checked: boolean) {
@@ -34,6 +50,50 @@ export default function Settings() {
     }
   }
 
+  async function savePluginTimeout(value: string) {
+    const seconds = parseInt(value)
+    if (!(seconds > 0)) return
+    setPluginTimeout(seconds)
+    await store.then(async s => {
+      await s.set("plugin_timeout", seconds)
+      await s.save()
+    })
+  }
+
+  /** Accepts ports and ranges like "5000-5009, 8080" */
+  async function saveOauthPorts(value: string) {
+    setOauthPorts(value)
+    const ports = value.split(",").map(s => s.trim()).filter(s => s).flatMap(part => {
+      const [start, end = start] = part.split("-").map(Number)
+      return Array.from({ length: end - start + 1 }, (_, i) => start + i)
+    })
+    if (!ports.length || ports.some(port => !Number.isInteger(port) || port < 1 || port > 65535)) return
+    await store.then(async s => {
+      await s.set("oauth_ports", ports)
+      await s.save()
+    })
+  }
+
+  async function toggleVault() {
+    try {
+      if (vaultState() == "unencrypted") {
+        await invoke("enable_vault", { passphrase: vaultPassphrase() })
+      } else {
+        await invoke("disable_vault")
+      }
+      setVaultError("")
+    } catch (e) {
+      setVaultError(String(e))
+    }
+    setVaultPassphrase("")
+    setVaultState(await invoke("vault_state"))
+  }
+
+  async function addTrustedKey() {
+    await invoke("add_trusted_key", { key: trustedKey() })
+    setTrustedKey("")
+  }
+
   return <PageRoot>
     <div class="overflow-y-scroll m-4">
       <h1 class="font-bold text-3xl justify-self-start mb-2">Notifications</h1>
@@ -58,6 +118,46 @@ export default function Settings() {
           <span>Hide when closed</span>
           <Switch onChange={toggleSetterAndSave(setCloseBehaviour, "hide_to_tray")} checked={closeBehaviour()} />
         </div>
+        <div>
+          <span>Run plugins in a separate process</span>
+          <Switch onChange={toggleSetterAndSave(setIsolatePlugins, "isolate_plugins")} checked={isolatePlugins()} />
+        </div>
+      </div>
+      <br />
+      <h1 class="font-bold text-3xl justify-self-start mb-2">Plugins</h1>
+      <div class="border-2 border-indigo-200 rounded-xl p-2">
+        <div>
+          <TextField label="Seconds a plugin call may stall before it times out" type="number" size="small" value={pluginTimeout()} onChange={(_, value) => savePluginTimeout(value)} />
+        </div>
+        <div>
+          <TextField label="Ports for the login redirect" size="small" value={oauthPorts()} onChange={(_, value) => saveOauthPorts(value)} />
+        </div>
+        <div>
+          <span>Redirect logins to a savesync:// link instead of a port</span>
+          <Switch onChange={toggleSetterAndSave(setOauthDeepLink, "oauth_deep_link")} checked={oauthDeepLink()} />
+        </div>
+        <div>
+          <TextField label="Trusted publisher key" size="small" value={trustedKey()} onChange={(_, value) => setTrustedKey(value)} />
+          <Button onClick={addTrustedKey} disabled={!trustedKey()}>Trust</Button>
+        </div>
+      </div>
+      <br />
+      <h1 class="font-bold text-3xl justify-self-start mb-2">Credentials</h1>
+      <div class="border-2 border-indigo-200 rounded-xl p-2">
+        <div>
+          <span>{vaultState() == "unencrypted" ? "Saved logins are stored unencrypted" : "Saved logins are encrypted with a passphrase"}</span>
+        </div>
+        <div>
+          <Show when={vaultState() == "unencrypted"}>
+            <TextField label="Passphrase" type="password" size="small" value={vaultPassphrase()} onChange={(_, value) => setVaultPassphrase(value)} />
+          </Show>
+          <Button onClick={toggleVault} disabled={vaultState() == "unencrypted" ? !vaultPassphrase() : vaultState() == "locked"}>
+            {vaultState() == "unencrypted" ? "Encrypt" : "Store unencrypted"}
+          </Button>
+          <Show when={vaultError()}>
+            <span class="text-red-600">{vaultError()}</span>
+          </Show>
+        </div>
       </div>
     </div>
   </PageRoot>
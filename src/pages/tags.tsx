@@ -64,6 +64,7 @@ export default function Tags() {
         </For>
         <Portal>
           <div class="fixed right-0 bottom-0 m-4">
+            <button class="mr-2" onclick={() => invoke("refresh_cloud")}>Refresh from cloud</button>
             <button onclick={() => { emit("unload"); navigate("/") }}>Back to plugin select</button>
           </div>
         </Portal>
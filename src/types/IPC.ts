@@ -1,4 +1,4 @@
-import { FolderMapping, RequiredList, Info } from "./data";
+import { FolderMapping, RequiredList, Info, WebDavLogin, S3Login, PluginSettings, SettingsValues, VaultState, DeviceCode } from "./data";
 import { OsString, SystemTime } from "./rust";
 
 /**
@@ -16,6 +16,18 @@ export type InvokeTypes = {
   add_plugin: [{ filepath: OsString }, undefined]
   logout: [{ filepath: OsString }, undefined]
   logged_in: [{filepath: OsString}, boolean]
+  set_local_folder: [{ path: string }, undefined]
+  set_webdav_login: [{ login: WebDavLogin }, undefined]
+  set_s3_login: [{ login: S3Login }, undefined]
+  add_trusted_key: [{ key: string }, undefined]
+  refresh_cloud: [undefined, undefined]
+  get_plugin_settings: [{ filepath: OsString }, PluginSettings | null]
+  set_plugin_settings: [{ filepath: OsString, values: SettingsValues }, boolean]
+  vault_state: [undefined, VaultState]
+  unlock_vault: [{ passphrase: string }, undefined]
+  enable_vault: [{ passphrase: string }, undefined]
+  disable_vault: [undefined, undefined]
+  start_device_login: [{ filepath: OsString }, DeviceCode | null]
 };
 
 /**
@@ -25,7 +37,7 @@ export type InvokeTypes = {
 export type EmitTypes = {
   init: OsString
   refresh: undefined
-  abort: OsString
+  abort: { tag: string, foldername: OsString }
   sync: { tag: string, foldername: OsString }
   unload: undefined
   saved_plugin: undefined
@@ -41,8 +53,10 @@ export type ListenTypes = {
   init_result: undefined
   abort_result: string
   plugin_error: [OsString, string]
+  device_code: [OsString, DeviceCode]
   saved_result: undefined
   sync_result: [string, OsString, boolean]
+  transfer_progress: [string, OsString, number, number]
   conflicting_files: [string, OsString, [SystemTime, SystemTime]]
 }
 
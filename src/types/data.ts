@@ -9,3 +9,19 @@ export type Info = Record<"name" | "description" | "author", string>
 export type FileTree = Record<string, Record<string, Record<"folder" | "loading" | "synced", boolean>>>
 export type FolderMapping = Record<string, OsString>
 export type RequiredList = string[]
+export type WebDavLogin = Record<"url" | "username" | "password", string>
+export type S3Login = Record<"endpoint" | "bucket" | "region" | "access_key" | "secret_key", string>
+export type SettingsValues = Record<string, string | number | boolean>
+/** JSON Schema of a plugin's settings, only the parts the settings dialog renders */
+export type SettingsSchema = {
+  type: "object"
+  properties?: Record<string, {
+    type?: "string" | "number" | "integer" | "boolean"
+    title?: string
+    description?: string
+    default?: string | number | boolean
+  }>
+}
+export type PluginSettings = { schema: SettingsSchema, values: SettingsValues }
+export type VaultState = "unencrypted" | "locked" | "unlocked"
+export type DeviceCode = { user_code: string, verification_uri: string }
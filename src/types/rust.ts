@@ -1,2 +1,2 @@
-export type OsString = Record<"Windows"/* | "Unix"*/, number[]>
+export type OsString = { Windows: number[] } | { Unix: number[] }
 export type SystemTime = Record<`${"nanos" | "secs"}_since_epoch`, number>